@@ -0,0 +1,18 @@
+use advent_of_code_2024::{
+    day03::{parse_input, parse_input_fast},
+    util::read_file_to_string,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn bench_parse_input(c: &mut Criterion) {
+    let input = read_file_to_string("data/day03.txt");
+    let mut group = c.benchmark_group("day03 parse");
+    group.bench_function("nom", |b| b.iter(|| parse_input(black_box(&input))));
+    group.bench_function("scanner", |b| {
+        b.iter(|| parse_input_fast(black_box(&input)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse_input);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+use advent_of_code_2024::{
+    day07::{parse_input, part_2},
+    util::read_file_to_string,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn bench_part_2(c: &mut Criterion) {
+    let calcs = parse_input(&read_file_to_string("data/day07.txt")).expect("input is valid");
+    c.bench_function("day07 part 2", |b| b.iter(|| part_2(black_box(&calcs))));
+}
+
+criterion_group!(benches, bench_part_2);
+criterion_main!(benches);
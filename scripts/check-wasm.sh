@@ -0,0 +1,6 @@
+#!/bin/sh
+# Check that the solvers build for the browser, without filesystem access.
+# Requires the target: `rustup target add wasm32-unknown-unknown`.
+set -e
+cd "$(dirname "$0")/.."
+cargo check --lib --no-default-features --target wasm32-unknown-unknown
@@ -1,24 +1,66 @@
 use std::cmp;
 
-use crate::util::parse_decimal;
-use nom::character::complete::space1;
-use nom::sequence::separated_pair;
+use crate::util::{parse_decimal, AocError};
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, space0, space1};
+use nom::combinator::{all_consuming, recognize};
+use nom::sequence::{separated_pair, terminated};
+use nom::IResult;
 
+/// What separates the two columns of the puzzle input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Separator<'a> {
+    /// Any run of spaces and tabs, as in the puzzle.
+    Whitespace,
+    Char(char),
+    Str(&'a str),
+}
+
+impl Separator<'_> {
+    fn parse<'i>(&self, input: &'i str) -> IResult<&'i str, &'i str> {
+        match self {
+            Separator::Whitespace => space1(input),
+            Separator::Char(c) => recognize(char(*c))(input),
+            Separator::Str(s) => tag(*s)(input),
+        }
+    }
+}
+
+/// Parse the two columns of numbers, separated by whitespace.
+///
+/// # Panics
+/// If a line is not two numbers separated by whitespace.
 pub fn parse_input<T>(input: &str) -> [Vec<T>; 2]
 where
     T: std::str::FromStr,
     <T as std::str::FromStr>::Err: std::fmt::Debug,
 {
-    let lines = input.lines();
+    parse_input_with(input, Separator::Whitespace).expect("every line is \"<int>    <int>\"")
+}
+
+/// Parse the two columns of numbers, separated by a separator. Trailing
+/// whitespace on a line is ignored.
+pub fn parse_input_with<T>(input: &str, separator: Separator) -> Result<[Vec<T>; 2], AocError>
+where
+    T: std::str::FromStr,
+    <T as std::str::FromStr>::Err: std::fmt::Debug,
+{
     let mut left = Vec::<T>::new();
     let mut right = Vec::<T>::new();
-    let mut parser = separated_pair(parse_decimal::<T>, space1, parse_decimal::<T>);
-    for line in lines {
-        let output = parser(line).expect("every line is \"<int>    <int>\"");
-        left.push(output.1 .0);
-        right.push(output.1 .1);
+    let mut parser = all_consuming(terminated(
+        separated_pair(
+            parse_decimal::<T>,
+            |input| separator.parse(input),
+            parse_decimal::<T>,
+        ),
+        space0,
+    ));
+    for (i, line) in input.lines().enumerate() {
+        let (_, (l, r)) = parser(line).map_err(|_| AocError::MalformedLine { line: i + 1 })?;
+        left.push(l);
+        right.push(r);
     }
-    [left, right]
+    Ok([left, right])
 }
 
 /// Compute the sum of all absolute differences between equally-indexed elements
@@ -92,8 +134,8 @@ pub fn part_2(data: &mut [Vec<isize>; 2]) -> isize {
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_input, part_1, part_2};
-    use crate::util::read_file_to_string;
+    use super::{parse_input, parse_input_with, Separator};
+    use crate::{testing::golden_tests, util::AocError};
     const INPUT: &str = "3   4\n4   3\n2   5\n1   3\n3   9\n3   3";
 
     #[test]
@@ -105,32 +147,34 @@ mod tests {
     }
 
     #[test]
-    fn test_part_1_small() {
-        assert_eq!(part_1(&mut parse_input::<isize>(INPUT)), 11)
-    }
-
-    #[test]
-    fn test_part_1_full() {
+    fn test_parse_input_with() {
+        let expected = parse_input::<usize>(INPUT);
+        for (input, separator) in [
+            ("3,4\n4,3\n2,5\n1,3\n3,9\n3,3", Separator::Char(',')),
+            ("3\t4\n4\t3\n2\t5\n1\t3\n3\t9\n3\t3", Separator::Char('\t')),
+            ("3, 4\n4, 3\n2, 5\n1, 3\n3, 9\n3, 3", Separator::Str(", ")),
+            (
+                "3 4\n4\t 3\n2      5\n1\t\t3 \n3  9\n3 3",
+                Separator::Whitespace,
+            ),
+        ] {
+            assert_eq!(parse_input_with(input, separator), Ok(expected.clone()));
+        }
         assert_eq!(
-            part_1(&mut parse_input::<isize>(&read_file_to_string(
-                "data/day01.txt"
-            ))),
-            1320851
+            parse_input_with::<usize>("3,4\n4;3", Separator::Char(',')),
+            Err(AocError::MalformedLine { line: 2 })
+        );
+        assert_eq!(
+            parse_input_with::<usize>("3   4\n4   3 2", Separator::Whitespace),
+            Err(AocError::MalformedLine { line: 2 })
         );
     }
 
-    #[test]
-    fn test_part_2_small() {
-        assert_eq!(part_2(&mut parse_input::<isize>(INPUT)), 31)
-    }
-
-    #[test]
-    fn test_part_2_full() {
-        assert_eq!(
-            part_2(&mut parse_input::<isize>(&read_file_to_string(
-                "data/day01.txt"
-            ))),
-            26859182
-        )
+    golden_tests! {
+        crate::day01,
+        part_1: |input| part_1(&mut parse_input::<isize>(input)),
+        part_2: |input| part_2(&mut parse_input::<isize>(input)),
+        examples: [(INPUT, 11, 31)],
+        full: ("data/day01.txt", 1320851, 26859182),
     }
 }
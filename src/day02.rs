@@ -156,10 +156,59 @@ where
     score
 }
 
+/// The rules a report has to follow to be safe.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Policy {
+    /// The largest absolute difference between neighboring levels.
+    pub max_delta: isize,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy { max_delta: 3 }
+    }
+}
+
+/// Whether a report is safe, and otherwise which level to remove to make it
+/// safe.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DampenerResult {
+    AlreadySafe,
+    /// The smallest index of a level whose removal makes the report safe.
+    Removable(usize),
+    Unsafe,
+}
+
+/// Find the smallest index of a level to remove to make a report safe, if it
+/// is not safe already.
+pub fn removal_index<T>(report: &[T], policy: &Policy) -> DampenerResult
+where
+    T: Copy + Into<isize> + std::ops::Sub<Output = T>,
+{
+    if is_ok(report, policy.max_delta) {
+        return DampenerResult::AlreadySafe;
+    }
+    (0..report.len())
+        .find(|idx| try_remove(report, *idx, policy.max_delta))
+        .map_or(DampenerResult::Unsafe, DampenerResult::Removable)
+}
+
+/// The `removal_index` of every report.
+pub fn removal_indices<T>(data: &[Vec<T>], policy: &Policy) -> Vec<DampenerResult>
+where
+    T: Copy + Into<isize> + std::ops::Sub<Output = T>,
+{
+    data.iter()
+        .map(|report| removal_index(report, policy))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{parse_input, part_1, part_2};
-    use crate::util::read_file_to_string;
+    use super::{
+        parse_input, part_1, part_2, removal_index, removal_indices, DampenerResult, Policy,
+    };
+    use crate::{testing::golden_tests, util::read_file_to_string};
     const INPUT: &str = "7 6 4 2 1\n1 2 7 8 9\n9 7 6 2 1\n1 3 2 4 5\n8 6 4 4 1\n1 3 6 7 9";
 
     #[test]
@@ -177,33 +226,52 @@ mod tests {
         )
     }
 
-    #[test]
-    fn test_part_1_small() {
-        assert_eq!(part_1(&(parse_input::<isize>(INPUT))), 2)
+    golden_tests! {
+        crate::day02,
+        part_1: |input| part_1(&parse_input::<isize>(input)),
+        part_2: |input| part_2(&parse_input::<isize>(input)),
+        examples: [(INPUT, 2, 4)],
+        full: ("data/day02.txt", 639, 674),
     }
 
     #[test]
-    fn test_part_1_full() {
+    fn test_removal_indices() {
+        let policy = Policy::default();
         assert_eq!(
-            part_1(&parse_input::<isize>(&read_file_to_string(
-                "data/day02.txt"
-            ))),
-            639
+            removal_indices(&parse_input::<isize>(INPUT), &policy),
+            [
+                DampenerResult::AlreadySafe,
+                DampenerResult::Unsafe,
+                DampenerResult::Unsafe,
+                DampenerResult::Removable(1),
+                DampenerResult::Removable(2),
+                DampenerResult::AlreadySafe,
+            ]
+        );
+        assert_eq!(
+            removal_index(&[10isize, 1, 2, 3, 4], &policy),
+            DampenerResult::Removable(0)
+        );
+        assert_eq!(
+            removal_index(&[1isize, 2, 3, 4, 10], &policy),
+            DampenerResult::Removable(4)
         );
-    }
-
-    #[test]
-    fn test_part_2_small() {
-        assert_eq!(part_2(&parse_input::<isize>(INPUT)), 4)
-    }
 
-    #[test]
-    fn test_part_2_full() {
+        let data = parse_input::<isize>(&read_file_to_string("data/day02.txt"));
+        let results = removal_indices(&data, &policy);
         assert_eq!(
-            part_2(&parse_input::<isize>(&read_file_to_string(
-                "data/day02.txt"
-            ))),
-            674
-        )
+            results
+                .iter()
+                .filter(|result| **result != DampenerResult::Unsafe)
+                .count(),
+            part_2(&data)
+        );
+        assert_eq!(
+            results
+                .iter()
+                .filter(|result| **result == DampenerResult::AlreadySafe)
+                .count(),
+            part_1(&data)
+        );
     }
 }
@@ -23,6 +23,57 @@ pub fn parse_input(input: &str) -> Vec<Instruction> {
     buffer
 }
 
+/// Find all instructions with a hand-rolled scanner instead of nom, which
+/// skips straight to the next `m` or `d` and never allocates for the junk in
+/// between. Unlike `parse_input`, the numbers of a multiplication must have one
+/// to three digits, as in the puzzle.
+pub fn parse_input_fast(input: &str) -> Vec<Instruction> {
+    let bytes = input.as_bytes();
+    let mut instructions = Vec::new();
+    let mut i = 0;
+    while let Some(offset) = bytes[i..].iter().position(|&b| b == b'm' || b == b'd') {
+        i += offset;
+        let rest = &bytes[i..];
+        if rest.starts_with(b"do()") {
+            instructions.push(Instruction::Do);
+            i += 4;
+        } else if rest.starts_with(b"don't()") {
+            instructions.push(Instruction::Dont);
+            i += 7;
+        } else if let Some((instruction, len)) = scan_mul(rest) {
+            instructions.push(instruction);
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+    instructions
+}
+
+/// Match `mul(l,r)` at the start of the bytes, returning the instruction and
+/// its length.
+fn scan_mul(bytes: &[u8]) -> Option<(Instruction, usize)> {
+    let rest = bytes.strip_prefix(b"mul(")?;
+    let (l, l_len) = scan_number(rest)?;
+    let rest = rest[l_len..].strip_prefix(b",")?;
+    let (r, r_len) = scan_number(rest)?;
+    rest[r_len..].strip_prefix(b")")?;
+    Some((Instruction::Mul(l, r), 4 + l_len + 1 + r_len + 1))
+}
+
+/// Match a number of one to three digits at the start of the bytes, returning
+/// the number and its length.
+fn scan_number(bytes: &[u8]) -> Option<(u32, usize)> {
+    let len = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    if !(1..=3).contains(&len) {
+        return None;
+    }
+    let number = bytes[..len]
+        .iter()
+        .fold(0, |acc, b| acc * 10 + u32::from(b - b'0'));
+    Some((number, len))
+}
+
 fn parse_instruction_mul(input: &str) -> IResult<&str, Instruction> {
     let (input, _) = tag("mul")(input)?;
     let (input, pair) = delimited(
@@ -76,7 +127,7 @@ pub fn part_2(data: &[Instruction]) -> u32 {
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_input, part_1, part_2, Instruction};
+    use super::{parse_input, parse_input_fast, part_1, part_2, Instruction};
     use crate::util::read_file_to_string;
     const INPUT: &str = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
 
@@ -95,6 +146,18 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_input_fast() {
+        assert_eq!(parse_input_fast(INPUT), parse_input(INPUT));
+        let input = read_file_to_string("data/day03.txt");
+        assert_eq!(parse_input_fast(&input), parse_input(&input));
+        assert_eq!(
+            parse_input_fast("mul(1234,5)mul(12,3)mul(,1)mul(4,5]do(don't()mudo()"),
+            [Instruction::Mul(12, 3), Instruction::Dont, Instruction::Do]
+        );
+        assert_eq!(parse_input_fast("mmul(1,2)d"), [Instruction::Mul(1, 2)]);
+    }
+
     #[test]
     fn test_part_1_small() {
         assert_eq!(part_1(&parse_input(INPUT)), 161)
@@ -43,6 +43,42 @@ fn count_xmas_samx_in_iter<'a>(
     .sum()
 }
 
+/// A pattern of rows of characters, where a `.` matches any character.
+fn pattern(rows: &[&str]) -> Matrix<Option<char>> {
+    Matrix::new(
+        rows.iter()
+            .map(|row| row.chars().map(|c| (c != '.').then_some(c)).collect())
+            .collect(),
+    )
+}
+
+/// Whether the pattern matches the crossword with its top left corner at the
+/// anchor.
+fn matches_at(data: &Matrix<char>, pattern: &Matrix<Option<char>>, anchor: [usize; 2]) -> bool {
+    pattern.iter().enumerate().all(|(r, row)| {
+        row.iter().enumerate().all(|(c, expected)| {
+            expected.is_none_or(|expected| {
+                data.get_element([anchor[0] + r, anchor[1] + c]) == Some(&expected)
+            })
+        })
+    })
+}
+
+/// Count the anchors, i.e. the top left corners, at which any of the patterns
+/// match the crossword. An anchor at which several patterns match is counted
+/// once.
+pub fn count_patterns(data: &Matrix<char>, patterns: &[Matrix<Option<char>>]) -> usize {
+    let [rows, cols] = data.shape();
+    (0..rows)
+        .cartesian_product(0..cols)
+        .filter(|(r, c)| {
+            patterns
+                .iter()
+                .any(|pattern| matches_at(data, pattern, [*r, *c]))
+        })
+        .count()
+}
+
 /// Count the number of occurences of
 /// ```text
 /// M . M  |  S . M  |  S . S  |  M . S  
@@ -51,71 +87,35 @@ fn count_xmas_samx_in_iter<'a>(
 /// ```
 /// in the crossword.
 pub fn part_2(data: &Matrix<char>) -> usize {
-    let mut score = 0;
-
-    for row in 0..(data.shape()[0] - 2) {
-        let top = get_row_as_char_vec(data, row).expect("i is in range");
-        let middle = get_row_as_char_vec(data, row + 1).expect("i + 1 is in range");
-        let bottom = get_row_as_char_vec(data, row + 2).expect("i + 2 is in range");
-        for ((m, t), b) in middle.windows(3).zip(top.windows(3)).zip(bottom.windows(3)) {
-            if m[1] != &'A' {
-                continue;
-            }
-            // M . M
-            // . A .
-            // S . S
-            if top_and_bottom_first_last_equals(t, b, ['M', 'M'], ['S', 'S']) {
-                score += 1;
-                continue;
-            }
-            // S . M
-            // . A .
-            // S . M
-            if top_and_bottom_first_last_equals(t, b, ['S', 'M'], ['S', 'M']) {
-                score += 1;
-                continue;
-            }
-            // S . S
-            // . A .
-            // M . M
-            if top_and_bottom_first_last_equals(t, b, ['S', 'S'], ['M', 'M']) {
-                score += 1;
-                continue;
-            }
-            // M . S
-            // . A .
-            // M . S
-            if top_and_bottom_first_last_equals(t, b, ['M', 'S'], ['M', 'S']) {
-                score += 1;
-                continue;
-            }
-        }
-    }
-    score
+    let patterns = [
+        pattern(&["M.M", ".A.", "S.S"]),
+        pattern(&["S.M", ".A.", "S.M"]),
+        pattern(&["S.S", ".A.", "M.M"]),
+        pattern(&["M.S", ".A.", "M.S"]),
+    ];
+    count_patterns(data, &patterns)
 }
 
-fn get_row_as_char_vec<T>(data: &Matrix<T>, index: usize) -> Option<Vec<&T>> {
-    data.row(index).map(|r| r.collect::<Vec<&T>>())
-}
-
-fn top_and_bottom_first_last_equals<T>(
-    top: &[&T],
-    bottom: &[&T],
-    top_equals: [T; 2],
-    bottom_equals: [T; 2],
-) -> bool
-where
-    T: PartialEq,
-{
-    top[0] == &top_equals[0]
-        && top[top.len() - 1] == &top_equals[1]
-        && bottom[0] == &bottom_equals[0]
-        && bottom[bottom.len() - 1] == &bottom_equals[1]
+/// Count the number of occurences of
+/// ```text
+/// . M .  |  . S .  |  . M .  |  . S .
+/// M A S  |  M A S  |  S A M  |  S A M
+/// . S .  |  . M .  |  . S .  |  . M .
+/// ```
+/// in the crossword.
+pub fn count_plus_pattern(data: &Matrix<char>) -> usize {
+    let patterns = [
+        pattern(&[".M.", "MAS", ".S."]),
+        pattern(&[".S.", "MAS", ".M."]),
+        pattern(&[".M.", "SAM", ".S."]),
+        pattern(&[".S.", "SAM", ".M."]),
+    ];
+    count_patterns(data, &patterns)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_input, part_1, part_2};
+    use super::{count_patterns, count_plus_pattern, parse_input, part_1, part_2, pattern};
     use crate::util::{read_file_to_string, Matrix};
     const INPUT: &str = "MMMSXXMASM
 MSAMXMSMSA
@@ -172,4 +172,33 @@ MXMXAXMASX";
             1900
         )
     }
+
+    #[test]
+    fn test_count_plus_pattern() {
+        // None of the A's in the example have MAS both across and down.
+        assert_eq!(count_plus_pattern(&parse_input(INPUT)), 0);
+        let data = Matrix::new(vec![
+            vec!['X', 'M', 'X', 'S', 'X'],
+            vec!['M', 'A', 'S', 'A', 'M'],
+            vec!['X', 'S', 'X', 'M', 'X'],
+        ]);
+        assert_eq!(count_plus_pattern(&data), 2);
+        assert_eq!(part_2(&data), 0);
+        assert_eq!(
+            count_plus_pattern(&parse_input(&read_file_to_string("data/day04.txt"))),
+            21
+        );
+    }
+
+    #[test]
+    fn test_count_patterns_overlap() {
+        // All patterns match at the start of both rows, which is counted once
+        // per row.
+        let data = parse_input("MAS\nMAS");
+        let patterns = [pattern(&["M.."]), pattern(&["..S"]), pattern(&[".A."])];
+        for pattern in patterns.iter() {
+            assert_eq!(count_patterns(&data, std::slice::from_ref(pattern)), 2);
+        }
+        assert_eq!(count_patterns(&data, &patterns), 2);
+    }
 }
@@ -72,26 +72,70 @@ where
             }))
         })
         .map(|page: &mut Vec<T>| {
-            page.sort_by(|a, b| {
-                if rules
-                    .get(b)
-                    .is_none_or(|after| after.binary_search(a).is_err())
-                {
-                    cmp::Ordering::Less
-                } else {
-                    cmp::Ordering::Greater
-                }
-            });
+            page.sort_by(|a, b| rule_order(rules, a, b));
             *page.get(page.len() / 2).expect("page should not be empty")
         })
         .sum()
 }
 
+/// Order two pages by the rules: `a` comes first unless a rule puts `b`
+/// before it.
+fn rule_order<T>(rules: &HashMap<T, Vec<T>>, a: &T, b: &T) -> cmp::Ordering
+where
+    T: std::cmp::Eq + std::hash::Hash + std::cmp::Ord,
+{
+    if rules
+        .get(b)
+        .is_none_or(|after| after.binary_search(a).is_err())
+    {
+        cmp::Ordering::Less
+    } else {
+        cmp::Ordering::Greater
+    }
+}
+
+/// Find the first page that has to come before a page seen earlier. Returns
+/// the indices of the earlier page and that page, or `None` when the pages are
+/// sorted according to the rules.
+pub fn first_violation<T>(rules: &HashMap<T, Vec<T>>, page: &[T]) -> Option<(usize, usize)>
+where
+    T: std::cmp::Eq + std::hash::Hash + std::cmp::Ord + std::marker::Copy,
+{
+    let mut seen: HashMap<T, usize> = HashMap::with_capacity(page.len());
+    for (j, number) in page.iter().enumerate() {
+        let earliest = rules
+            .get(number)
+            .into_iter()
+            .flatten()
+            .filter_map(|after| seen.get(after))
+            .min();
+        if let Some(i) = earliest {
+            return Some((*i, j));
+        }
+        seen.entry(*number).or_insert(j);
+    }
+    None
+}
+
+/// The first violation of every list of pages, see `first_violation`.
+pub fn violations_report<T>(
+    rules: &HashMap<T, Vec<T>>,
+    pages: &[Vec<T>],
+) -> Vec<Option<(usize, usize)>>
+where
+    T: std::cmp::Eq + std::hash::Hash + std::cmp::Ord + std::marker::Copy,
+{
+    pages
+        .iter()
+        .map(|page| first_violation(rules, page))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use super::{parse_input, part_1, part_2};
+    use super::{first_violation, parse_input, part_1, part_2, rule_order, violations_report};
     use crate::util::read_file_to_string;
     const INPUT: &str = "47|53
 97|13
@@ -170,4 +214,48 @@ mod tests {
         let (map, mut pages) = parse_input(&read_file_to_string("data/day05.txt"));
         assert_eq!(part_2(&map, &mut pages), 4230)
     }
+
+    #[test]
+    fn test_violations_report() {
+        let (map, pages) = parse_input(INPUT);
+        assert_eq!(
+            violations_report(&map, &pages),
+            vec![
+                None,
+                None,
+                None,
+                // 75 comes before 97.
+                Some((0, 1)),
+                // 13 comes before 29.
+                Some((1, 2)),
+                // 13 comes before 75.
+                Some((1, 2)),
+            ]
+        );
+
+        let (map, mut pages) = parse_input(&read_file_to_string("data/day05.txt"));
+        // A page has no violation exactly when sorting it by the rules, like
+        // `part_2` does, leaves it as is.
+        let unsorted: Vec<_> = pages
+            .iter()
+            .filter(|page| {
+                let mut sorted = page.to_vec();
+                sorted.sort_by(|a, b| rule_order(&map, a, b));
+                sorted != **page
+            })
+            .cloned()
+            .collect();
+        assert!(!unsorted.is_empty() && unsorted.len() < pages.len());
+        for page in pages.iter() {
+            assert_eq!(
+                first_violation(&map, page).is_none(),
+                !unsorted.contains(page),
+                "{page:?}"
+            );
+        }
+        assert_eq!(
+            part_2(&map, &mut pages),
+            part_2(&map, &mut unsorted.clone())
+        );
+    }
 }
@@ -1,30 +1,22 @@
 use std::collections::HashSet;
 
-use crate::util::Matrix;
-
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
-enum Direction {
-    North,
-    East,
-    South,
-    West,
-}
-
-impl Direction {
-    pub fn clockwise(&self) -> Direction {
-        match self {
-            Self::North => Self::East,
-            Self::East => Self::South,
-            Self::South => Self::West,
-            Self::West => Self::North,
-        }
-    }
-}
+use crate::util::{AocError, Cardinal, Coordinate, Matrix};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct Guard {
     position: [usize; 2],
-    direction: Direction,
+    direction: Cardinal,
+}
+
+/// A single observable step in the walk of a guard.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GuardEvent {
+    /// The guard moved forward to a vacant position.
+    Moved(Coordinate),
+    /// The guard faced an obstacle and turned to the new direction.
+    Turned(Cardinal),
+    /// The guard walked off the map.
+    Exited,
 }
 
 impl Guard {
@@ -32,12 +24,54 @@ impl Guard {
         self.direction = self.direction.clockwise()
     }
 
+    /// Take a single step: move forward if possible, turn if blocked or exit
+    /// if the next position is off the map.
+    pub fn step(&mut self, matrix: &Matrix<bool>) -> GuardEvent {
+        self.step_with(matrix, None)
+    }
+
+    /// Take a single step like `step`, on the map with an extra obstacle.
+    fn step_with(&mut self, matrix: &Matrix<bool>, obstacle: Option<[usize; 2]>) -> GuardEvent {
+        match self.peek(matrix.shape()) {
+            // Guard cannot move there.
+            Some([row, col]) if matrix[row][col] || obstacle == Some([row, col]) => {
+                self.rotate();
+                GuardEvent::Turned(self.direction)
+            }
+            Some(next_position) => {
+                self.position = next_position;
+                GuardEvent::Moved(Coordinate::new(
+                    next_position[0] as isize,
+                    next_position[1] as isize,
+                ))
+            }
+            None => GuardEvent::Exited,
+        }
+    }
+
+    /// Iterate over the steps of the guard, ending after it exits the map.
+    /// A guard stuck in a loop yields events forever.
+    pub fn steps<'a>(
+        &'a mut self,
+        matrix: &'a Matrix<bool>,
+    ) -> impl Iterator<Item = GuardEvent> + 'a {
+        let mut exited = false;
+        std::iter::from_fn(move || {
+            if exited {
+                return None;
+            }
+            let event = self.step(matrix);
+            exited = event == GuardEvent::Exited;
+            Some(event)
+        })
+    }
+
     pub fn peek(&self, bounds: [usize; 2]) -> Option<[usize; 2]> {
         let dest = match self.direction {
-            Direction::North => [self.position[0].checked_sub(1), Some(self.position[1])],
-            Direction::East => [Some(self.position[0]), self.position[1].checked_add(1)],
-            Direction::South => [self.position[0].checked_add(1), Some(self.position[1])],
-            Direction::West => [Some(self.position[0]), self.position[1].checked_sub(1)],
+            Cardinal::North => [self.position[0].checked_sub(1), Some(self.position[1])],
+            Cardinal::East => [Some(self.position[0]), self.position[1].checked_add(1)],
+            Cardinal::South => [self.position[0].checked_add(1), Some(self.position[1])],
+            Cardinal::West => [Some(self.position[0]), self.position[1].checked_sub(1)],
         };
         if dest[0].is_some_and(|val| val < bounds[0]) && dest[1].is_some_and(|val| val < bounds[1])
         {
@@ -49,50 +83,160 @@ impl Guard {
 
 const CHAR_EMPTY: char = '.';
 const CHAR_OCCUPIED: char = '#';
-const CHAR_GUARD: char = '^';
+const CHAR_GUARD_NORTH: char = '^';
+const CHAR_GUARD_EAST: char = '>';
+const CHAR_GUARD_SOUTH: char = 'v';
+const CHAR_GUARD_WEST: char = '<';
 
-pub fn parse_input(input: &str) -> (Matrix<bool>, Guard) {
-    let mut guard = Guard {
-        position: [0, 0],
-        direction: Direction::North,
-    };
+/// Parse the map and all guards on it, each facing the direction its
+/// character points to. Guards are listed in reading order.
+pub fn parse_input_multi(input: &str) -> Result<(Matrix<bool>, Vec<Guard>), AocError> {
+    let mut guards = vec![];
     let mut matrix = vec![];
     for (row, line) in input.lines().enumerate() {
         let mut vec: Vec<bool> = Vec::with_capacity(line.len());
         for (col, char) in line.chars().enumerate() {
-            match char {
-                CHAR_EMPTY => vec.push(false),
-                CHAR_OCCUPIED => vec.push(true),
-                CHAR_GUARD => {
-                    vec.push(false);
-                    guard.position = [row, col];
+            let direction = match char {
+                CHAR_EMPTY | CHAR_OCCUPIED => None,
+                CHAR_GUARD_NORTH => Some(Cardinal::North),
+                CHAR_GUARD_EAST => Some(Cardinal::East),
+                CHAR_GUARD_SOUTH => Some(Cardinal::South),
+                CHAR_GUARD_WEST => Some(Cardinal::West),
+                _ => {
+                    return Err(AocError::InvalidCharacter {
+                        line: row + 1,
+                        col: col + 1,
+                        char,
+                    })
                 }
-                _ => unreachable!(),
+            };
+            vec.push(char == CHAR_OCCUPIED);
+            if let Some(direction) = direction {
+                guards.push(Guard {
+                    position: [row, col],
+                    direction,
+                });
             }
         }
         matrix.push(vec);
     }
-    (Matrix::new(matrix), guard)
+    Ok((Matrix::new(matrix), guards))
+}
+
+/// Parse the map and the single guard on it, facing the direction its
+/// character points to.
+pub fn parse_input(input: &str) -> Result<(Matrix<bool>, Guard), AocError> {
+    let (matrix, guards) = parse_input_multi(input)?;
+    match guards[..] {
+        [] => Err(AocError::Missing("guard")),
+        [guard] => Ok((matrix, guard)),
+        [_, second, ..] => Err(AocError::Duplicate {
+            what: "guard",
+            line: second.position[0] + 1,
+            col: second.position[1] + 1,
+        }),
+    }
+}
+
+/// The ordered states of the guard, starting from its initial state and
+/// recording every move until it exits the map. A guard walled in on all sides
+/// never moves, so its walk ends once it turned all the way around.
+fn walk(matrix: &Matrix<bool>, guard: &mut Guard) -> Vec<Guard> {
+    let mut state = *guard;
+    let mut walk = vec![state];
+    let mut turns = 0;
+    for event in guard.steps(matrix) {
+        match event {
+            GuardEvent::Moved(position) => {
+                state.position = [position.r as usize, position.c as usize];
+                walk.push(state);
+                turns = 0;
+            }
+            GuardEvent::Turned(direction) => {
+                state.direction = direction;
+                turns += 1;
+                if turns == 4 {
+                    break;
+                }
+            }
+            GuardEvent::Exited => {}
+        }
+    }
+    walk
 }
 
 fn visits(matrix: &Matrix<bool>, guard: &mut Guard) -> HashSet<[usize; 2]> {
-    let mut visited = HashSet::from([guard.position]);
+    walk(matrix, guard)
+        .into_iter()
+        .map(|state| state.position)
+        .collect()
+}
+
+/// Walk the guard, on the map with an extra obstacle if any, until it either
+/// exits the map or returns to a state it has been in before, in which case it
+/// is stuck in a loop. A guard walled in on all sides is stuck as well.
+fn is_loop(
+    matrix: &Matrix<bool>,
+    guard: &mut Guard,
+    visited: &mut HashSet<Guard>,
+    obstacle: Option<[usize; 2]>,
+) -> bool {
     loop {
-        if let Some(next_position) = guard.peek(matrix.shape()) {
-            match matrix[next_position[0]][next_position[1]] {
-                // Guard cannot move there.
-                true => {
-                    guard.rotate();
+        match guard.step_with(matrix, obstacle) {
+            GuardEvent::Moved(_) | GuardEvent::Turned(_) => {
+                if !visited.insert(*guard) {
+                    return true;
+                }
+            }
+            GuardEvent::Exited => return false,
+        }
+    }
+}
+
+/// Advance all guards in lockstep, one step each per tick, until every guard
+/// has either exited the map or is stuck in a loop. Guards do not see each
+/// other as obstacles and can freely share a position, so every guard walks
+/// exactly as it would on its own. Returns the positions visited per guard.
+pub fn simulate_all(matrix: &Matrix<bool>, guards: &mut [Guard]) -> Vec<HashSet<[usize; 2]>> {
+    let mut visited: Vec<_> = guards
+        .iter()
+        .map(|guard| HashSet::from([guard.position]))
+        .collect();
+    let mut states: Vec<_> = guards.iter().map(|guard| HashSet::from([*guard])).collect();
+    let mut active = vec![true; guards.len()];
+    while active.contains(&true) {
+        for (i, guard) in guards.iter_mut().enumerate() {
+            if !active[i] {
+                continue;
+            }
+            match guard.step(matrix) {
+                GuardEvent::Moved(_) => {
+                    visited[i].insert(guard.position);
+                    // The guard is stuck in a loop.
+                    if !states[i].insert(*guard) {
+                        active[i] = false;
+                    }
                 }
-                false => {
-                    visited.insert(next_position);
-                    guard.position = next_position;
+                // The guard is walled in and keeps turning in place.
+                GuardEvent::Turned(_) => {
+                    if !states[i].insert(*guard) {
+                        active[i] = false;
+                    }
                 }
+                GuardEvent::Exited => active[i] = false,
             }
-        } else {
-            return visited;
         }
     }
+    visited
+}
+
+/// The number of unique squares visited by at least one of the guards.
+pub fn coverage(matrix: &Matrix<bool>, guards: &mut [Guard]) -> usize {
+    simulate_all(matrix, guards)
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>()
+        .len()
 }
 
 /// The number of unique squares the guard will visit.
@@ -100,57 +244,56 @@ pub fn part_1(matrix: &Matrix<bool>, guard: &mut Guard) -> usize {
     visits(matrix, guard).len()
 }
 
-/// The number of loops the guard can get stuck in by adding a single obstacle.
-pub fn part_2(matrix: &mut Matrix<bool>, guard: &mut Guard) -> usize {
-    let mut obstacles = 0;
-    let position_original = guard.position;
-    let direction_orginal = guard.direction;
-
-    // The guard would not normally visit this position so any obstacle
+/// The positions where a single added obstacle gets the guard stuck in a loop.
+/// The obstacle is passed along the walk rather than placed on the map, which
+/// is left as is.
+pub fn loop_obstacles(matrix: &Matrix<bool>, guard: &Guard) -> HashSet<[usize; 2]> {
+    let mut obstacles = HashSet::new();
+    // The guard would not normally visit any other position so any obstacle
     // placed there would not be encountered anyway.
-    let mut visited = visits(matrix, guard);
+    let walk = walk(matrix, &mut guard.clone());
     // The guard would notice placing an obstacle on his position.
-    visited.remove(&position_original);
+    let mut tested = HashSet::from([walk[0].position]);
     let mut visited_with_obstacle = HashSet::new();
-    for [row, col] in visited {
-        // A valid obstacle position.
-        matrix[row][col] = true;
-        guard.position = position_original;
-        guard.direction = direction_orginal;
-        visited_with_obstacle.insert((guard.direction, guard.position));
-        while let Some(next_position) = guard.peek(matrix.shape()) {
-            match matrix[next_position[0]][next_position[1]] {
-                // Guard cannot move there.
-                true => {
-                    guard.rotate();
-                }
-                false => {
-                    guard.position = next_position;
-                    // The guard is stuck in a loop.
-                    if visited_with_obstacle.contains(&(guard.direction, guard.position)) {
-                        obstacles += 1;
-                        break;
-                    } else {
-                        // The guard moves to a vacant square.
-                        visited_with_obstacle.insert((guard.direction, guard.position));
-                    }
-                }
-            }
+    for (step, state) in walk.iter().enumerate().skip(1) {
+        // Up to the first time the guard reaches the obstacle, the walk is
+        // identical to the one without it. Later crossings of the same
+        // position would already have been diverted.
+        if !tested.insert(state.position) {
+            continue;
+        }
+        visited_with_obstacle.extend(walk[..step].iter().copied());
+        let mut guard = walk[step - 1];
+        if is_loop(
+            matrix,
+            &mut guard,
+            &mut visited_with_obstacle,
+            Some(state.position),
+        ) {
+            obstacles.insert(state.position);
         }
-        // Undoing the obstacle.
-        matrix[row][col] = false;
         visited_with_obstacle.clear();
     }
     obstacles
 }
 
+/// The number of loops the guard can get stuck in by adding a single obstacle.
+pub fn part_2(matrix: &Matrix<bool>, guard: &Guard) -> usize {
+    loop_obstacles(matrix, guard).len()
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::{parse_input, part_1, part_2};
+    use std::collections::HashSet;
+
+    use super::{
+        coverage, is_loop, loop_obstacles, parse_input, parse_input_multi, part_1, part_2,
+        simulate_all, visits, GuardEvent,
+    };
     use crate::{
-        day06::{Direction, Guard},
-        util::{read_file_to_string, Matrix},
+        day06::Guard,
+        util::{read_file_to_string, AocError, Cardinal, Coordinate, Matrix},
     };
     const INPUT: &str = "....#.....
 .........#
@@ -163,11 +306,38 @@ mod tests {
 #.........
 ......#...";
 
+    /// The guard crosses [3, 2] twice: first going north, later going west.
+    /// An obstacle there diverts the guard on the first crossing, so it
+    /// never reaches the loop it would close on the second crossing.
+    const INPUT_CROSSING: &str = "..##....
+.......#
+........
+........
+......#.
+..^.....";
+
+    /// Re-simulates the full walk from the start for every obstacle.
+    fn part_2_naive(matrix: &mut Matrix<bool>, guard: &mut Guard) -> usize {
+        let original = *guard;
+        let mut visited = visits(matrix, guard);
+        visited.remove(&original.position);
+        let mut obstacles = 0;
+        for [row, col] in visited {
+            matrix[row][col] = true;
+            *guard = original;
+            if is_loop(matrix, guard, &mut HashSet::from([original]), None) {
+                obstacles += 1;
+            }
+            matrix[row][col] = false;
+        }
+        obstacles
+    }
+
     #[test]
     fn test_parse_input() {
         assert_eq!(
             parse_input(INPUT),
-            (
+            Ok((
                 Matrix::new(vec![
                     vec![false, false, false, false, true, false, false, false, false, false],
                     vec![false, false, false, false, false, false, false, false, false, true],
@@ -182,33 +352,179 @@ mod tests {
                 ]),
                 Guard {
                     position: [6, 4],
-                    direction: Direction::North
+                    direction: Cardinal::North
                 }
-            )
+            ))
+        )
+    }
+
+    #[test]
+    fn test_parse_input_east() {
+        let (matrix, guard) = parse_input("...\n.>.\n..#").expect("input is valid");
+        assert_eq!(matrix.shape(), [3, 3]);
+        assert_eq!(
+            guard,
+            Guard {
+                position: [1, 1],
+                direction: Cardinal::East
+            }
         )
     }
 
+    #[test]
+    fn test_parse_input_errors() {
+        assert_eq!(
+            parse_input("..^\n.x."),
+            Err(AocError::InvalidCharacter {
+                line: 2,
+                col: 2,
+                char: 'x'
+            })
+        );
+        assert_eq!(parse_input("...\n.#."), Err(AocError::Missing("guard")));
+        assert_eq!(
+            parse_input("..^\n<#."),
+            Err(AocError::Duplicate {
+                what: "guard",
+                line: 2,
+                col: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_steps() {
+        let (matrix, mut guard) = parse_input(INPUT).expect("input is valid");
+        let moved = |r, c| GuardEvent::Moved(Coordinate::new(r, c));
+        assert_eq!(
+            guard.steps(&matrix).take(12).collect::<Vec<_>>(),
+            vec![
+                moved(5, 4),
+                moved(4, 4),
+                moved(3, 4),
+                moved(2, 4),
+                moved(1, 4),
+                GuardEvent::Turned(Cardinal::East),
+                moved(1, 5),
+                moved(1, 6),
+                moved(1, 7),
+                moved(1, 8),
+                GuardEvent::Turned(Cardinal::South),
+                moved(2, 8),
+            ]
+        );
+        assert_eq!(guard.position, [2, 8]);
+        assert_eq!(guard.direction, Cardinal::South);
+    }
+
+    #[test]
+    fn test_steps_exit() {
+        let (matrix, mut guard) = parse_input("#.\n.^").expect("input is valid");
+        assert_eq!(
+            guard.steps(&matrix).collect::<Vec<_>>(),
+            vec![GuardEvent::Moved(Coordinate::new(0, 1)), GuardEvent::Exited]
+        );
+    }
+
     #[test]
     fn test_part_1_small() {
-        let (matrix, mut guard) = parse_input(INPUT);
+        let (matrix, mut guard) = parse_input(INPUT).expect("input is valid");
         assert_eq!(part_1(&matrix, &mut guard), 41)
     }
 
     #[test]
     fn test_part_1_full() {
-        let (matrix, mut guard) = parse_input(&read_file_to_string("data/day06.txt"));
+        let (matrix, mut guard) =
+            parse_input(&read_file_to_string("data/day06.txt")).expect("input is valid");
         assert_eq!(part_1(&matrix, &mut guard), 4696)
     }
 
+    #[test]
+    fn test_simulate_all_single() {
+        for input in [INPUT, &read_file_to_string("data/day06.txt")] {
+            let (matrix, mut guard) = parse_input(input).expect("input is valid");
+            let (_, mut guards) = parse_input_multi(input).expect("input is valid");
+            let expected = visits(&matrix, &mut guard);
+            assert_eq!(simulate_all(&matrix, &mut guards), vec![expected.clone()]);
+            assert_eq!(guards, vec![guard]);
+        }
+    }
+
+    #[test]
+    fn test_simulate_all_multi() {
+        // The first guard loops around the center, the second one exits.
+        let (matrix, mut guards) = parse_input_multi(
+            ".#..
+.^.#
+#...
+>.#.",
+        )
+        .expect("input is valid");
+        let visited = simulate_all(&matrix, &mut guards.clone());
+        assert_eq!(
+            visited,
+            vec![
+                HashSet::from([[1, 1], [1, 2], [2, 2], [2, 1]]),
+                HashSet::from([[3, 0], [3, 1]]),
+            ]
+        );
+        assert_eq!(coverage(&matrix, &mut guards), 6);
+    }
+
+    #[test]
+    fn test_boxed_in() {
+        let input = ".#.\n#^#\n.#.";
+        let (matrix, mut guards) = parse_input_multi(input).expect("input is valid");
+        assert_eq!(
+            simulate_all(&matrix, &mut guards),
+            vec![HashSet::from([[1, 1]])]
+        );
+        let (matrix, guard) = parse_input(input).expect("input is valid");
+        assert_eq!(part_1(&matrix, &mut guard.clone()), 1);
+        assert_eq!(part_2(&matrix, &guard), 0);
+        // An obstacle on the only way out walls the guard in for good.
+        let (mut matrix, guard) = parse_input("#.#\n#^#\n###").expect("input is valid");
+        assert_eq!(loop_obstacles(&matrix, &guard), HashSet::from([[0, 1]]));
+        assert_eq!(part_2_naive(&mut matrix, &mut guard.clone()), 1);
+    }
+
     #[test]
     fn test_part_2_small() {
-        let (mut matrix, mut guard) = parse_input(INPUT);
-        assert_eq!(part_2(&mut matrix, &mut guard), 6)
+        let (matrix, guard) = parse_input(INPUT).expect("input is valid");
+        assert_eq!(part_2(&matrix, &guard), 6)
     }
 
     #[test]
     fn test_part_2_full() {
-        let (mut matrix, mut guard) = parse_input(&read_file_to_string("data/day06.txt"));
-        assert_eq!(part_2(&mut matrix, &mut guard), 1443)
+        let (matrix, guard) =
+            parse_input(&read_file_to_string("data/day06.txt")).expect("input is valid");
+        assert_eq!(part_2(&matrix, &guard), 1443)
+    }
+
+    #[test]
+    fn test_loop_obstacles() {
+        let (matrix, guard) = parse_input(INPUT).expect("input is valid");
+        assert_eq!(
+            loop_obstacles(&matrix, &guard),
+            HashSet::from([[6, 3], [7, 6], [7, 7], [8, 1], [8, 3], [9, 7]])
+        );
+        // The map and guard are left untouched.
+        assert_eq!(parse_input(INPUT), Ok((matrix, guard)));
+    }
+
+    #[test]
+    fn test_part_2_naive() {
+        for input in [INPUT, &read_file_to_string("data/day06.txt")] {
+            let (mut matrix, guard) = parse_input(input).expect("input is valid");
+            let expected = part_2_naive(&mut matrix, &mut guard.clone());
+            assert_eq!(part_2(&matrix, &guard), expected)
+        }
+    }
+
+    #[test]
+    fn test_part_2_crossing() {
+        let (mut matrix, guard) = parse_input(INPUT_CROSSING).expect("input is valid");
+        assert_eq!(part_2_naive(&mut matrix, &mut guard.clone()), 1);
+        assert_eq!(part_2(&matrix, &guard), 1)
     }
 }
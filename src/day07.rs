@@ -1,22 +1,78 @@
 use std::ops::ControlFlow;
 
 use nom::{
-    bytes::complete::tag,
-    character::complete::{self, line_ending},
-    error::Error,
-    multi::{fold_many1, separated_list1},
-    sequence::{separated_pair, terminated},
+    bytes::complete::tag, character::complete, combinator::all_consuming, error::Error,
+    multi::separated_list1, sequence::separated_pair,
 };
 
-use crate::util::count_digits;
+use crate::util::{count_digits, AocError};
 
-#[derive(Clone, Copy, Debug)]
+/// A binary operator that combines the accumulator with the next component of
+/// a calculation.
+pub trait Operator {
+    /// Apply the operator, returning `None` when the result does not fit.
+    fn apply(&self, acc: u64, next: u64) -> Option<u64>;
+
+    /// Find the accumulator that gives `result` when combined with `last`,
+    /// returning `None` when there is no such accumulator.
+    fn unapply(&self, result: u64, last: u64) -> Option<u64>;
+
+    /// Whether applying the operator never decreases the accumulator. Only
+    /// then can the search stop as soon as the accumulator exceeds the result.
+    fn is_increasing(&self) -> bool {
+        true
+    }
+}
+
+impl<O: Operator + ?Sized> Operator for &O {
+    fn apply(&self, acc: u64, next: u64) -> Option<u64> {
+        (**self).apply(acc, next)
+    }
+
+    fn unapply(&self, result: u64, last: u64) -> Option<u64> {
+        (**self).unapply(result, last)
+    }
+
+    fn is_increasing(&self) -> bool {
+        (**self).is_increasing()
+    }
+}
+
+/// The operators supported by the puzzle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Operation {
     Add,
     Multiply,
+    /// Concatenate the digits of both operands.
     Combine,
 }
 
+impl Operator for Operation {
+    fn apply(&self, acc: u64, next: u64) -> Option<u64> {
+        match self {
+            Operation::Add => acc.checked_add(next),
+            Operation::Multiply => acc.checked_mul(next),
+            Operation::Combine => acc
+                .checked_mul(10u64.checked_pow(count_digits(next))?)?
+                .checked_add(next),
+        }
+    }
+
+    fn unapply(&self, result: u64, last: u64) -> Option<u64> {
+        match self {
+            Operation::Add => result.checked_sub(last),
+            Operation::Multiply => match last {
+                0 => None,
+                _ => result.is_multiple_of(last).then_some(result / last),
+            },
+            Operation::Combine => {
+                let power = 10u64.checked_pow(count_digits(last))?;
+                (result % power == last).then_some(result / power)
+            }
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 pub struct Calculation<T> {
     result: T,
@@ -29,96 +85,206 @@ impl<T> Calculation<T> {
     }
 }
 
-pub fn parse_input(input: &str) -> Vec<Calculation<u64>> {
-    let (_input, parsed) = fold_many1(
-        terminated(
-            separated_pair(
-                complete::u64::<&str, Error<_>>,
-                tag(": "),
-                separated_list1(tag(" "), complete::u64),
-            ),
-            line_ending,
-        ),
-        Vec::new,
-        |mut acc: Vec<_>, (result, components)| {
-            acc.push(Calculation::new(result, components));
-            acc
-        },
-    )(input)
-    .expect("should be able to parse input");
-    parsed
-}
-
-fn backtrack(
+/// Parse one calculation per line, formatted as `<result>: <component> ...`.
+pub fn parse_input(input: &str) -> Result<Vec<Calculation<u64>>, AocError> {
+    let mut parser = all_consuming(separated_pair(
+        complete::u64::<&str, Error<_>>,
+        tag(": "),
+        separated_list1(tag(" "), complete::u64),
+    ));
+    input
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            parser(line)
+                .map(|(_, (result, components))| Calculation::new(result, components))
+                .map_err(|_| AocError::MalformedLine { line: i + 1 })
+        })
+        .collect()
+}
+
+/// Counters describing the work done by the backtracking search.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SolveStats {
+    /// The number of (partial) operation sequences that were explored.
+    pub nodes_visited: usize,
+    /// The number of evaluations that were stopped because the accumulator
+    /// exceeded the result.
+    pub overflows: usize,
+}
+
+/// Recursively add operations until a solution is found, in which case the
+/// operations are left on the stack.
+///
+/// Returns `Continue(found)` when all permutations of the current prefix have
+/// been explored, or `Break(index)` when the operation at `index` made the
+/// accumulator overflow the result. Every permutation sharing the operations
+/// up to and including that index is then doomed as well, so the stack is
+/// popped back to that index without exploring them.
+fn backtrack<O: Operator + Copy>(
     calc: &Calculation<u64>,
-    operations: &mut Vec<Operation>,
-    supported: &[Operation],
-) -> bool {
-    if operations.len() < calc.components.len() - 1 {
+    operations: &mut Vec<O>,
+    supported: &[O],
+    stats: &mut SolveStats,
+) -> ControlFlow<usize, bool> {
+    stats.nodes_visited += 1;
+    let depth = operations.len();
+    if depth < calc.components.len() - 1 {
         for operation in supported {
             operations.push(*operation);
-            if backtrack(calc, operations, supported) {
-                return true;
+            let flow = backtrack(calc, operations, supported, stats);
+            if flow == ControlFlow::Continue(true) {
+                return flow;
             }
             operations.pop();
+            match flow {
+                // The overflow happened before this depth, so trying other
+                // operations here is pointless.
+                ControlFlow::Break(index) if index < depth => return flow,
+                _ => {}
+            }
         }
         // No solution has been found.
-        return false;
+        return ControlFlow::Continue(false);
     }
     // Base case: the correct number of operations has been added.
-    // TODO: check overflow through ControlFlow.
-    is_ok(calc, operations)
+    let prune = supported.iter().all(|operation| operation.is_increasing());
+    let flow = is_ok(calc, operations, prune);
+    if flow.is_break() {
+        stats.overflows += 1;
+    }
+    flow.map_continue(|acc| acc == calc.result)
 }
 
-fn is_ok(calc: &Calculation<u64>, operations: &[Operation]) -> bool {
-    (1..(calc.components.len())).try_fold(calc.components[0], |mut acc, i| {
-        let other = calc.components[i];
-        match operations[i - 1] {
-            Operation::Add => acc += other,
-            Operation::Multiply => acc *= other,
-            Operation::Combine => {
-                acc = acc * 10u64.pow(count_digits(other)) + other;
-            }
-        }
-        // Early return whenever the values get too large.
-        if acc <= calc.result {
-            ControlFlow::Continue(acc)
-        } else {
-            ControlFlow::Break(acc)
+/// Evaluate the operations from left to right. Returns the final value, or
+/// breaks with the index of the operation that overflowed or, when `prune` is
+/// set, made the accumulator exceed the result.
+fn is_ok<O: Operator>(
+    calc: &Calculation<u64>,
+    operations: &[O],
+    prune: bool,
+) -> ControlFlow<usize, u64> {
+    (1..(calc.components.len())).try_fold(calc.components[0], |acc, i| {
+        match operations[i - 1].apply(acc, calc.components[i]) {
+            // Early return whenever the values get too large.
+            Some(acc) if !prune || acc <= calc.result => ControlFlow::Continue(acc),
+            _ => ControlFlow::Break(i - 1),
         }
-    }) == ControlFlow::Continue(calc.result)
+    })
 }
 
-/// The sum of the results of all calculations that can be made using Add and Multiply.
-pub fn part_1(calcs: &[Calculation<u64>]) -> u64 {
-    calcs
+/// Find the first permutation of operations that solves the calculation. At
+/// every position, from left to right, the supported operations are tried in
+/// the order they are given.
+pub fn find_operations<O: Operator + Copy>(
+    calc: &Calculation<u64>,
+    supported: &[O],
+) -> Option<Vec<O>> {
+    find_operations_with_stats(calc, supported, &mut SolveStats::default())
+}
+
+fn find_operations_with_stats<O: Operator + Copy>(
+    calc: &Calculation<u64>,
+    supported: &[O],
+    stats: &mut SolveStats,
+) -> Option<Vec<O>> {
+    let mut operations = Vec::with_capacity(calc.components.len() - 1);
+    match backtrack(calc, &mut operations, supported, stats) {
+        ControlFlow::Continue(true) => Some(operations),
+        _ => None,
+    }
+}
+
+/// Count every permutation of the supported operations that solves the
+/// calculation, with the same pruning as `find_operations`.
+///
+/// # Panics
+///
+/// If the number of solutions does not fit in a `u64`.
+pub fn count_solutions<O: Operator>(calc: &Calculation<u64>, supported: &[O]) -> u64 {
+    let prune = supported.iter().all(|operation| operation.is_increasing());
+    count_from(calc, supported, 1, calc.components[0], prune)
+}
+
+/// Count the solutions given the accumulator up to the component at `i`.
+fn count_from<O: Operator>(
+    calc: &Calculation<u64>,
+    supported: &[O],
+    i: usize,
+    acc: u64,
+    prune: bool,
+) -> u64 {
+    if prune && acc > calc.result {
+        return 0;
+    }
+    if i == calc.components.len() {
+        return u64::from(acc == calc.result);
+    }
+    supported
+        .iter()
+        .filter_map(|operation| operation.apply(acc, calc.components[i]))
+        .map(|acc| count_from(calc, supported, i + 1, acc, prune))
+        .try_fold(0u64, |count, solutions| count.checked_add(solutions))
+        .expect("the number of solutions should fit in a u64")
+}
+
+/// The sum of the results of all calculations that can be made using the
+/// supported operations, along with statistics on the search.
+pub fn solve_with_stats<O: Operator + Copy>(
+    calcs: &[Calculation<u64>],
+    supported: &[O],
+) -> (u64, SolveStats) {
+    let mut stats = SolveStats::default();
+    let sum = calcs
         .iter()
-        .filter(|calc| backtrack(calc, &mut vec![], &[Operation::Add, Operation::Multiply]))
+        .filter(|calc| find_operations_with_stats(calc, supported, &mut stats).is_some())
         .map(|calc| calc.result)
-        .sum()
+        .sum();
+    (sum, stats)
 }
 
-/// The sum of the results of all calculations that can be made using Add, Multiply and Combine.
-pub fn part_2(calcs: &[Calculation<u64>]) -> u64 {
-    // TODO: include some early return that lets us know at which operation
-    // index we started overflowing and pop all untill there.
-    calcs
+/// The part 1 sum, along with the index and operations of every calculation
+/// that can be made using Add and Multiply.
+pub fn part_1_with_solutions(calcs: &[Calculation<u64>]) -> (u64, Vec<(usize, Vec<Operation>)>) {
+    let solutions: Vec<_> = calcs
         .iter()
-        .filter(|calc| {
-            backtrack(
-                calc,
-                &mut vec![],
-                &[Operation::Add, Operation::Multiply, Operation::Combine],
-            )
+        .enumerate()
+        .filter_map(|(i, calc)| {
+            find_operations(calc, &[Operation::Add, Operation::Multiply])
+                .map(|operations| (i, operations))
         })
-        .map(|calc| calc.result)
-        .sum()
+        .collect();
+    let sum = solutions.iter().map(|(i, _)| calcs[*i].result).sum();
+    (sum, solutions)
+}
+
+/// The sum of the results of all calculations that can be made using Add and Multiply.
+pub fn part_1(calcs: &[Calculation<u64>]) -> u64 {
+    solve_with_stats(calcs, &[Operation::Add, Operation::Multiply]).0
+}
+
+/// The sum of the results of all calculations that can be made using Add, Multiply and Combine.
+pub fn part_2(calcs: &[Calculation<u64>]) -> u64 {
+    solve_with_stats(
+        calcs,
+        &[Operation::Add, Operation::Multiply, Operation::Combine],
+    )
+    .0
 }
+
 #[cfg(test)]
 mod tests {
 
-    use super::{parse_input, part_1, part_2};
-    use crate::{day07::Calculation, util::read_file_to_string};
+    use std::ops::ControlFlow;
+
+    use super::{
+        backtrack, count_solutions, find_operations, parse_input, part_1, part_1_with_solutions,
+        part_2, solve_with_stats, Operation, Operator, SolveStats,
+    };
+    use crate::{
+        day07::Calculation,
+        util::{read_file_to_string, AocError},
+    };
     const INPUT: &str = "190: 10 19
 3267: 81 40 27
 83: 17 5
@@ -133,7 +299,7 @@ mod tests {
     #[test]
     fn test_parse_input() {
         assert_eq!(
-            parse_input(INPUT),
+            parse_input(INPUT).expect("input is valid"),
             &[
                 Calculation::new(190, vec![10, 19]),
                 Calculation::new(3267, vec![81, 40, 27]),
@@ -148,29 +314,227 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_input_no_trailing_newline() {
+        let calcs = parse_input(INPUT.trim_end()).expect("input is valid");
+        assert_eq!(calcs, parse_input(INPUT).expect("input is valid"));
+        assert_eq!(
+            calcs.last(),
+            Some(&Calculation::new(292, vec![11, 6, 16, 20]))
+        );
+    }
+
+    #[test]
+    fn test_parse_input_malformed() {
+        assert_eq!(
+            parse_input("190: 10 19\n3267 81 40 27\n83: 17 5\n"),
+            Err(AocError::MalformedLine { line: 2 })
+        );
+        assert_eq!(
+            parse_input("190: 10 19\n83: 17 5 x"),
+            Err(AocError::MalformedLine { line: 2 })
+        );
+    }
+
     #[test]
     fn test_part_1_small() {
-        assert_eq!(part_1(&parse_input(INPUT)), 3749)
+        assert_eq!(part_1(&parse_input(INPUT).expect("input is valid")), 3749)
     }
 
     #[test]
     fn test_part_1_full() {
         assert_eq!(
-            part_1(&parse_input(&read_file_to_string("data/day07.txt"))),
+            part_1(&parse_input(&read_file_to_string("data/day07.txt")).expect("input is valid")),
             7710205485870
         )
     }
 
     #[test]
     fn test_part_2_small() {
-        assert_eq!(part_2(&parse_input(INPUT)), 11387)
+        assert_eq!(part_2(&parse_input(INPUT).expect("input is valid")), 11387)
     }
 
     #[test]
     fn test_part_2_full() {
         assert_eq!(
-            part_2(&parse_input(&read_file_to_string("data/day07.txt"))),
+            part_2(&parse_input(&read_file_to_string("data/day07.txt")).expect("input is valid")),
             20928985450275
         )
     }
+
+    #[test]
+    fn test_find_operations() {
+        let supported = [Operation::Add, Operation::Multiply];
+        let operations = find_operations(&Calculation::new(3267, vec![81, 40, 27]), &supported)
+            .expect("is solvable");
+        assert!(
+            operations == [Operation::Add, Operation::Multiply]
+                || operations == [Operation::Multiply, Operation::Add]
+        );
+        assert_eq!(
+            find_operations(&Calculation::new(292, vec![11, 6, 16, 20]), &supported),
+            Some(vec![Operation::Add, Operation::Multiply, Operation::Add])
+        );
+        assert_eq!(
+            find_operations(&Calculation::new(83, vec![17, 5]), &supported),
+            None
+        );
+    }
+
+    #[test]
+    fn test_count_solutions() {
+        let supported = [Operation::Add, Operation::Multiply];
+        assert_eq!(
+            count_solutions(&Calculation::new(292, vec![11, 6, 16, 20]), &supported),
+            1
+        );
+        assert_eq!(
+            count_solutions(&Calculation::new(3267, vec![81, 40, 27]), &supported),
+            2
+        );
+        assert_eq!(
+            count_solutions(&Calculation::new(83, vec![17, 5]), &supported),
+            0
+        );
+        assert_eq!(
+            count_solutions(&Calculation::new(4, vec![2, 2]), &supported),
+            2
+        );
+
+        let calcs = parse_input(&read_file_to_string("data/day07.txt")).expect("input is valid");
+        for supported in [
+            &[Operation::Add, Operation::Multiply][..],
+            &[Operation::Add, Operation::Multiply, Operation::Combine],
+        ] {
+            for calc in calcs.iter() {
+                assert_eq!(
+                    count_solutions(calc, supported) > 0,
+                    find_operations(calc, supported).is_some()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_part_1_with_solutions() {
+        let calcs = parse_input(INPUT).expect("input is valid");
+        let (sum, solutions) = part_1_with_solutions(&calcs);
+        assert_eq!(sum, part_1(&calcs));
+        assert_eq!(
+            solutions.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            vec![0, 1, 8]
+        );
+        for (i, operations) in solutions {
+            let calc = &calcs[i];
+            let value = operations
+                .iter()
+                .zip(&calc.components[1..])
+                .try_fold(calc.components[0], |acc, (operation, next)| {
+                    operation.apply(acc, *next)
+                });
+            assert_eq!(value, Some(calc.result));
+        }
+    }
+
+    #[test]
+    fn test_overflow() {
+        let supported = [Operation::Add, Operation::Multiply, Operation::Combine];
+        // Wrapping multiplication would give 2^63 * 2 = 0.
+        let calc = Calculation::new(0, vec![1 << 63, 2]);
+        assert_eq!(Operation::Multiply.apply(1 << 63, 2), None);
+        assert_eq!(find_operations(&calc, &supported), None);
+        // Wrapping concatenation would give 2^64 + 4 + 5 = 9.
+        let calc = Calculation::new(9, vec![1_844_674_407_370_955_162, 5]);
+        assert_eq!(Operation::Combine.apply(1_844_674_407_370_955_162, 5), None);
+        assert_eq!(find_operations(&calc, &supported), None);
+        // Without pruning, the overflow itself has to stop the evaluation.
+        let supported: [&dyn Operator; 2] = [&Operation::Multiply, &Subtract];
+        let calc = Calculation::new(0, vec![1 << 63, 2, 1]);
+        assert!(find_operations(&calc, &supported).is_none());
+    }
+
+    #[test]
+    fn test_backtrack_pruning() {
+        // The very first operation already overflows the result, so every
+        // permutation is doomed after exploring a single branch per operation.
+        let calc = Calculation::new(1, vec![2; 20]);
+        let supported = [Operation::Add, Operation::Multiply, Operation::Combine];
+        let mut stats = SolveStats::default();
+        assert_eq!(
+            backtrack(&calc, &mut vec![], &supported, &mut stats),
+            ControlFlow::Continue(false)
+        );
+        // Naive backtracking visits 1 + 3 + 3^2 + ... + 3^19 nodes.
+        let naive: usize = (0..20).map(|depth| 3usize.pow(depth)).sum();
+        assert_eq!(
+            stats,
+            SolveStats {
+                nodes_visited: 1 + 3 * 19,
+                overflows: 3
+            }
+        );
+        assert!(stats.nodes_visited * 1_000_000 < naive);
+    }
+
+    #[test]
+    fn test_solve_with_stats() {
+        let (sum, stats) = solve_with_stats(
+            &parse_input(INPUT).expect("input is valid"),
+            &[Operation::Add, Operation::Multiply],
+        );
+        assert_eq!(sum, part_1(&parse_input(INPUT).expect("input is valid")));
+        assert!(stats.overflows > 0);
+        assert!(stats.nodes_visited > stats.overflows);
+    }
+
+    /// Subtraction decreases the accumulator, so calculations can no longer
+    /// be pruned once the accumulator exceeds the result.
+    struct Subtract;
+
+    impl Operator for Subtract {
+        fn apply(&self, acc: u64, next: u64) -> Option<u64> {
+            acc.checked_sub(next)
+        }
+
+        fn unapply(&self, result: u64, last: u64) -> Option<u64> {
+            result.checked_add(last)
+        }
+
+        fn is_increasing(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_custom_operator() {
+        let calcs = [
+            // 10 * 3 - 20
+            Calculation::new(10, vec![10, 3, 20]),
+            // 9 - 4 - 5
+            Calculation::new(0, vec![9, 4, 5]),
+            // 8 - 3 * 2
+            Calculation::new(10, vec![8, 3, 2]),
+            // Underflows for every permutation containing a subtraction.
+            Calculation::new(7, vec![1, 2, 5]),
+        ];
+        let supported: [&dyn Operator; 3] = [&Operation::Add, &Operation::Multiply, &Subtract];
+        assert_eq!(solve_with_stats(&calcs, &supported).0, 27);
+        assert_eq!(
+            solve_with_stats(&calcs, &[Operation::Add, Operation::Multiply]).0,
+            7
+        );
+    }
+
+    #[test]
+    fn test_unapply() {
+        for operation in [Operation::Add, Operation::Multiply, Operation::Combine] {
+            for (acc, last) in [(12, 345), (1, 1), (90, 10)] {
+                let result = operation.apply(acc, last).expect("no overflow");
+                assert_eq!(operation.unapply(result, last), Some(acc));
+            }
+        }
+        assert_eq!(Operation::Multiply.unapply(7, 2), None);
+        assert_eq!(Operation::Combine.unapply(1234, 5), None);
+        assert_eq!(Operation::Add.unapply(3, 4), None);
+    }
 }
@@ -1,12 +1,14 @@
 use std::collections::{HashMap, HashSet};
+use std::ops::{Bound, RangeBounds};
 
-use crate::util::Coordinate;
+use crate::util::{AocError, Coordinate, Matrix};
 
 #[derive(Debug, PartialEq)]
 pub struct SparseMatrix<T>
 where
     T: std::cmp::Eq + std::hash::Hash,
 {
+    /// The shape as `[n_rows, n_cols]`, like `Matrix::shape`.
     shape: [usize; 2],
     elements: HashMap<T, Vec<Coordinate>>,
 }
@@ -15,17 +17,150 @@ impl<T> SparseMatrix<T>
 where
     T: std::cmp::Eq + std::hash::Hash,
 {
+    /// Collect every element that is not `ignore`, by reading order.
+    pub fn from_matrix(matrix: &Matrix<T>, ignore: T) -> Self
+    where
+        T: Copy,
+    {
+        let mut elements = HashMap::<T, Vec<Coordinate>>::new();
+        for (r, row) in matrix.iter().enumerate() {
+            for (c, el) in row.iter().enumerate().filter(|(_c, el)| **el != ignore) {
+                elements
+                    .entry(*el)
+                    .or_default()
+                    .push(Coordinate::from([r as isize, c as isize]));
+            }
+        }
+        let shape = [matrix.len(), matrix.first().map_or(0, |row| row.len())];
+        SparseMatrix { shape, elements }
+    }
+
+    /// Create a dense matrix of the same shape, filling the empty positions
+    /// with the `background`.
+    pub fn to_matrix(&self, background: T) -> Matrix<T>
+    where
+        T: Copy,
+    {
+        let mut matrix = Matrix::new(vec![vec![background; self.shape[1]]; self.shape[0]]);
+        for (el, locations) in self.elements.iter() {
+            for location in locations {
+                matrix[location.r as usize][location.c as usize] = *el;
+            }
+        }
+        matrix
+    }
+
+    /// Find all nodes created by antenna pairs, regardless of their symbol.
+    ///
+    /// * `n`: the number of nodes to compute per antenna, `None` for all.
     pub fn find_nodes(&self, n: Option<usize>) -> HashSet<Coordinate> {
-        let mut hashset = HashSet::<Coordinate>::new();
-        for (_, locations) in self.elements.iter() {
+        let (start, end) = node_bounds(n);
+        self.nodes_by_frequency(|_| true, start, end)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// Find all nodes created by antenna pairs of the given symbols only.
+    ///
+    /// * `n`: the number of nodes to compute per antenna, `None` for all.
+    pub fn find_nodes_for(&self, symbols: &[T], n: Option<usize>) -> HashSet<Coordinate> {
+        let (start, end) = node_bounds(n);
+        self.nodes_by_frequency(|symbol| symbols.contains(symbol), start, end)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// The symbols of the antennas, in no particular order.
+    pub fn frequencies(&self) -> impl Iterator<Item = &T> {
+        self.elements.keys()
+    }
+
+    /// Lazily iterate over all nodes created by antenna pairs, regardless of
+    /// their symbol. Nodes created by several pairs are yielded repeatedly.
+    ///
+    /// * `n`: the number of nodes to compute per antenna, `None` for all.
+    pub fn iter_nodes(&self, n: Option<usize>) -> impl Iterator<Item = Coordinate> + '_ {
+        let (start, end) = node_bounds(n);
+        self.elements
+            .values()
+            .flat_map(|locations| {
+                locations
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(i, &antenna1)| {
+                        locations[i + 1..]
+                            .iter()
+                            .map(move |&antenna2| (antenna1, antenna2))
+                    })
+            })
+            .flat_map(move |(antenna1, antenna2)| {
+                self.calc_antenna_pair(antenna1, antenna2, start, end)
+            })
+    }
+
+    /// Count the unique nodes created by antenna pairs, regardless of their
+    /// symbol. Rather than collecting all nodes, they are marked in a bitset
+    /// with a bit per position of the matrix.
+    ///
+    /// * `n`: the number of nodes to compute per antenna, `None` for all.
+    pub fn count_unique_nodes(&self, n: Option<usize>) -> usize {
+        let n_cols = self.shape[1];
+        let mut bitset = vec![0u64; (self.shape[0] * n_cols).div_ceil(64)];
+        let mut count = 0;
+        for node in self.iter_nodes(n) {
+            let index = node.r as usize * n_cols + node.c as usize;
+            let (word, bit) = (index / 64, 1 << (index % 64));
+            if bitset[word] & bit == 0 {
+                bitset[word] |= bit;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Find all nodes of the given harmonics created by antenna pairs,
+    /// regardless of their symbol. The `k`-th harmonic of an antenna lies `k`
+    /// times the offset to the other antenna away from it, so the antennas
+    /// themselves are the 0th harmonic. For example, `1..=1` gives the nodes
+    /// of part 1 and `0..` those of part 2.
+    pub fn find_nodes_in_range(&self, harmonics: impl RangeBounds<usize>) -> HashSet<Coordinate> {
+        let (start, end) = harmonic_bounds(harmonics);
+        self.nodes_by_frequency(|_| true, start, end)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+
+    /// Find the nodes created by antenna pairs of every symbol separately.
+    /// Nodes created by several symbols are listed under each of them.
+    ///
+    /// * `n`: the number of nodes to compute per antenna, `None` for all.
+    pub fn find_nodes_by_frequency(&self, n: Option<usize>) -> HashMap<&T, HashSet<Coordinate>> {
+        let (start, end) = node_bounds(n);
+        self.nodes_by_frequency(|_| true, start, end)
+    }
+
+    /// Find the nodes of the harmonics from `start` up to `end` for every
+    /// included symbol.
+    fn nodes_by_frequency(
+        &self,
+        include: impl Fn(&T) -> bool,
+        start: usize,
+        end: Option<usize>,
+    ) -> HashMap<&T, HashSet<Coordinate>> {
+        let mut hashmap = HashMap::new();
+        for (frequency, locations) in self.elements.iter().filter(|(symbol, _)| include(symbol)) {
+            let hashset = hashmap.entry(frequency).or_insert_with(HashSet::new);
             for i in 0..(locations.len() - 1) {
                 let antenna1 = locations[i];
                 for &antenna2 in locations.iter().skip(i + 1) {
-                    self.calc_antenna_pair(antenna1, antenna2, n, &mut hashset);
+                    hashset.extend(self.calc_antenna_pair(antenna1, antenna2, start, end));
                 }
             }
         }
-        hashset
+        hashmap
     }
 
     /// Compute where nodes will be positioned relative to any antenna pair.
@@ -36,74 +171,132 @@ where
     /// n2 = b - d = [b1 - (a1 - b1), b2 - (a2 - b2)] = [2b1 - a1, 2b2 - a2]
     ///
     /// * `a1`, `a2`: the antenna pair in question
-    /// * `n`: the number of nodes to compute, `None` for all.
-    /// * `hashset`: mutable reference to the `HashSet` storing all nodes.
+    /// * `start`, `end`: the harmonics to compute, `end` being exclusive and
+    ///   `None` for all harmonics inside the shape of the matrix.
     fn calc_antenna_pair(
         &self,
         a1: Coordinate,
         a2: Coordinate,
-        n: Option<usize>,
-        hashset: &mut HashSet<Coordinate>,
-    ) {
+        start: usize,
+        end: Option<usize>,
+    ) -> impl Iterator<Item = Coordinate> {
         let delta = a1 - a2;
         let origin = Coordinate::new(0, 0);
         let topright = Coordinate::from([
             self.shape[0].try_into().expect("shape fits in i32"),
             self.shape[1].try_into().expect("shape fits in i32"),
         ]);
-        let nodes1 = (0isize..)
-            .map(|i| a1 + delta * i)
-            .take_while(|sum| sum.is_in(&origin, &topright));
-        let nodes2 = (0isize..)
-            .map(|i| a2 - delta * i)
-            .take_while(|sum| sum.is_in(&origin, &topright));
-        if let Some(n) = n {
-            // When not calculating all nodes, an antenna is not considered a node.
-            hashset.extend(nodes1.skip(1).take(n));
-            hashset.extend(nodes2.skip(1).take(n));
-        } else {
-            hashset.extend(nodes1);
-            hashset.extend(nodes2);
-        }
+        // The offsets of the harmonics from an antenna, stopping at the first
+        // one that does not fit in a coordinate, which is off the map anyway.
+        let offsets = move |delta: Coordinate| {
+            (start..=usize::MAX)
+                .take_while(move |i| end.is_none_or(|end| *i < end))
+                .map_while(move |i| delta.checked_mul(isize::try_from(i).ok()?))
+        };
+        let nodes1 = offsets(delta)
+            .map_while(move |offset| a1.checked_add(offset))
+            .take_while(move |sum| sum.is_in(&origin, &topright));
+        let nodes2 = offsets(Coordinate::new(0, 0) - delta)
+            .map_while(move |offset| a2.checked_add(offset))
+            .take_while(move |sum| sum.is_in(&origin, &topright));
+        nodes1.chain(nodes2)
+    }
+}
+
+/// The harmonics to compute for `n` nodes per antenna, `None` for all, as an
+/// inclusive start and an exclusive end.
+fn node_bounds(n: Option<usize>) -> (usize, Option<usize>) {
+    match n {
+        // When not calculating all nodes, an antenna is not considered a node.
+        Some(n) => (1, Some(n + 1)),
+        None => (0, None),
     }
 }
 
-pub fn parse_input(input: &str) -> SparseMatrix<char> {
+/// Convert the bounds of a range of harmonics to an inclusive start and an
+/// exclusive end, `None` if unbounded.
+fn harmonic_bounds(harmonics: impl RangeBounds<usize>) -> (usize, Option<usize>) {
+    let start = match harmonics.start_bound() {
+        Bound::Included(start) => *start,
+        Bound::Excluded(start) => match start.checked_add(1) {
+            Some(start) => start,
+            // No harmonic comes after the largest one.
+            None => return (0, Some(0)),
+        },
+        Bound::Unbounded => 0,
+    };
+    let end = match harmonics.end_bound() {
+        // Ending after the largest harmonic is the same as not ending.
+        Bound::Included(end) => end.checked_add(1),
+        Bound::Excluded(end) => Some(*end),
+        Bound::Unbounded => None,
+    };
+    (start, end)
+}
+
+impl From<&Matrix<char>> for SparseMatrix<char> {
+    fn from(value: &Matrix<char>) -> Self {
+        SparseMatrix::from_matrix(value, '.')
+    }
+}
+
+/// Parse the map of antennas, which are marked by alphanumeric characters on a
+/// background of `.`. A final empty line is ignored.
+pub fn parse_input(input: &str) -> Result<SparseMatrix<char>, AocError> {
     const IGNORE: char = '.';
-    let mut elements = HashMap::<char, Vec<Coordinate>>::new();
-    let mut shape = [0, 0];
-    let mut row_map = HashMap::<char, Vec<isize>>::new();
-    for (i, row) in input.lines().enumerate() {
-        shape[0] = row.len();
-        parse_row(&mut row_map, row, IGNORE);
-        for (char, row) in row_map.iter_mut() {
-            elements
-                .entry(*char)
-                .and_modify(|vec| {
-                    vec.extend(
-                        row.iter()
-                            .map(|el| Coordinate::from([i.try_into().unwrap(), *el])),
-                    );
-                })
-                .or_insert(
-                    row.iter()
-                        .map(|el| Coordinate::from([i.try_into().unwrap(), *el]))
-                        .collect(),
-                );
+    let mut lines: Vec<&str> = input.lines().collect();
+    if lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let mut rows: Vec<Vec<char>> = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let row: Vec<char> = line.chars().collect();
+        if let Some(first) = rows.first() {
+            if row.len() != first.len() {
+                return Err(AocError::RaggedRow {
+                    line: i + 1,
+                    len: row.len(),
+                    expected: first.len(),
+                });
+            }
         }
-        row_map.clear();
-        shape[1] = i + 1;
+        if let Some((col, ch)) = row
+            .iter()
+            .enumerate()
+            .find(|(_col, ch)| **ch != IGNORE && !ch.is_ascii_alphanumeric())
+        {
+            return Err(AocError::InvalidCharacter {
+                line: i + 1,
+                col: col + 1,
+                char: *ch,
+            });
+        }
+        rows.push(row);
     }
-    SparseMatrix { shape, elements }
+    Ok(SparseMatrix::from_matrix(&Matrix::new(rows), IGNORE))
 }
 
-fn parse_row(hashmap: &mut HashMap<char, Vec<isize>>, row: &str, ignore: char) {
-    for (i, ch) in row.chars().enumerate().filter(|(_i, ch)| *ch != ignore) {
-        hashmap
-            .entry(ch)
-            .and_modify(|vec| vec.push(i.try_into().unwrap()))
-            .or_insert(vec![i.try_into().unwrap()]);
+/// Render the map like the puzzle statement: antennas by their symbol, nodes
+/// that do not coincide with an antenna as `#` and everything else as `.`.
+pub fn render(matrix: &SparseMatrix<char>, nodes: &HashSet<Coordinate>) -> String {
+    let antennas: HashMap<Coordinate, char> = matrix
+        .elements
+        .iter()
+        .flat_map(|(ch, locations)| locations.iter().map(|location| (*location, *ch)))
+        .collect();
+    let mut string = String::with_capacity((matrix.shape[1] + 1) * matrix.shape[0]);
+    for r in 0..matrix.shape[0] {
+        for c in 0..matrix.shape[1] {
+            let coordinate = Coordinate::from([r as isize, c as isize]);
+            string.push(match antennas.get(&coordinate) {
+                Some(ch) => *ch,
+                None if nodes.contains(&coordinate) => '#',
+                None => '.',
+            });
+        }
+        string.push('\n');
     }
+    string
 }
 
 /// Count all the nodes created from antenna with the same symbol.
@@ -112,7 +305,7 @@ where
     T: std::cmp::Eq,
     T: std::hash::Hash,
 {
-    matrix.find_nodes(Some(1)).len()
+    matrix.count_unique_nodes(Some(1))
 }
 
 /// Count all nodes created from antenna with the same symbol. Nodes are placed
@@ -122,18 +315,19 @@ pub fn part_2<T>(matrix: &SparseMatrix<T>) -> usize
 where
     T: std::cmp::Eq + std::hash::Hash,
 {
-    matrix.find_nodes(None).len()
+    matrix.count_unique_nodes(None)
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
+    use std::ops::Bound;
 
-    use super::{parse_input, part_1, part_2};
+    use super::{parse_input, part_1, part_2, render};
     use crate::{
         day08::SparseMatrix,
-        util::{read_file_to_string, Coordinate},
+        util::{read_file_to_string, AocError, Coordinate, Matrix},
     };
     const INPUT: &str = "............
 ........0...
@@ -151,7 +345,7 @@ mod tests {
     #[test]
     fn test_parse_input() {
         assert_eq!(
-            parse_input(INPUT),
+            parse_input(INPUT).expect("input is valid"),
             SparseMatrix {
                 shape: [12, 12],
                 elements: HashMap::from([
@@ -177,28 +371,182 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_find_nodes_by_frequency() {
+        let matrix = parse_input(INPUT).expect("input is valid");
+        let counts = |n| {
+            let nodes = matrix.find_nodes_by_frequency(n);
+            [nodes[&'0'].len(), nodes[&'A'].len()]
+        };
+        // Nodes shared between frequencies are only counted once in the union.
+        assert_eq!(counts(Some(1)), [10, 5]);
+        assert_eq!(matrix.find_nodes(Some(1)).len(), 14);
+        assert_eq!(counts(None), [21, 16]);
+        assert_eq!(matrix.find_nodes(None).len(), 34);
+    }
+
+    #[test]
+    fn test_find_nodes_for() {
+        let matrix = parse_input(INPUT).expect("input is valid");
+        let mut frequencies: Vec<char> = matrix.frequencies().copied().collect();
+        frequencies.sort();
+        assert_eq!(frequencies, ['0', 'A']);
+        assert_eq!(matrix.find_nodes_for(&['A'], Some(1)).len(), 5);
+        assert_eq!(matrix.find_nodes_for(&['0'], Some(1)).len(), 10);
+        assert_eq!(matrix.find_nodes_for(&['A', '0'], Some(1)).len(), 14);
+        assert_eq!(
+            matrix.find_nodes_for(&frequencies, None),
+            matrix.find_nodes(None)
+        );
+        assert!(matrix.find_nodes_for(&['x'], Some(1)).is_empty());
+    }
+
+    fn to_char_matrix(input: &str) -> Matrix<char> {
+        Matrix::new(input.lines().map(|line| line.chars().collect()).collect())
+    }
+
+    #[test]
+    fn test_to_matrix() {
+        for input in [INPUT, "....a.\n....a.\n......\n.b...b"] {
+            let sparse = parse_input(input).expect("input is valid");
+            let dense = sparse.to_matrix('.');
+            assert_eq!(dense, to_char_matrix(input));
+            assert_eq!(dense.shape(), sparse.shape);
+            assert_eq!(SparseMatrix::from(&dense), sparse);
+            assert_eq!(SparseMatrix::from_matrix(&dense, '.'), sparse);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let matrix = parse_input(INPUT).expect("input is valid");
+        assert_eq!(
+            render(&matrix, &matrix.find_nodes(Some(1))),
+            "......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#.
+"
+        );
+    }
+
+    #[test]
+    fn test_render_non_square() {
+        let matrix = parse_input("....a.\n....a.\n......\n......").expect("input is valid");
+        assert_eq!(
+            render(&matrix, &matrix.find_nodes(Some(1))),
+            "....a.\n....a.\n....#.\n......\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_input_trailing_newline() {
+        for input in [format!("{INPUT}\n"), format!("{INPUT}\n\n")] {
+            let matrix = parse_input(&input).expect("input is valid");
+            assert_eq!(matrix, parse_input(INPUT).expect("input is valid"));
+            assert_eq!(part_1(&matrix), 14);
+            assert_eq!(part_2(&matrix), 34);
+        }
+    }
+
+    #[test]
+    fn test_parse_input_errors() {
+        assert_eq!(
+            parse_input("....\n..a.\n...\n...."),
+            Err(AocError::RaggedRow {
+                line: 3,
+                len: 3,
+                expected: 4
+            })
+        );
+        assert_eq!(
+            parse_input("....\n..a.\n.#.."),
+            Err(AocError::InvalidCharacter {
+                line: 3,
+                col: 2,
+                char: '#'
+            })
+        );
+    }
+
+    #[test]
+    fn test_find_nodes_in_range() {
+        let matrix = parse_input(INPUT).expect("input is valid");
+        assert_eq!(matrix.find_nodes_in_range(1..=1).len(), 14);
+        assert_eq!(matrix.find_nodes_in_range(0..).len(), 34);
+        assert_eq!(matrix.find_nodes_in_range(1..2), matrix.find_nodes(Some(1)));
+        assert_eq!(matrix.find_nodes_in_range(..), matrix.find_nodes(None));
+        assert_eq!(
+            matrix.find_nodes_in_range(..=usize::MAX),
+            matrix.find_nodes(None)
+        );
+        assert_eq!(
+            matrix.find_nodes_in_range(1..=usize::MAX),
+            matrix.find_nodes_in_range(1..)
+        );
+        assert!(matrix
+            .find_nodes_in_range((Bound::Excluded(usize::MAX), Bound::Unbounded))
+            .is_empty());
+        // Harmonics that do not fit in a coordinate are off the map.
+        for start in [usize::MAX, isize::MAX as usize, isize::MAX as usize / 2 + 1] {
+            assert!(matrix.find_nodes_in_range(start..).is_empty());
+            assert!(matrix.find_nodes_in_range(start..=usize::MAX).is_empty());
+        }
+        // The 0-frequency pairs [2, 5]-[3, 7], [1, 8]-[3, 7] and [2, 5]-[4, 4]
+        // give [0, 1], [5, 11], [7, 5] and [8, 2]. The A-frequency pair
+        // [8, 8]-[9, 9] gives [6, 6] and [11, 11].
+        assert_eq!(
+            matrix.find_nodes_in_range(2..=2),
+            HashSet::from(
+                [[0, 1], [5, 11], [7, 5], [8, 2], [6, 6], [11, 11]].map(Coordinate::from)
+            )
+        );
+    }
+
+    #[test]
+    fn test_iter_nodes() {
+        for input in [INPUT, &read_file_to_string("data/day08.txt")] {
+            let matrix = parse_input(input).expect("input is valid");
+            for n in [Some(1), Some(3), None] {
+                let nodes: Vec<_> = matrix.iter_nodes(n).collect();
+                let unique: HashSet<_> = nodes.iter().copied().collect();
+                assert!(nodes.len() >= unique.len());
+                assert_eq!(unique, matrix.find_nodes(n));
+                assert_eq!(matrix.count_unique_nodes(n), unique.len());
+            }
+        }
+    }
+
     #[test]
     fn test_part_1_small() {
-        assert_eq!(part_1(&parse_input(INPUT)), 14)
+        assert_eq!(part_1(&parse_input(INPUT).expect("input is valid")), 14)
     }
 
     #[test]
     fn test_part_1_full() {
         assert_eq!(
-            part_1(&parse_input(&read_file_to_string("data/day08.txt"))),
+            part_1(&parse_input(&read_file_to_string("data/day08.txt")).expect("input is valid")),
             265
         )
     }
 
     #[test]
     fn test_part_2_small() {
-        assert_eq!(part_2(&parse_input(INPUT)), 34)
+        assert_eq!(part_2(&parse_input(INPUT).expect("input is valid")), 34)
     }
 
     #[test]
     fn test_part_2_full() {
         assert_eq!(
-            part_2(&parse_input(&read_file_to_string("data/day08.txt"))),
+            part_2(&parse_input(&read_file_to_string("data/day08.txt")).expect("input is valid")),
             962
         )
     }
@@ -1,15 +1,8 @@
-const SUMMATION: [usize; 10] = [
-    0,  // 0
-    1,  // 1
-    3,  // 2
-    6,  // 3
-    10, // 4
-    15, // 5
-    21, // 6
-    28, // 7
-    36, // 8
-    45, // 9
-];
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::io::{BufRead, Cursor, ErrorKind};
+
+use crate::util::AocError;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BlockValue {
@@ -17,6 +10,35 @@ pub enum BlockValue {
     File(usize),
 }
 
+/// A violation of the structure of a `Memory`.
+#[derive(Debug, PartialEq)]
+pub enum MemoryInvariantError {
+    /// A block whose size is not its stop minus its start.
+    InconsistentSize(Block),
+    /// A gap in the files or a file in the gaps.
+    WrongValue(Block),
+    /// A block that does not start after the previous block of its kind.
+    Unsorted(Block),
+    /// Two blocks that share some cells.
+    Overlap(Block, Block),
+    /// Cells that are covered by neither a file nor a gap.
+    Hole { start: usize, stop: usize },
+}
+
+impl Display for MemoryInvariantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InconsistentSize(block) => write!(f, "inconsistent size of {block:?}"),
+            Self::WrongValue(block) => write!(f, "wrong value of {block:?}"),
+            Self::Unsorted(block) => write!(f, "unsorted {block:?}"),
+            Self::Overlap(block1, block2) => write!(f, "{block1:?} overlaps {block2:?}"),
+            Self::Hole { start, stop } => write!(f, "hole from {start} to {stop}"),
+        }
+    }
+}
+
+impl std::error::Error for MemoryInvariantError {}
+
 #[derive(Debug, PartialEq)]
 pub struct Memory {
     files: Vec<Block>,
@@ -70,6 +92,39 @@ impl Memory {
         Memory { files, gaps }
     }
 
+    /// Check that the files and gaps are each sorted by start, have consistent
+    /// sizes and together exactly cover the memory without overlapping.
+    pub fn validate(&self) -> Result<(), MemoryInvariantError> {
+        for (blocks, is_file) in [(&self.files, true), (&self.gaps, false)] {
+            for (i, block) in blocks.iter().enumerate() {
+                if block.stop.checked_sub(block.start) != Some(block.size) {
+                    return Err(MemoryInvariantError::InconsistentSize(*block));
+                }
+                if matches!(block.value, BlockValue::File(_)) != is_file {
+                    return Err(MemoryInvariantError::WrongValue(*block));
+                }
+                if i > 0 && blocks[i - 1].start >= block.start {
+                    return Err(MemoryInvariantError::Unsorted(*block));
+                }
+            }
+        }
+        let mut blocks: Vec<_> = self.files.iter().chain(self.gaps.iter()).collect();
+        blocks.sort_by_key(|block| block.start);
+        for (i, block) in blocks.iter().enumerate() {
+            let stop = i.checked_sub(1).map_or(0, |i| blocks[i].stop);
+            if block.start < stop {
+                return Err(MemoryInvariantError::Overlap(*blocks[i - 1], **block));
+            }
+            if block.start > stop {
+                return Err(MemoryInvariantError::Hole {
+                    start: stop,
+                    stop: block.start,
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Imagine a memory block with file index `f` extending from index `i` to `j`,
     /// for a total size s=j-i.
     /// ```text
@@ -82,145 +137,440 @@ impl Memory {
     /// c = i * f + (i+i) * f ... (j-1) * f = f * (i + i+1 + ... + j)
     ///   = f * (i + i+1 + ... + i+s-1) = f * (s*i + SUM(0, s-1))
     /// ```
-    /// where `SUM(0, s-1)` can be computed as by indexing `SUMMATION[s-1]`.
-    pub fn checksum(&self) -> usize {
+    /// where `SUM(0, s-1) = s * (s-1) / 2`. The sums are computed in `u128`,
+    /// so blocks anywhere in a `usize` address space fit, see
+    /// `block_checksum`.
+    pub fn checksum(&self) -> u128 {
         self.files
             .iter()
-            .map(|block| match block.value {
+            .fold(0, |checksum, block| match block.value {
                 BlockValue::File(file_idx) => {
-                    file_idx * (block.start * block.size + SUMMATION[block.size - 1])
+                    let block_checksum = block_checksum(file_idx, block.start, block.size);
+                    debug_assert!(
+                        checksum.checked_add(block_checksum).is_some(),
+                        "checksum overflows u128"
+                    );
+                    checksum.wrapping_add(block_checksum)
                 }
                 BlockValue::Empty => unreachable!(),
             })
-            .sum()
+    }
+}
+
+/// The content of a single memory cell, used for rendering.
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    /// Not covered by any file or gap.
+    Hole,
+    /// Covered by more than one file or gap.
+    Overlap,
+    Block(BlockValue),
+}
+
+/// Render every cell as its file index or `.` for gaps, like the puzzle
+/// statement. Indices above 9 are rendered as `(idx)` per cell. Cells that
+/// are covered by multiple blocks are marked `!`, cells that are not covered
+/// at all `?`.
+impl Display for Memory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let blocks = self.files.iter().chain(self.gaps.iter());
+        let total_length = blocks.clone().map(|block| block.stop).max().unwrap_or(0);
+        let mut cells = vec![Cell::Hole; total_length];
+        for block in blocks {
+            for cell in &mut cells[block.start.min(block.stop)..block.stop] {
+                *cell = match cell {
+                    Cell::Hole => Cell::Block(block.value),
+                    _ => Cell::Overlap,
+                };
+            }
+        }
+        for cell in cells {
+            match cell {
+                Cell::Hole => write!(f, "?")?,
+                Cell::Overlap => write!(f, "!")?,
+                Cell::Block(BlockValue::Empty) => write!(f, ".")?,
+                Cell::Block(BlockValue::File(idx)) if idx < 10 => write!(f, "{idx}")?,
+                Cell::Block(BlockValue::File(idx)) => write!(f, "({idx})")?,
+            }
+        }
+        Ok(())
     }
 }
 
 /// Get sizes of the files and gaps.
-pub fn parse_input(input: &str) -> Memory {
-    let bytes = input.bytes();
-    let mut files = Vec::with_capacity(bytes.len() / 2);
-    let mut gaps = Vec::with_capacity(bytes.len() / 2);
+/// An empty input gives an empty memory, a single digit a single file.
+/// Trailing whitespace is ignored, any other non-digit is an error.
+pub fn parse_input(input: &str) -> Result<Memory, AocError> {
+    parse_reader(Cursor::new(input))
+}
+
+/// Get sizes of the files and gaps, reading the input chunk by chunk rather
+/// than holding it in memory at once. See `parse_input`.
+pub fn parse_reader<R: BufRead>(mut reader: R) -> Result<Memory, AocError> {
+    let mut files = Vec::new();
+    let mut gaps = Vec::new();
     let mut start = 0;
-    for (i, byte) in bytes.enumerate() {
-        // Digit 0 is represented by 0x30.
-        let size = (byte - 0x30) as usize;
-        // The block is empty.
-        if size == 0 {
-            continue;
-        }
-        // Digits alternate between a file and a gap.
-        if i % 2 == 0 {
-            files.push(Block::new(start, start + size, BlockValue::File(i / 2)));
-        } else {
-            gaps.push(Block::new(start, start + size, BlockValue::Empty));
+    // The index of the current digit in the whole input.
+    let mut i = 0;
+    // Whitespace is only allowed at the end, so remember where it started.
+    let mut whitespace = None;
+    loop {
+        let chunk = match reader.fill_buf() {
+            Ok([]) => break,
+            Ok(chunk) => chunk,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(AocError::Io(e.kind())),
+        };
+        let len = chunk.len();
+        for &byte in chunk {
+            if byte.is_ascii_whitespace() {
+                whitespace.get_or_insert(AocError::InvalidByte { offset: i, byte });
+                i += 1;
+                continue;
+            }
+            if !byte.is_ascii_digit() {
+                return Err(AocError::InvalidByte { offset: i, byte });
+            }
+            if let Some(error) = whitespace {
+                return Err(error);
+            }
+            // Digit 0 is represented by 0x30.
+            let size = (byte - 0x30) as usize;
+            // Digits alternate between a file and a gap. An empty block is
+            // skipped.
+            if size > 0 {
+                if i % 2 == 0 {
+                    files.push(Block::new(start, start + size, BlockValue::File(i / 2)));
+                } else {
+                    gaps.push(Block::new(start, start + size, BlockValue::Empty));
+                }
+            }
+            start += size;
+            i += 1;
         }
-        start += size;
+        reader.consume(len);
     }
-    Memory::new(files, gaps)
+    Ok(Memory::new(files, gaps))
 }
 
-/// Compute the checksum of the filesystem after moving file fragments from the
-/// back into open gaps at the front.
-pub fn part_1(memory: &mut Memory) -> usize {
-    let total_length = memory
-        .files
-        .last()
-        .expect("at least 1 file")
-        .stop
-        .max(memory.gaps.last().expect("at least 1 gap").stop);
-    let n_gaps = memory.gaps.len() - 1;
-    let mut files = Vec::new();
-    let mut i_file = memory.files.len() - 1;
-    let mut i_gap = 0;
-    let mut file = &mut memory.files[i_file];
-    let mut gap = &mut memory.gaps[i_gap];
-    while file.start >= gap.stop {
-        let block = Block::new(gap.start, gap.start + file.size.min(gap.size), file.value);
-        // The file is (more than) exactly emptied into the gap.
-        if file.update_stop(file.stop - block.size).is_err() {
-            // All files have been processed.
-            if i_file == 0 {
-                break;
+/// A file fragment of `size` cells that moved from index `from` to `to`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub file: usize,
+    pub from: usize,
+    pub to: usize,
+    pub size: usize,
+}
+
+impl Memory {
+    /// Move file fragments from the back into open gaps at the front, one cell
+    /// at a time. Returns the moves in the order they were made.
+    pub fn defragment_fragments(&mut self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let (Some(last_file), Some(last_gap)) = (self.files.last(), self.gaps.last()) else {
+            // There is nothing to move.
+            return moves;
+        };
+        let total_length = last_file.stop.max(last_gap.stop);
+        let n_gaps = self.gaps.len() - 1;
+        let mut files = Vec::new();
+        let mut i_file = self.files.len() - 1;
+        let mut i_gap = 0;
+        let mut file = &mut self.files[i_file];
+        let mut gap = &mut self.gaps[i_gap];
+        while file.start >= gap.stop {
+            let block = Block::new(gap.start, gap.start + file.size.min(gap.size), file.value);
+            files.push(block);
+            if let BlockValue::File(file_idx) = file.value {
+                moves.push(Move {
+                    file: file_idx,
+                    from: file.stop - block.size,
+                    to: block.start,
+                    size: block.size,
+                });
             }
-            i_file -= 1;
-            file = &mut memory.files[i_file];
-        }
-        // The gap is (more than) exactly filled by the file.
-        if gap.update_start(gap.start + block.size).is_err() {
-            // All gaps have been processed.
-            if i_gap == n_gaps {
-                break;
+            // The file is (more than) exactly emptied into the gap.
+            if file.update_stop(file.stop - block.size).is_err() {
+                // All files have been processed.
+                if i_file == 0 {
+                    break;
+                }
+                i_file -= 1;
+                file = &mut self.files[i_file];
             }
-            i_gap += 1;
-            gap = &mut memory.gaps[i_gap];
-        }
-        files.push(block);
-    }
-    files.extend(memory.files.drain(0..=i_file));
-    files.sort_by(|a, b| a.start.cmp(&b.start));
-    // The last file could have some of its final elements moved to a gap
-    // connected to its first element. In that case, group them.
-    let n_files = files.len();
-    let mut last_file_stop = 0;
-    if n_files > 1 {
-        let last = files.pop().expect("at least 2 files");
-        last_file_stop = last.stop;
-        let mut prev = files.pop().expect("at least 2 files");
-        if last.value == prev.value {
-            prev.update_stop(last.stop)
+            // The gap is (more than) exactly filled by the file.
+            if gap.update_start(gap.start + block.size).is_err() {
+                // All gaps have been processed.
+                if i_gap == n_gaps {
+                    break;
+                }
+                i_gap += 1;
+                gap = &mut self.gaps[i_gap];
+            }
+        }
+        files.extend(self.files.drain(0..=i_file));
+        files.sort_by_key(|a| a.start);
+        // The last file could have some of its final elements moved to a gap
+        // connected to its first element. In that case, group them.
+        let n_files = files.len();
+        if n_files > 1 {
+            let last = files.pop().expect("at least 2 files");
+            let mut prev = files.pop().expect("at least 2 files");
+            if last.value == prev.value {
+                prev.update_stop(last.stop)
+                    .expect("stop is larger than start");
+                files.push(prev);
+            } else {
+                files.push(prev);
+                files.push(last);
+            }
+        }
+        // The gaps are whatever is not covered by the files.
+        let mut gaps = Vec::new();
+        let mut stop = 0;
+        for file in files.iter() {
+            if file.start > stop {
+                gaps.push(Block::new(stop, file.start, BlockValue::Empty));
+            }
+            stop = file.stop;
+        }
+        if total_length > stop {
+            gaps.push(Block::new(stop, total_length, BlockValue::Empty));
+        }
+        self.files = files;
+        self.gaps = gaps;
+        debug_assert_eq!(self.validate(), Ok(()));
+        moves
+    }
+
+    /// Move whole files from the back into the first open gap at the front
+    /// that can completely house them. Returns the moves in the order they
+    /// were made.
+    pub fn defragment_whole_files(&mut self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        // The starts of the gaps, indexed by their size. The leftmost gap that
+        // can house a file is the smallest first start over all sizes that are
+        // large enough.
+        let max_size = self.gaps.iter().map(|gap| gap.size).max().unwrap_or(0);
+        let mut starts_by_size = vec![BTreeSet::new(); max_size + 1];
+        for gap in self.gaps.iter() {
+            starts_by_size[gap.size].insert(gap.start);
+        }
+        for file in self.files.iter_mut().rev() {
+            let Some((size, start)) = starts_by_size
+                .iter()
+                .enumerate()
+                .skip(file.size)
+                .filter_map(|(size, starts)| starts.first().map(|start| (size, *start)))
+                .min_by_key(|(_size, start)| *start)
+            else {
+                // No gap is large enough.
+                continue;
+            };
+            // Files can only move to the left. Vacated gaps and the gaps they
+            // were merged with are not kept up to date in the index, but those
+            // are to the right of all files that remain to be moved.
+            if start > file.start {
+                continue;
+            }
+            starts_by_size[size].remove(&start);
+            let i_gap = self
+                .gaps
+                .binary_search_by_key(&start, |gap| gap.start)
+                .expect("indexed gap exists");
+            let vacated = Block::new(file.start, file.stop, BlockValue::Empty);
+            if let BlockValue::File(file_idx) = file.value {
+                moves.push(Move {
+                    file: file_idx,
+                    from: file.start,
+                    to: start,
+                    size: file.size,
+                });
+            }
+            file.move_to_start(start);
+            let gap = &mut self.gaps[i_gap];
+            if gap.update_start(file.stop).is_err() {
+                self.gaps.remove(i_gap);
+            } else {
+                starts_by_size[gap.size].insert(gap.start);
+            }
+            // Moving a file leaves behind a gap. As files are moved right to
+            // left only once, no other file can ever fill it, but it keeps the
+            // gaps consistent with the files.
+            insert_gap(&mut self.gaps, vacated);
+        }
+        self.files.sort_by_key(|a| a.start);
+        debug_assert_eq!(self.validate(), Ok(()));
+        moves
+    }
+
+    /// Replay a move, merging the moved fragment with directly adjacent
+    /// fragments of the same file. Returns `None`, leaving the memory
+    /// untouched, if the fragment is not part of the file or does not fit in
+    /// a gap at its destination.
+    pub fn apply(&mut self, m: &Move) -> Option<()> {
+        let value = BlockValue::File(m.file);
+        let i_file = self.files.partition_point(|file| file.stop <= m.from);
+        let file = *self.files.get(i_file)?;
+        let i_gap = self.gaps.partition_point(|gap| gap.stop <= m.to);
+        let gap = *self.gaps.get(i_gap)?;
+        if file.value != value
+            || file.start > m.from
+            || file.stop < m.from + m.size
+            || gap.start > m.to
+            || gap.stop < m.to + m.size
+        {
+            return None;
+        }
+        // Split the gap around the destination.
+        self.gaps.remove(i_gap);
+        for (start, stop) in [(gap.start, m.to), (m.to + m.size, gap.stop)] {
+            if start < stop {
+                insert_gap(&mut self.gaps, Block::new(start, stop, BlockValue::Empty));
+            }
+        }
+        insert_gap(
+            &mut self.gaps,
+            Block::new(m.from, m.from + m.size, BlockValue::Empty),
+        );
+        // Split the file around the source and add the moved fragment.
+        self.files.remove(i_file);
+        for (start, stop) in [
+            (file.start, m.from),
+            (m.from + m.size, file.stop),
+            (m.to, m.to + m.size),
+        ] {
+            if start < stop {
+                self.insert_file(Block::new(start, stop, value));
+            }
+        }
+        Some(())
+    }
+
+    /// Insert a file into the sorted files, merging it with any directly
+    /// adjacent fragments of the same file.
+    fn insert_file(&mut self, mut file: Block) {
+        let i_file = self.files.partition_point(|other| other.start < file.start);
+        if let Some(next) = self.files.get(i_file) {
+            if next.value == file.value && next.start == file.stop {
+                file.update_stop(next.stop)
+                    .expect("stop is larger than start");
+                self.files.remove(i_file);
+            }
+        }
+        if let Some(prev) = i_file.checked_sub(1).map(|i| &mut self.files[i]) {
+            if prev.value == file.value && prev.stop == file.start {
+                prev.update_stop(file.stop)
+                    .expect("stop is larger than start");
+                return;
+            }
+        }
+        self.files.insert(i_file, file);
+    }
+}
+
+/// Insert a gap into a list of gaps sorted by start, merging it with any
+/// directly adjacent gaps.
+fn insert_gap(gaps: &mut Vec<Block>, mut gap: Block) {
+    let i_gap = gaps.partition_point(|other| other.start < gap.start);
+    if let Some(next) = gaps.get(i_gap) {
+        if next.start == gap.stop {
+            gap.update_stop(next.stop)
                 .expect("stop is larger than start");
-            files.push(prev);
-        } else {
-            files.push(prev);
-            files.push(last);
+            gaps.remove(i_gap);
         }
     }
-    memory.files = files;
-    memory.gaps = vec![Block::new(last_file_stop, total_length, BlockValue::Empty)];
+    if let Some(prev) = i_gap.checked_sub(1).map(|i| &mut gaps[i]) {
+        if prev.stop == gap.start {
+            prev.update_stop(gap.stop)
+                .expect("stop is larger than start");
+            return;
+        }
+    }
+    gaps.insert(i_gap, gap);
+}
 
+/// Compute the checksum of the filesystem after moving file fragments from the
+/// back into open gaps at the front.
+pub fn part_1(memory: &mut Memory) -> u128 {
+    memory.defragment_fragments();
     memory.checksum()
 }
 
-/// Compute the checksum of the filesystem after moving file fragments from the
-/// back into the first open gap at the front that can completely house them.
-pub fn part_2(memory: &mut Memory) -> usize {
-    let mut n_gaps = memory.gaps.len();
-    for file in memory.files.iter_mut().rev() {
-        // All gaps have already been filled.
-        if n_gaps == 0 {
-            break;
-        }
-        for i_gap in 0..n_gaps {
-            let gap = &mut memory.gaps[i_gap];
-            // Files can only move to the left.
-            if gap.start > file.start {
-                break;
-            }
-            if gap.size >= file.size {
-                let mut new_gap = *file;
-                new_gap.value = BlockValue::Empty;
-                file.move_to_start(gap.start);
-                if gap.update_start(file.stop).is_err() {
-                    memory.gaps.remove(i_gap);
+/// The sum of `id` times every index from `start` up to `start + size`, see
+/// `Memory::checksum`. Overflowing `u128` is only checked in debug builds.
+fn block_checksum(id: usize, start: usize, size: usize) -> u128 {
+    let [id, start, size] = [id, start, size].map(|n| n as u128);
+    // Halve the even factor first to not overflow needlessly.
+    let summation = match size.is_multiple_of(2) {
+        true => size / 2 * size.saturating_sub(1),
+        false => size * ((size - 1) / 2),
+    };
+    debug_assert!(
+        start
+            .checked_mul(size)
+            .and_then(|sum| sum.checked_add(summation))
+            .and_then(|sum| sum.checked_mul(id))
+            .is_some(),
+        "checksum of file {id} at {start} overflows u128"
+    );
+    start
+        .wrapping_mul(size)
+        .wrapping_add(summation)
+        .wrapping_mul(id)
+}
+
+/// Compute the same checksum as `part_1`, straight from the disk map. A head
+/// walks forward over the digits and a tail walks back over the files, filling
+/// every gap at the head from the file at the tail, without building a
+/// `Memory`, in constant memory. Trailing whitespace is ignored, any other byte
+/// that is not a digit is an error.
+pub fn part_1_streaming(input: &str) -> Result<u128, AocError> {
+    let bytes = input.trim_end().as_bytes();
+    if let Some(offset) = bytes.iter().position(|byte| !byte.is_ascii_digit()) {
+        return Err(AocError::InvalidByte {
+            offset,
+            byte: bytes[offset],
+        });
+    }
+    if bytes.is_empty() {
+        return Ok(0);
+    }
+    let digit = |i: usize| (bytes[i] - b'0') as usize;
+    // The last digit that describes a file, and how much of it is left.
+    let mut tail = (bytes.len() - 1) / 2 * 2;
+    let mut tail_left = digit(tail);
+    let mut start = 0;
+    let mut checksum = 0;
+    let mut head = 0;
+    while head <= tail {
+        if head % 2 == 0 {
+            let size = if head == tail { tail_left } else { digit(head) };
+            checksum += block_checksum(head / 2, start, size);
+            start += size;
+        } else {
+            let mut gap = digit(head);
+            while gap > 0 && tail > head {
+                let size = gap.min(tail_left);
+                checksum += block_checksum(tail / 2, start, size);
+                start += size;
+                gap -= size;
+                tail_left -= size;
+                if tail_left == 0 {
+                    tail -= 2;
+                    tail_left = digit(tail);
                 }
-                // The mut_range_bound clippy warning does not apply because we
-                // are modifying the range bound for the next iteration, due to
-                // the immediate break.
-                // https://rust-lang.github.io/rust-clippy/master/index.html#mut_range_bound
-                n_gaps -= 1;
-                break;
-
-                // In principle, we should account for the fact that moving a
-                // file to another location leaves behind a gap. However, as
-                // files are moved right to left only once, no other file can
-                // ever fill this newly created gap.
-                // If needed, the files before and after could be found using
-                // binary search by matching to the new_gap endpoints.
             }
         }
+        head += 1;
     }
-    memory.files.sort_by(|a, b| a.start.cmp(&b.start));
+    Ok(checksum)
+}
+
+/// Compute the checksum of the filesystem after moving file fragments from the
+/// back into the first open gap at the front that can completely house them.
+pub fn part_2(memory: &mut Memory) -> u128 {
+    memory.defragment_whole_files();
     memory.checksum()
 }
 
@@ -229,8 +579,17 @@ mod tests {
 
     use std::vec;
 
-    use super::{parse_input, part_1, part_2, Block, Memory};
-    use crate::{day09::BlockValue, util::read_file_to_string};
+    use std::io::{BufReader, ErrorKind, Read};
+
+    use super::{
+        insert_gap, parse_input, parse_reader, part_1, part_1_streaming, part_2, Block, Memory,
+        MemoryInvariantError, Move,
+    };
+    use crate::{
+        day09::BlockValue,
+        testing::XorShift,
+        util::{read_file_to_string, AocError},
+    };
 
     // 0    5    10   15   20   25   30   35   40
     // 00...111...2...333.44.5555.6666.777.888899
@@ -240,7 +599,7 @@ mod tests {
     #[test]
     fn test_parse_input() {
         assert_eq!(
-            parse_input(INPUT),
+            parse_input(INPUT).expect("input is valid"),
             Memory::new(
                 vec![
                     Block::new(0, 2, BlockValue::File(0)),
@@ -268,34 +627,460 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_parse_input_whitespace() {
+        for input in [INPUT, &read_file_to_string("data/day09.txt")] {
+            let trimmed = input.trim_end();
+            for input in [format!("{trimmed}\n"), format!("{trimmed} \r\n")] {
+                let mut memory = parse_input(&input).expect("input is valid");
+                assert_eq!(memory, parse_input(trimmed).expect("input is valid"));
+                assert_eq!(
+                    part_1(&mut memory),
+                    part_1(&mut parse_input(trimmed).expect("input is valid"))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_input_errors() {
+        assert_eq!(
+            parse_input("2333a33"),
+            Err(AocError::InvalidByte {
+                offset: 4,
+                byte: b'a'
+            })
+        );
+        assert_eq!(
+            parse_input("23 33"),
+            Err(AocError::InvalidByte {
+                offset: 2,
+                byte: b' '
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_reader_chunks() {
+        let input = format!("{INPUT}\n");
+        for capacity in [1, 2, 3, 7] {
+            let reader = BufReader::with_capacity(capacity, input.as_bytes());
+            assert_eq!(
+                parse_reader(reader),
+                parse_input(INPUT),
+                "capacity {capacity}"
+            );
+        }
+        let reader = BufReader::with_capacity(2, "2333a33".as_bytes());
+        assert_eq!(
+            parse_reader(reader),
+            Err(AocError::InvalidByte {
+                offset: 4,
+                byte: b'a'
+            })
+        );
+    }
+
+    /// A reader that fails after reading some bytes.
+    struct FailingReader<'a>(&'a [u8]);
+
+    impl Read for FailingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::new(ErrorKind::BrokenPipe, "broken"));
+            }
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_parse_reader_failing() {
+        let reader = BufReader::with_capacity(4, FailingReader(INPUT.as_bytes()));
+        assert_eq!(
+            parse_reader(reader),
+            Err(AocError::Io(ErrorKind::BrokenPipe))
+        );
+    }
+
+    #[test]
+    fn test_parse_input_edge_cases() {
+        let mut memory = parse_input("").expect("input is valid");
+        assert_eq!(memory, Memory::new(vec![], vec![]));
+        assert_eq!(part_1(&mut memory), 0);
+        assert_eq!(part_2(&mut memory), 0);
+        let mut memory = parse_input("3\n").expect("input is valid");
+        assert_eq!(
+            memory,
+            Memory::new(vec![Block::new(0, 3, BlockValue::File(0))], vec![])
+        );
+        assert_eq!(part_1(&mut memory), 0);
+        assert_eq!(part_2(&mut memory), 0);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            parse_input(INPUT).expect("input is valid").to_string(),
+            "00...111...2...333.44.5555.6666.777.888899"
+        );
+        assert_eq!(
+            parse_input("101010101010101010112")
+                .expect("input is valid")
+                .to_string(),
+            "0123456789.(10)(10)"
+        );
+        // Overlapping and missing cells.
+        let memory = Memory::new(
+            vec![
+                Block::new(0, 2, BlockValue::File(0)),
+                Block::new(5, 7, BlockValue::File(1)),
+            ],
+            vec![Block::new(1, 3, BlockValue::Empty)],
+        );
+        assert_eq!(memory.to_string(), "0!.??11");
+    }
+
+    #[test]
+    fn test_checksum_large_blocks() {
+        // Parsed files span at most 9 cells, so build larger blocks directly.
+        let memory = Memory::new(
+            vec![
+                Block::new(0, 11, BlockValue::File(1)),
+                Block::new(11, 111, BlockValue::File(2)),
+            ],
+            vec![],
+        );
+        let expected: usize = (0..11).sum::<usize>() + 2 * (11..111).sum::<usize>();
+        assert_eq!(memory.checksum(), expected as u128);
+        // Used to overflow a usize.
+        let size = usize::MAX / 2;
+        let memory = Memory::new(vec![Block::new(0, size, BlockValue::File(1))], vec![]);
+        assert_eq!(memory.checksum(), size as u128 * (size as u128 - 1) / 2);
+    }
+
+    #[test]
+    fn test_checksum_beyond_u32() {
+        // Files far beyond 2^32, with ids and positions whose products do not
+        // fit in a u64.
+        let [start_1, start_2] = [1usize << 40, (1 << 40) + 100];
+        let [id_1, id_2] = [1usize << 33, (1 << 33) + 1];
+        let memory = Memory::new(
+            vec![
+                Block::new(start_1, start_1 + 10, BlockValue::File(id_1)),
+                Block::new(start_2, start_2 + 7, BlockValue::File(id_2)),
+            ],
+            vec![Block::new(start_1 + 10, start_2, BlockValue::Empty)],
+        );
+        let cells = (start_1..start_1 + 10)
+            .map(|i| id_1 as u128 * i as u128)
+            .chain((start_2..start_2 + 7).map(|i| id_2 as u128 * i as u128));
+        let expected =
+            id_1 as u128 * (10 * start_1 as u128 + 45) + id_2 as u128 * (7 * start_2 as u128 + 21);
+        assert!(expected > u64::MAX as u128);
+        assert_eq!(memory.checksum(), cells.sum::<u128>());
+        assert_eq!(memory.checksum(), expected);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overflows u128")]
+    fn test_checksum_overflow() {
+        let memory = Memory::new(
+            vec![Block::new(
+                usize::MAX - 10,
+                usize::MAX,
+                BlockValue::File(usize::MAX),
+            )],
+            vec![],
+        );
+        memory.checksum();
+    }
+
+    #[test]
+    fn test_part_1_merged_tail() {
+        // 000000000..111111111
+        // 000000000111111111..
+        // The moved fragment of file 1 fills the last gap and is merged with
+        // the rest of it.
+        let mut memory = parse_input("929").expect("input is valid");
+        let expected: usize = (9..18).sum();
+        assert_eq!(part_1(&mut memory), expected as u128);
+        assert_eq!(memory.to_string(), "000000000111111111..");
+    }
+
+    #[test]
+    fn test_validate() {
+        for input in [INPUT, &read_file_to_string("data/day09.txt")] {
+            let mut memory = parse_input(input).expect("input is valid");
+            assert_eq!(memory.validate(), Ok(()));
+            part_1(&mut memory);
+            assert_eq!(memory.validate(), Ok(()));
+            let mut memory = parse_input(input).expect("input is valid");
+            part_2(&mut memory);
+            assert_eq!(memory.validate(), Ok(()));
+        }
+        let mut memory = parse_input(INPUT).expect("input is valid");
+        part_1(&mut memory);
+        assert_eq!(
+            memory.to_string(),
+            "0099811188827773336446555566.............."
+        );
+    }
+
+    #[test]
+    fn test_validate_corrupted() {
+        let file = |start, stop, idx| Block::new(start, stop, BlockValue::File(idx));
+        let gap = |start, stop| Block::new(start, stop, BlockValue::Empty);
+        let mut block = file(0, 2, 0);
+        block.size = 3;
+        assert_eq!(
+            Memory::new(vec![block], vec![gap(2, 3)]).validate(),
+            Err(MemoryInvariantError::InconsistentSize(block))
+        );
+        assert_eq!(
+            Memory::new(vec![file(0, 2, 0)], vec![file(2, 3, 1)]).validate(),
+            Err(MemoryInvariantError::WrongValue(file(2, 3, 1)))
+        );
+        assert_eq!(
+            Memory::new(vec![file(3, 4, 1), file(0, 2, 0)], vec![gap(2, 3)]).validate(),
+            Err(MemoryInvariantError::Unsorted(file(0, 2, 0)))
+        );
+        assert_eq!(
+            Memory::new(vec![file(0, 2, 0), file(3, 4, 1)], vec![gap(1, 3)]).validate(),
+            Err(MemoryInvariantError::Overlap(file(0, 2, 0), gap(1, 3)))
+        );
+        assert_eq!(
+            Memory::new(vec![file(0, 2, 0), file(5, 6, 1)], vec![gap(2, 3)]).validate(),
+            Err(MemoryInvariantError::Hole { start: 3, stop: 5 })
+        );
+    }
+
     #[test]
     fn test_part_1_small() {
         // 0    5    10   15   20   25   30   35   40
         // 00...111...2...333.44.5555.6666.777.888899
         // 0099811188827773336446555566..............
-        assert_eq!(part_1(&mut parse_input(INPUT)), 1928)
+        assert_eq!(
+            part_1(&mut parse_input(INPUT).expect("input is valid")),
+            1928
+        )
     }
 
     #[test]
     fn test_part_1_full() {
         assert_eq!(
-            part_1(&mut parse_input(&read_file_to_string("data/day09.txt"))),
+            part_1(
+                &mut parse_input(&read_file_to_string("data/day09.txt")).expect("input is valid")
+            ),
             6242766523059
         )
     }
 
+    #[test]
+    fn test_part_1_streaming() {
+        assert_eq!(part_1_streaming(INPUT), Ok(1928));
+        assert_eq!(
+            part_1_streaming(&read_file_to_string("data/day09.txt")),
+            Ok(6242766523059)
+        );
+        assert_eq!(part_1_streaming(""), Ok(0));
+        assert_eq!(part_1_streaming("12345\n"), Ok(60));
+        assert_eq!(
+            part_1_streaming("123\n45"),
+            Err(AocError::InvalidByte {
+                offset: 3,
+                byte: b'\n'
+            })
+        );
+        assert_eq!(
+            part_1_streaming("12a45"),
+            Err(AocError::InvalidByte {
+                offset: 2,
+                byte: b'a'
+            })
+        );
+
+        let mut rng = XorShift::new(0x2024_0901);
+        for _ in 0..200 {
+            let len = 1 + (rng.next_u64() % 100) as usize;
+            let input: String = (0..len)
+                .map(|_| char::from(b'0' + (rng.next_u64() % 10) as u8))
+                .collect();
+            let mut memory = parse_input(&input).expect("input is valid");
+            assert_eq!(part_1_streaming(&input), Ok(part_1(&mut memory)), "{input}");
+        }
+    }
+
     #[test]
     fn test_part_2_small() {
         // 0    5    10   15   20   25   30   35   40
         // 00...111...2...333.44.5555.6666.777.888899
         // 00992111777.44.333....5555.6666.....8888..
-        assert_eq!(part_2(&mut parse_input(INPUT)), 2858)
+        assert_eq!(
+            part_2(&mut parse_input(INPUT).expect("input is valid")),
+            2858
+        )
+    }
+
+    /// Scan all gaps from the left for every file.
+    fn part_2_naive(memory: &mut Memory) -> u128 {
+        for file in memory.files.iter_mut().rev() {
+            for i_gap in 0..memory.gaps.len() {
+                let gap = &mut memory.gaps[i_gap];
+                if gap.start > file.start {
+                    break;
+                }
+                if gap.size >= file.size {
+                    let vacated = Block::new(file.start, file.stop, BlockValue::Empty);
+                    file.move_to_start(gap.start);
+                    if gap.update_start(file.stop).is_err() {
+                        memory.gaps.remove(i_gap);
+                    }
+                    insert_gap(&mut memory.gaps, vacated);
+                    break;
+                }
+            }
+        }
+        memory.files.sort_by_key(|a| a.start);
+        memory.checksum()
+    }
+
+    #[test]
+    fn test_part_2_naive() {
+        for input in [INPUT, &read_file_to_string("data/day09.txt")] {
+            let mut memory = parse_input(input).expect("input is valid");
+            let mut memory_naive = parse_input(input).expect("input is valid");
+            assert_eq!(part_2(&mut memory), part_2_naive(&mut memory_naive));
+            assert_eq!(memory, memory_naive);
+        }
+    }
+
+    #[test]
+    fn test_part_2_random() {
+        let mut rng = XorShift::new(0x2024_0909);
+        for _ in 0..200 {
+            let len = 1 + 2 * (rng.next_u64() % 100) as usize;
+            let input: String = (0..len)
+                .map(|_| char::from(b'0' + (rng.next_u64() % 10) as u8))
+                .collect();
+            let mut memory = parse_input(&input).expect("input is valid");
+            let mut memory_naive = parse_input(&input).expect("input is valid");
+            assert_eq!(
+                part_2(&mut memory),
+                part_2_naive(&mut memory_naive),
+                "{input}"
+            );
+            assert_eq!(memory, memory_naive, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_defragment_whole_files() {
+        // 0    5    10   15   20   25   30   35   40
+        // 00...111...2...333.44.5555.6666.777.888899
+        // 00992111777.44.333....5555.6666.....8888..
+        let mut memory = parse_input(INPUT).expect("input is valid");
+        let moves = memory.defragment_whole_files();
+        let m = |file, from, to, size| Move {
+            file,
+            from,
+            to,
+            size,
+        };
+        assert_eq!(
+            moves,
+            vec![
+                m(9, 40, 2, 2),
+                m(7, 32, 8, 3),
+                m(4, 19, 12, 2),
+                m(2, 11, 4, 1)
+            ]
+        );
+        assert_eq!(
+            memory.to_string(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        for input in [INPUT, "929", &read_file_to_string("data/day09.txt")] {
+            for whole_files in [false, true] {
+                let mut memory = parse_input(input).expect("input is valid");
+                let moves = match whole_files {
+                    true => memory.defragment_whole_files(),
+                    false => memory.defragment_fragments(),
+                };
+                let mut replayed = parse_input(input).expect("input is valid");
+                for m in moves.iter() {
+                    assert_eq!(replayed.apply(m), Some(()), "{m:?}");
+                }
+                assert_eq!(replayed.checksum(), memory.checksum());
+                assert_eq!(replayed, memory);
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let mut memory = parse_input(INPUT).expect("input is valid");
+        let original = parse_input(INPUT).expect("input is valid");
+        let m = |file, from, to, size| Move {
+            file,
+            from,
+            to,
+            size,
+        };
+        // Not the right file.
+        assert_eq!(memory.apply(&m(8, 40, 2, 2)), None);
+        // Not entirely inside the file.
+        assert_eq!(memory.apply(&m(9, 41, 2, 2)), None);
+        // Not entirely inside a gap.
+        assert_eq!(memory.apply(&m(9, 40, 4, 2)), None);
+        assert_eq!(memory.apply(&m(9, 40, 1, 2)), None);
+        assert_eq!(memory, original);
+    }
+
+    /// Check that the files and gaps exactly cover `0..total_length`, without
+    /// overlaps or adjacent gaps.
+    fn assert_tiled(memory: &Memory, total_length: usize) {
+        let mut blocks: Vec<_> = memory.files.iter().chain(&memory.gaps).collect();
+        blocks.sort_by_key(|block| block.start);
+        let mut stop = 0;
+        for block in blocks {
+            assert_eq!(block.start, stop);
+            assert_eq!(block.size, block.stop - block.start);
+            stop = block.stop;
+        }
+        assert_eq!(stop, total_length);
+        for pair in memory.gaps.windows(2) {
+            assert!(pair[0].stop < pair[1].start);
+        }
+    }
+
+    #[test]
+    fn test_part_2_gaps() {
+        let mut memory = parse_input(INPUT).expect("input is valid");
+        part_2(&mut memory);
+        assert_eq!(
+            memory.to_string(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+        assert_tiled(&memory, 42);
+
+        let mut memory =
+            parse_input(&read_file_to_string("data/day09.txt")).expect("input is valid");
+        let total_length = memory.files.last().expect("at least 1 file").stop;
+        part_2(&mut memory);
+        assert_tiled(&memory, total_length);
     }
 
     #[test]
     fn test_part_2_full() {
         assert_eq!(
-            part_2(&mut parse_input(&read_file_to_string("data/day09.txt"))),
+            part_2(
+                &mut parse_input(&read_file_to_string("data/day09.txt")).expect("input is valid")
+            ),
             6272188244509
         )
     }
@@ -1,6 +1,9 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
-use crate::util::{Coordinate, Matrix, COORDINATE_OFFSETS_NESW};
+use crate::util::{AocError, Coordinate, Matrix, COORDINATE_OFFSETS_NESW};
 
 struct EvaluationState {
     reachable: HashMap<Coordinate, HashSet<Coordinate>>,
@@ -18,102 +21,220 @@ impl EvaluationState {
     }
 }
 
-/// Recursively construct inclining paths from a starting position.
-///
-/// For a more optimal solution, we might need to check if a visited coordinate
-/// does not have any known solutions.
-///
-/// if let Some(hashed) = state.reachable.get(current_coord).cloned() {
-///    state.trailheads.insert(trail[0]);
-///    state
-///        .reachable
-///        .entry(trail[0])
-///        .and_modify(|peaks| {
-///            peaks.extend(hashed.iter());
-///         })
-///         .or_insert(hashed);
-///     return;
-/// }
-fn evaluate_coordinate(
-    current_coord: &Coordinate,
-    current_val: u8,
-    trail: &mut Vec<Coordinate>,
-    matrix: &Matrix<u8>,
-    bounds: &[&Coordinate; 2],
-    state: &mut EvaluationState,
-) {
-    for offset in COORDINATE_OFFSETS_NESW {
-        let neighbor_coord = *current_coord + offset;
-        if !neighbor_coord.is_in(bounds[0], bounds[1]) {
-            continue;
+/// The heights at which trails start and end, and the change in height of
+/// every step along a trail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrailRules {
+    pub start: u8,
+    pub end: u8,
+    pub step: i8,
+}
+
+impl Default for TrailRules {
+    fn default() -> Self {
+        TrailRules {
+            start: 0,
+            end: 9,
+            step: 1,
         }
-        let neighbor_val = matrix[neighbor_coord.r as usize][neighbor_coord.c as usize];
-        if neighbor_val != current_val + 1 {
-            continue;
+    }
+}
+
+/// A set of `TrailRules` that no trail can satisfy.
+#[derive(Debug, PartialEq)]
+pub enum TrailRulesError {
+    /// A height that is not a single digit.
+    HeightOutOfRange(u8),
+    /// A step that does not change the height.
+    ZeroStep,
+    /// An end that can not be reached from the start in whole steps.
+    Unreachable(TrailRules),
+}
+
+impl Display for TrailRulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::HeightOutOfRange(height) => write!(f, "height {height} is not a digit"),
+            Self::ZeroStep => write!(f, "step does not change the height"),
+            Self::Unreachable(rules) => write!(
+                f,
+                "end {} is unreachable from start {} in steps of {}",
+                rules.end, rules.start, rules.step
+            ),
         }
-        trail.push(neighbor_coord);
-        if trail.len() == 10 {
-            state.n_trails += 1;
-            state.trailheads.insert(trail[0]);
-            for coord in trail.iter() {
-                state
-                    .reachable
-                    .entry(*coord)
-                    .and_modify(|peaks| {
-                        peaks.insert(neighbor_coord);
-                    })
-                    .or_insert(HashSet::from([neighbor_coord]));
+    }
+}
+
+impl std::error::Error for TrailRulesError {}
+
+impl TrailRules {
+    /// Check that a trail can get from the start to the end in one or more
+    /// steps, without leaving the single digit heights.
+    pub fn validate(&self) -> Result<(), TrailRulesError> {
+        for height in [self.start, self.end] {
+            if height > 9 {
+                return Err(TrailRulesError::HeightOutOfRange(height));
             }
-        } else {
-            evaluate_coordinate(&neighbor_coord, neighbor_val, trail, matrix, bounds, state);
         }
-        trail.pop();
+        if self.step == 0 {
+            return Err(TrailRulesError::ZeroStep);
+        }
+        let distance = self.end as i8 - self.start as i8;
+        if distance % self.step != 0 || distance / self.step <= 0 {
+            return Err(TrailRulesError::Unreachable(*self));
+        }
+        Ok(())
+    }
+
+    /// The height that follows a height along a trail, if any.
+    fn next(&self, height: u8) -> Option<u8> {
+        u8::try_from(height as i8 + self.step).ok()
     }
 }
 
-/// Loop over all coordinates and recursively construct inclining paths from all
-/// 0-height starting positions.
-fn solve(matrix: &Matrix<u8>) -> EvaluationState {
+/// Loop over all coordinates and construct paths from all starting positions
+/// to all ending positions, using an explicit stack instead of recursion. Only
+/// the peaks reachable from trailheads are stored.
+fn solve(
+    matrix: &Matrix<Option<u8>>,
+    rules: &TrailRules,
+) -> Result<EvaluationState, TrailRulesError> {
+    rules.validate()?;
     let mut state = EvaluationState::new();
     let bounds = [
         &Coordinate::new(0, 0),
         &Coordinate::new(matrix.shape()[0] as isize, matrix.shape()[1] as isize),
     ];
-    let mut trail = Vec::<Coordinate>::with_capacity(10);
+    let mut stack = Vec::<(Coordinate, u8)>::new();
     for row in 0..matrix.shape()[0] {
         for col in 0..matrix.shape()[1] {
-            let current_val = matrix[row][col];
-            if current_val != 0 {
+            if matrix[row][col] != Some(rules.start) {
                 continue;
             }
-            let current_coord = Coordinate::new(row as isize, col as isize);
-            // We explore a new trail from this position.
-            trail.clear();
-            trail.push(current_coord);
-            evaluate_coordinate(
-                &current_coord,
-                current_val,
-                &mut trail,
-                matrix,
-                &bounds,
-                &mut state,
-            );
+            let trailhead = Coordinate::new(row as isize, col as isize);
+            // We explore all trails from this position. Every path that
+            // reaches a peak is a distinct trail.
+            let mut peaks = HashSet::<Coordinate>::new();
+            stack.push((trailhead, rules.start));
+            while let Some((current_coord, current_val)) = stack.pop() {
+                if current_val == rules.end {
+                    state.n_trails += 1;
+                    peaks.insert(current_coord);
+                    continue;
+                }
+                let Some(next_val) = rules.next(current_val) else {
+                    continue;
+                };
+                for offset in COORDINATE_OFFSETS_NESW {
+                    let neighbor_coord = current_coord + offset;
+                    if !neighbor_coord.is_in(bounds[0], bounds[1]) {
+                        continue;
+                    }
+                    if matrix[neighbor_coord.r as usize][neighbor_coord.c as usize]
+                        == Some(next_val)
+                    {
+                        stack.push((neighbor_coord, next_val));
+                    }
+                }
+            }
+            if !peaks.is_empty() {
+                state.trailheads.insert(trailhead);
+                state.reachable.insert(trailhead, peaks);
+            }
         }
     }
-    state
+    Ok(state)
 }
 
-pub fn parse_input(input: &str) -> Matrix<u8> {
-    let mut data = vec![];
-    for line in input.lines() {
+/// Construct every distinct inclining path from a trailhead to a 9-height
+/// element, ordered from the trailhead to the peak. Trails sharing a prefix are
+/// only counted once per distinct continuation.
+pub fn trails_from(matrix: &Matrix<Option<u8>>, trailhead: Coordinate) -> Vec<Vec<Coordinate>> {
+    let bounds = [
+        &Coordinate::new(0, 0),
+        &Coordinate::new(matrix.shape()[0] as isize, matrix.shape()[1] as isize),
+    ];
+    if !trailhead.is_in(bounds[0], bounds[1])
+        || matrix[trailhead.r as usize][trailhead.c as usize] != Some(0)
+    {
+        return Vec::new();
+    }
+    let mut trails = Vec::new();
+    let mut stack = vec![vec![trailhead]];
+    while let Some(trail) = stack.pop() {
+        let current_coord = *trail.last().expect("trails are never empty");
+        let current_val = matrix[current_coord.r as usize][current_coord.c as usize]
+            .expect("trails only contain passable cells");
+        if current_val == 9 {
+            trails.push(trail);
+            continue;
+        }
+        for offset in COORDINATE_OFFSETS_NESW {
+            let neighbor_coord = current_coord + offset;
+            if !neighbor_coord.is_in(bounds[0], bounds[1]) {
+                continue;
+            }
+            if matrix[neighbor_coord.r as usize][neighbor_coord.c as usize] == Some(current_val + 1)
+            {
+                let mut next = trail.clone();
+                next.push(neighbor_coord);
+                stack.push(next);
+            }
+        }
+    }
+    trails
+}
+
+/// Construct every distinct inclining path from all 0-height starting
+/// positions to a 9-height element.
+pub fn find_trails(matrix: &Matrix<Option<u8>>) -> Vec<Vec<Coordinate>> {
+    let mut trails = Vec::new();
+    for row in 0..matrix.shape()[0] {
+        for col in 0..matrix.shape()[1] {
+            if matrix[row][col] == Some(0) {
+                trails.extend(trails_from(
+                    matrix,
+                    Coordinate::new(row as isize, col as isize),
+                ));
+            }
+        }
+    }
+    trails
+}
+
+/// Parse a topographic map of single digit heights. Cells marked `.` are
+/// impassable and parsed as `None`, any other non-digit is an error.
+pub fn parse_input(input: &str) -> Result<Matrix<Option<u8>>, AocError> {
+    const IMPASSABLE: char = '.';
+    let mut data: Vec<Vec<Option<u8>>> = vec![];
+    for (i, line) in input.lines().enumerate() {
         let mut row = Vec::with_capacity(line.len());
-        for byte in line.bytes() {
-            // Digit 0 is represented by 0x30.
-            row.push(byte - 0x30);
+        for (col, ch) in line.chars().enumerate() {
+            row.push(match ch {
+                // Digit 0 is represented by 0x30.
+                '0'..='9' => Some(ch as u8 - 0x30),
+                IMPASSABLE => None,
+                _ => {
+                    return Err(AocError::InvalidCharacter {
+                        line: i + 1,
+                        col: col + 1,
+                        char: ch,
+                    })
+                }
+            });
+        }
+        if let Some(first) = data.first() {
+            if row.len() != first.len() {
+                return Err(AocError::RaggedRow {
+                    line: i + 1,
+                    len: row.len(),
+                    expected: first.len(),
+                });
+            }
         }
         data.push(row);
     }
-    Matrix::new(data)
+    Ok(Matrix::new(data))
 }
 
 /// Compute the sum of all trailhead scores.
@@ -122,14 +243,23 @@ pub fn parse_input(input: &str) -> Matrix<u8> {
 /// - it has at least 1 continuous path from itself to a 9-value element. Moves
 ///   can only occur in the four cardinal directions North, East, South and West.
 ///   The score of a trailhead equals the number of acceptable paths.
-pub fn part_1(matrix: &Matrix<u8>) -> usize {
-    let state = solve(matrix);
-    state
+pub fn part_1(matrix: &Matrix<Option<u8>>) -> usize {
+    part_1_with_rules(matrix, &TrailRules::default()).expect("default rules are valid")
+}
+
+/// Compute the sum of all trailhead scores, with custom start and end heights
+/// and step size.
+pub fn part_1_with_rules(
+    matrix: &Matrix<Option<u8>>,
+    rules: &TrailRules,
+) -> Result<usize, TrailRulesError> {
+    let state = solve(matrix, rules)?;
+    Ok(state
         .trailheads
         .iter()
         .filter_map(|coord| state.reachable.get(coord))
         .map(|peaks: &HashSet<Coordinate>| peaks.len())
-        .sum()
+        .sum())
 }
 
 /// Compute the sum of all distinct trails that depart from a trailhead.
@@ -138,14 +268,31 @@ pub fn part_1(matrix: &Matrix<u8>) -> usize {
 /// - it has at least 1 continuous path from itself to a 9-value element. Moves
 ///   can only occur in the four cardinal directions North, East, South and West.
 ///   The score of a trailhead equals the number of acceptable paths.
-pub fn part_2(matrix: &Matrix<u8>) -> usize {
-    solve(matrix).n_trails
+pub fn part_2(matrix: &Matrix<Option<u8>>) -> usize {
+    part_2_with_rules(matrix, &TrailRules::default()).expect("default rules are valid")
+}
+
+/// Compute the sum of all distinct trails that depart from a trailhead, with
+/// custom start and end heights and step size.
+pub fn part_2_with_rules(
+    matrix: &Matrix<Option<u8>>,
+    rules: &TrailRules,
+) -> Result<usize, TrailRulesError> {
+    Ok(solve(matrix, rules)?.n_trails)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_input, part_1, part_2};
-    use crate::util::{read_file_to_string, Matrix};
+    use std::collections::{HashMap, HashSet};
+
+    use super::{
+        find_trails, parse_input, part_1, part_1_with_rules, part_2, part_2_with_rules, solve,
+        trails_from, TrailRules, TrailRulesError,
+    };
+    use crate::{
+        testing::XorShift,
+        util::{read_file_to_string, AocError, Coordinate, Matrix, COORDINATE_OFFSETS_NESW},
+    };
     const INPUT: &str = "89010123
 78121874
 87430965
@@ -158,17 +305,22 @@ mod tests {
     #[test]
     fn test_parse_input() {
         assert_eq!(
-            &parse_input(INPUT),
-            &Matrix::new(vec![
-                vec![8, 9, 0, 1, 0, 1, 2, 3],
-                vec![7, 8, 1, 2, 1, 8, 7, 4],
-                vec![8, 7, 4, 3, 0, 9, 6, 5],
-                vec![9, 6, 5, 4, 9, 8, 7, 4],
-                vec![4, 5, 6, 7, 8, 9, 0, 3],
-                vec![3, 2, 0, 1, 9, 0, 1, 2],
-                vec![0, 1, 3, 2, 9, 8, 0, 1],
-                vec![1, 0, 4, 5, 6, 7, 3, 2],
-            ])
+            parse_input(INPUT),
+            Ok(Matrix::new(
+                [
+                    vec![8, 9, 0, 1, 0, 1, 2, 3],
+                    vec![7, 8, 1, 2, 1, 8, 7, 4],
+                    vec![8, 7, 4, 3, 0, 9, 6, 5],
+                    vec![9, 6, 5, 4, 9, 8, 7, 4],
+                    vec![4, 5, 6, 7, 8, 9, 0, 3],
+                    vec![3, 2, 0, 1, 9, 0, 1, 2],
+                    vec![0, 1, 3, 2, 9, 8, 0, 1],
+                    vec![1, 0, 4, 5, 6, 7, 3, 2],
+                ]
+                .into_iter()
+                .map(|row| row.into_iter().map(Some).collect())
+                .collect()
+            ))
         )
     }
 
@@ -176,27 +328,290 @@ mod tests {
     fn test_part_1_small() {
         // Scores of trailheads in reading order.
         // 5, 6, 5, 3, 1, 3, 5, 3, 5 expected
-        assert_eq!(part_1(&parse_input(INPUT)), 36)
+        assert_eq!(part_1(&parse_input(INPUT).expect("input is valid")), 36)
     }
 
     #[test]
     fn test_part_1_full() {
         assert_eq!(
-            part_1(&parse_input(&read_file_to_string("data/day10.txt"))),
+            part_1(&parse_input(&read_file_to_string("data/day10.txt")).expect("input is valid")),
             794
         );
     }
 
     #[test]
     fn test_part_2_small() {
-        assert_eq!(part_2(&parse_input(INPUT)), 81)
+        assert_eq!(part_2(&parse_input(INPUT).expect("input is valid")), 81)
     }
 
     #[test]
     fn test_part_2_full() {
         assert_eq!(
-            part_2(&parse_input(&read_file_to_string("data/day10.txt"))),
+            part_2(&parse_input(&read_file_to_string("data/day10.txt")).expect("input is valid")),
             1706
         )
     }
+
+    #[test]
+    fn test_parse_input_impassable() {
+        let matrix = parse_input("0.\n.9").expect("input is valid");
+        assert_eq!(
+            matrix,
+            Matrix::new(vec![vec![Some(0), None], vec![None, Some(9)]])
+        );
+    }
+
+    #[test]
+    fn test_parse_input_invalid() {
+        assert_eq!(
+            parse_input("01\n2#"),
+            Err(AocError::InvalidCharacter {
+                line: 2,
+                col: 2,
+                char: '#'
+            })
+        );
+        assert_eq!(
+            parse_input("012\n34"),
+            Err(AocError::RaggedRow {
+                line: 2,
+                len: 2,
+                expected: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_impassable_examples() {
+        // A single trailhead with a score of 2.
+        let matrix = parse_input(
+            "...0...
+...1...
+...2...
+6543456
+7.....7
+8.....8
+9.....9",
+        )
+        .expect("input is valid");
+        assert_eq!(part_1(&matrix), 2);
+        // A single trailhead with a score of 4.
+        let matrix = parse_input(
+            "..90..9
+...1.98
+...2..7
+6543456
+765.987
+876....
+987....",
+        )
+        .expect("input is valid");
+        assert_eq!(part_1(&matrix), 4);
+        assert_eq!(part_2(&matrix), 13);
+        // Two trailheads with scores of 1 and 2.
+        let matrix = parse_input(
+            "10..9..
+2...8..
+3...7..
+4567654
+...8..3
+...9..2
+.....01",
+        )
+        .expect("input is valid");
+        assert_eq!(part_1(&matrix), 3);
+        // A single trailhead with a rating of 3.
+        let matrix = parse_input(
+            ".....0.
+..4321.
+..5..2.
+..6543.
+..7..4.
+..8765.
+..9....",
+        )
+        .expect("input is valid");
+        assert_eq!(part_2(&matrix), 3);
+    }
+
+    #[test]
+    fn test_find_trails() {
+        for input in [INPUT, &read_file_to_string("data/day10.txt")] {
+            let matrix = parse_input(input).expect("input is valid");
+            let trails = find_trails(&matrix);
+            assert_eq!(trails.len(), part_2(&matrix));
+            assert_eq!(trails.iter().collect::<HashSet<_>>().len(), trails.len());
+            for trail in trails.iter() {
+                assert_eq!(trail.len(), 10);
+                for (height, coord) in trail.iter().enumerate() {
+                    assert_eq!(
+                        matrix[coord.r as usize][coord.c as usize],
+                        Some(height as u8)
+                    );
+                }
+                for pair in trail.windows(2) {
+                    let step = pair[1] - pair[0];
+                    assert_eq!(step.r.abs() + step.c.abs(), 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trails_from() {
+        let matrix = parse_input(INPUT).expect("input is valid");
+        // Ratings of trailheads in reading order.
+        // 20, 24, 10, 4, 1, 4, 5, 8, 5
+        let trails = trails_from(&matrix, Coordinate::new(6, 0));
+        assert_eq!(trails.len(), 5);
+        assert_eq!(trails.iter().collect::<HashSet<_>>().len(), 5);
+        assert!(trails.iter().all(|trail| trail[0] == Coordinate::new(6, 0)));
+        // Not a trailhead.
+        assert!(trails_from(&matrix, Coordinate::new(0, 0)).is_empty());
+        assert!(trails_from(&matrix, Coordinate::new(8, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_rules_descending() {
+        let matrix = parse_input(INPUT).expect("input is valid");
+        let inverted = Matrix::new(
+            matrix
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|height| height.map(|height| 9 - height))
+                        .collect()
+                })
+                .collect(),
+        );
+        let rules = TrailRules {
+            start: 9,
+            end: 0,
+            step: -1,
+        };
+        assert_eq!(part_1_with_rules(&inverted, &rules), Ok(36));
+        assert_eq!(part_2_with_rules(&inverted, &rules), Ok(81));
+    }
+
+    #[test]
+    fn test_rules_shorter() {
+        let matrix = parse_input("12345678\n00000000").expect("input is valid");
+        assert_eq!(part_2(&matrix), 0);
+        let rules = TrailRules {
+            start: 1,
+            end: 8,
+            step: 1,
+        };
+        assert_eq!(part_1_with_rules(&matrix, &rules), Ok(1));
+        assert_eq!(part_2_with_rules(&matrix, &rules), Ok(1));
+        let rules = TrailRules {
+            start: 2,
+            end: 8,
+            step: 2,
+        };
+        assert_eq!(part_2_with_rules(&matrix, &rules), Ok(0));
+    }
+
+    #[test]
+    fn test_rules_invalid() {
+        let matrix = parse_input(INPUT).expect("input is valid");
+        let rules = |start, end, step| TrailRules { start, end, step };
+        assert_eq!(
+            part_1_with_rules(&matrix, &rules(0, 10, 1)),
+            Err(TrailRulesError::HeightOutOfRange(10))
+        );
+        assert_eq!(
+            part_1_with_rules(&matrix, &rules(0, 9, 0)),
+            Err(TrailRulesError::ZeroStep)
+        );
+        for rules in [rules(0, 9, 2), rules(0, 9, -1), rules(5, 5, 1)] {
+            assert_eq!(
+                part_2_with_rules(&matrix, &rules),
+                Err(TrailRulesError::Unreachable(rules))
+            );
+        }
+    }
+
+    /// The original recursive implementation, returning the number of trails
+    /// and the peaks reachable from every trailhead.
+    fn solve_recursive(
+        matrix: &Matrix<Option<u8>>,
+    ) -> (usize, HashMap<Coordinate, HashSet<Coordinate>>) {
+        fn evaluate(
+            current_coord: Coordinate,
+            current_val: u8,
+            trail: &mut Vec<Coordinate>,
+            matrix: &Matrix<Option<u8>>,
+            state: &mut (usize, HashMap<Coordinate, HashSet<Coordinate>>),
+        ) {
+            let bounds = Coordinate::new(matrix.shape()[0] as isize, matrix.shape()[1] as isize);
+            for offset in COORDINATE_OFFSETS_NESW {
+                let neighbor_coord = current_coord + offset;
+                if !neighbor_coord.is_in(&Coordinate::new(0, 0), &bounds) {
+                    continue;
+                }
+                let neighbor_val = current_val + 1;
+                if matrix[neighbor_coord.r as usize][neighbor_coord.c as usize]
+                    != Some(neighbor_val)
+                {
+                    continue;
+                }
+                trail.push(neighbor_coord);
+                if trail.len() == 10 {
+                    state.0 += 1;
+                    state.1.entry(trail[0]).or_default().insert(neighbor_coord);
+                } else {
+                    evaluate(neighbor_coord, neighbor_val, trail, matrix, state);
+                }
+                trail.pop();
+            }
+        }
+
+        let mut state = (0, HashMap::new());
+        for row in matrix.row_range() {
+            for col in matrix.col_range() {
+                if matrix[row][col] == Some(0) {
+                    let coord = Coordinate::new(row as isize, col as isize);
+                    evaluate(coord, 0, &mut vec![coord], matrix, &mut state);
+                }
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn test_solve_random() {
+        let mut rng = XorShift::new(0x2545_f491_4f6c_dd1d);
+        let mut total_trails = 0;
+        for _ in 0..200 {
+            let n_rows = 1 + (rng.next_u64() % 12) as usize;
+            let n_cols = 1 + (rng.next_u64() % 12) as usize;
+            // Heights mostly increase along the rows and columns so that long
+            // trails actually occur, with some random and impassable cells to
+            // break them up.
+            let data = (0..n_rows)
+                .map(|row| {
+                    (0..n_cols)
+                        .map(|col| match rng.next_u64() % 6 {
+                            0 => None,
+                            1 => Some((rng.next_u64() % 10) as u8),
+                            _ => Some(((row + col) % 10) as u8),
+                        })
+                        .collect()
+                })
+                .collect();
+            let matrix = Matrix::new(data);
+            let state = solve(&matrix, &TrailRules::default()).expect("default rules are valid");
+            let (n_trails, reachable) = solve_recursive(&matrix);
+            assert_eq!(state.n_trails, n_trails, "{matrix:?}");
+            assert_eq!(state.reachable, reachable, "{matrix:?}");
+            assert_eq!(
+                state.trailheads,
+                reachable.keys().copied().collect::<HashSet<_>>()
+            );
+            total_trails += n_trails;
+        }
+        // Make sure the grids are not trivially free of trails.
+        assert!(total_trails > 0);
+    }
 }
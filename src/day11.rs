@@ -1,11 +1,12 @@
 use std::{
     collections::HashMap,
+    fmt::Display,
     ops::{Deref, DerefMut},
 };
 
 use nom::{bytes::complete::tag, error::Error, multi::separated_list1};
 
-use crate::util::{count_digits, hashmap_add_or_default};
+use crate::util::{count_digits_in_base, hashmap_add_or_default, Memo};
 
 #[derive(Debug, PartialEq)]
 pub struct Stones<T>(HashMap<T, usize>)
@@ -46,33 +47,243 @@ where
     pub fn count(&self) -> usize {
         self.values().sum()
     }
+
+    /// The number of stones with a value, 0 if absent.
+    pub fn get(&self, stone: &T) -> usize {
+        self.0.get(stone).copied().unwrap_or(0)
+    }
+
+    /// Add `n` stones with a value.
+    pub fn insert_n(&mut self, stone: T, n: usize) {
+        hashmap_add_or_default(&mut self.0, stone, n);
+    }
+
+    /// Add all stones of another collection.
+    pub fn merge(&mut self, other: Stones<T>) {
+        for (stone, count) in other.0 {
+            self.insert_n(stone, count);
+        }
+    }
+
+    /// The number of distinct stone values.
+    pub fn distinct(&self) -> usize {
+        self.len()
+    }
+
+    /// The `k` most common stone values and their counts, most common first.
+    /// Ties are broken by the smallest value.
+    pub fn most_common(&self, k: usize) -> Vec<(T, usize)>
+    where
+        T: Ord,
+    {
+        let mut counts: Vec<(T, usize)> =
+            self.iter().map(|(&stone, &count)| (stone, count)).collect();
+        counts.sort_by(|(stone_a, count_a), (stone_b, count_b)| {
+            count_b.cmp(count_a).then(stone_a.cmp(stone_b))
+        });
+        counts.truncate(k);
+        counts
+    }
+}
+
+/// A stone whose transformation does not fit in a u64.
+#[derive(Debug, PartialEq)]
+pub struct StoneOverflowError {
+    pub stone: u64,
+}
+
+impl Display for StoneOverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stone {} overflows when transformed", self.stone)
+    }
+}
+
+impl std::error::Error for StoneOverflowError {}
+
+/// A rule that transforms a single stone into one or two new stones when
+/// blinking.
+pub trait BlinkRule {
+    fn apply(&self, stone: u64) -> Result<(u64, Option<u64>), StoneOverflowError>;
+}
+
+/// The rules of the puzzle:
+/// - Value 0 becomes 1.
+/// - Even number of digits becomes 2 values with equally split digits,
+///   ignoring leading zeros.
+/// - Else, a value becomes 2024 x original.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultRules;
+
+impl BlinkRule for DefaultRules {
+    fn apply(&self, stone: u64) -> Result<(u64, Option<u64>), StoneOverflowError> {
+        BlinkConfig::default().apply(stone)
+    }
+}
+
+/// The constants of the puzzle rules: stones with an even number of digits in
+/// `base` are split, other non-zero stones are multiplied by `multiplier`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlinkConfig {
+    pub multiplier: u64,
+    pub base: u64,
+}
+
+impl Default for BlinkConfig {
+    fn default() -> Self {
+        BlinkConfig {
+            multiplier: 2024,
+            base: 10,
+        }
+    }
+}
+
+impl BlinkRule for BlinkConfig {
+    /// # Panics
+    /// If the base is smaller than 2.
+    fn apply(&self, stone: u64) -> Result<(u64, Option<u64>), StoneOverflowError> {
+        if stone == 0 {
+            return Ok((1, None));
+        }
+        let digits = count_digits_in_base(stone, self.base);
+        if digits.is_multiple_of(2) {
+            // The power has fewer digits than the stone, so this can not
+            // overflow.
+            let power = self.base.pow(digits / 2);
+            Ok((stone / power, Some(stone % power)))
+        } else {
+            let new_stone = stone
+                .checked_mul(self.multiplier)
+                .ok_or(StoneOverflowError { stone })?;
+            Ok((new_stone, None))
+        }
+    }
 }
 
 impl Stones<u64> {
-    pub fn take_step(&mut self) {
+    /// Blink once, transforming every stone at the same time.
+    ///
+    /// # Panics
+    /// If a stone overflows, see `try_step_once`.
+    pub fn step_once(&mut self) {
+        self.try_step_once().expect("stones should not overflow");
+    }
+
+    /// Blink once, transforming every stone at the same time. If any stone
+    /// overflows, the stones are left untouched.
+    pub fn try_step_once(&mut self) -> Result<(), StoneOverflowError> {
+        self.try_step_with(&DefaultRules)
+    }
+
+    /// Blink once, transforming every stone with custom rules.
+    ///
+    /// # Panics
+    /// If a stone overflows, see `try_step_with`.
+    pub fn step_with(&mut self, rules: &impl BlinkRule) {
+        self.try_step_with(rules)
+            .expect("stones should not overflow");
+    }
+
+    /// Blink once, transforming every stone with the given constants.
+    ///
+    /// # Panics
+    /// If a stone overflows or the base is smaller than 2.
+    pub fn take_step_with(&mut self, config: &BlinkConfig) {
+        self.step_with(config);
+    }
+
+    /// Blink once, transforming every stone with custom rules. If any stone
+    /// overflows, the stones are left untouched.
+    pub fn try_step_with(&mut self, rules: &impl BlinkRule) -> Result<(), StoneOverflowError> {
         let mut new_hashmap = HashMap::<u64, usize>::new();
         for (&stone, &count) in self.iter() {
-            if stone == 0 {
-                // new_hashmap.insert(1, count);
-                hashmap_add_or_default(&mut new_hashmap, 1, count);
-                continue;
-            }
-            let digits = count_digits(stone);
-            if digits % 2 == 0 {
-                let power = 10u64.pow(digits / 2);
-                // new_hashmap.insert(stone / power, count);
-                // new_hashmap.insert(stone % power, count);
-                hashmap_add_or_default(&mut new_hashmap, stone / power, count);
-                hashmap_add_or_default(&mut new_hashmap, stone % power, count);
-            } else {
-                // new_hashmap.insert(stone * 2024, count);
-                hashmap_add_or_default(&mut new_hashmap, stone * 2024, count);
+            let (first, second) = rules.apply(stone)?;
+            hashmap_add_or_default(&mut new_hashmap, first, count);
+            if let Some(second) = second {
+                hashmap_add_or_default(&mut new_hashmap, second, count);
             }
         }
         self.0 = new_hashmap;
+        Ok(())
+    }
+
+    /// Blink `n` times.
+    pub fn blink(&mut self, n: usize) {
+        for _ in 0..n {
+            self.step_once();
+        }
+    }
+
+    /// Blink `n` times. If any stone overflows, the stones are left as they
+    /// were after the last successful blink.
+    pub fn try_blink(&mut self, n: usize) -> Result<(), StoneOverflowError> {
+        for _ in 0..n {
+            self.try_step_once()?;
+        }
+        Ok(())
+    }
+
+    /// Count the number of stones after blinking `n` times, leaving these
+    /// stones untouched.
+    pub fn count_after(&self, n: usize) -> usize {
+        let mut stones = Stones(self.0.clone());
+        stones.blink(n);
+        stones.count()
+    }
+}
+
+/// Count the number of stones a single stone turns into after blinking
+/// `blinks` times. The cache maps a stone and a number of blinks to the number
+/// of stones and can be shared between queries.
+///
+/// # Panics
+/// If a stone overflows, see `Stones::try_step_once`.
+pub fn count_descendants(
+    stone: u64,
+    blinks: usize,
+    cache: &mut Memo<(u64, usize), usize>,
+) -> usize {
+    if blinks == 0 {
+        return 1;
+    }
+    cache.get_or_insert_with((stone, blinks), |cache| {
+        let (first, second) = DefaultRules
+            .apply(stone)
+            .expect("stones should not overflow");
+        count_descendants(first, blinks - 1, cache)
+            + second.map_or(0, |second| count_descendants(second, blinks - 1, cache))
+    })
+}
+
+/// Counts the descendants of single stones, remembering all intermediate
+/// results across queries.
+#[derive(Debug, Default)]
+pub struct DescendantCounter {
+    cache: Memo<(u64, usize), usize>,
+}
+
+impl DescendantCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the number of stones a single stone turns into after blinking
+    /// `blinks` times.
+    pub fn count(&mut self, stone: u64, blinks: usize) -> usize {
+        count_descendants(stone, blinks, &mut self.cache)
     }
 }
 
+/// The number of stones each initial stone turns into after blinking `blinks`
+/// times, in input order. The counts add up to the number of stones the whole
+/// input turns into. All stones share one cache.
+pub fn contributions(initial: &[u64], blinks: usize) -> Vec<(u64, usize)> {
+    let mut counter = DescendantCounter::new();
+    initial
+        .iter()
+        .map(|&stone| (stone, counter.count(stone, blinks)))
+        .collect()
+}
+
 pub fn parse_input(input: &str) -> Stones<u64> {
     let mut parser = separated_list1(tag(" "), nom::character::complete::u64::<&str, Error<_>>);
     let (_, output) = parser(input).expect("should be able to parse input");
@@ -85,25 +296,30 @@ pub fn parse_input(input: &str) -> Stones<u64> {
 ///   ignoring leading zeros.
 /// - Else, a value becomes 2024 x original.
 pub fn part_1(stones: &mut Stones<u64>) -> usize {
-    for _ in 0..25 {
-        stones.take_step();
-    }
+    stones.blink(25);
     stones.count()
 }
 
 /// For each number in the first vector calculate the value times the number of
 /// occurences in the second vector, and sum all these results.
 pub fn part_2(stones: &mut Stones<u64>) -> usize {
-    for _ in 0..75 {
-        stones.take_step();
-    }
+    stones.blink(75);
     stones.count()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_input, part_1, part_2};
-    use crate::{day11::Stones, util::read_file_to_string};
+    use std::collections::HashMap;
+
+    use super::{
+        contributions, count_descendants, parse_input, BlinkConfig, BlinkRule, DefaultRules,
+        DescendantCounter, StoneOverflowError,
+    };
+    use crate::{
+        day11::Stones,
+        testing::{golden_tests, XorShift},
+        util::Memo,
+    };
     const INPUT: &str = "125 17";
 
     #[test]
@@ -112,28 +328,230 @@ mod tests {
     }
 
     #[test]
-    fn test_part_1_small() {
-        assert_eq!(part_1(&mut parse_input(INPUT)), 55312)
+    fn test_blink() {
+        let mut stones = parse_input(INPUT);
+        stones.step_once();
+        assert_eq!(stones, Stones::new(&[253000, 1, 7]));
+        stones.blink(5);
+        assert_eq!(stones.count(), 22);
+        stones.blink(19);
+        assert_eq!(stones.count(), 55312);
+    }
+
+    #[test]
+    fn test_count_after() {
+        let stones = parse_input(INPUT);
+        assert_eq!(stones.count_after(6), 22);
+        assert_eq!(stones.count_after(25), 55312);
+        assert_eq!(stones, Stones::new(&[125, 17]));
     }
 
     #[test]
-    fn test_part_1_full() {
+    fn test_overflow() {
+        // The smallest stone with an odd number of digits that overflows.
+        let stone = 10u64.pow(16);
+        assert!(stone > u64::MAX / 2024);
+        let mut stones = Stones::new(&[stone, 0]);
+        assert_eq!(stones.try_step_once(), Err(StoneOverflowError { stone }));
+        assert_eq!(stones, Stones::new(&[stone, 0]));
+        // The largest stone that does not overflow.
+        let stone = 10u64.pow(15) - 1;
+        let mut stones = Stones::new(&[stone]);
+        assert_eq!(stones.try_step_once(), Ok(()));
+        assert_eq!(stones, Stones::new(&[stone * 2024]));
+    }
+
+    #[test]
+    fn test_try_blink() {
+        // The first blink yields a 17 digit stone, which overflows.
+        let mut stones = Stones::new(&[5 * 10u64.pow(12)]);
         assert_eq!(
-            part_1(&mut parse_input(&read_file_to_string("data/day11.txt"))),
-            193899
+            stones.try_blink(2),
+            Err(StoneOverflowError {
+                stone: 5 * 10u64.pow(12) * 2024
+            })
         );
+        assert_eq!(stones, Stones::new(&[5 * 10u64.pow(12) * 2024]));
+        let mut stones = parse_input(INPUT);
+        assert_eq!(stones.try_blink(25), Ok(()));
+        assert_eq!(stones.count(), 55312);
     }
 
     #[test]
-    fn test_part_2_small() {
-        assert_eq!(part_2(&mut parse_input(INPUT)), 65601038650482)
+    #[should_panic]
+    fn test_step_once_overflow() {
+        Stones::new(&[10u64.pow(16)]).step_once();
+    }
+
+    #[test]
+    fn test_count_descendants() {
+        let mut cache = Memo::new();
+        for (blinks, expected) in [(6, 22), (25, 55312), (75, 65601038650482)] {
+            assert_eq!(
+                count_descendants(125, blinks, &mut cache)
+                    + count_descendants(17, blinks, &mut cache),
+                expected
+            );
+        }
+        assert_eq!(count_descendants(0, 0, &mut cache), 1);
+    }
+
+    #[test]
+    fn test_contributions() {
+        assert_eq!(
+            contributions(&[125, 17], 25),
+            vec![(125, 19025), (17, 36287)]
+        );
+        let stones = [125, 17, 125];
+        for blinks in [0, 6, 25, 75] {
+            let counts = contributions(&stones, blinks);
+            assert_eq!(counts[0], counts[2]);
+            assert_eq!(
+                counts.iter().map(|(_, count)| count).sum::<usize>(),
+                Stones::new(&stones).count_after(blinks)
+            );
+        }
+    }
+
+    #[test]
+    fn test_descendant_counter() {
+        let mut counter = DescendantCounter::new();
+        let mut rng = XorShift::new(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20 {
+            let stone = rng.next_u64() % 1_000_000;
+            for blinks in [0, 1, 10, 30] {
+                assert_eq!(
+                    counter.count(stone, blinks),
+                    Stones::new(&[stone]).count_after(blinks),
+                    "{stone} after {blinks}"
+                );
+            }
+        }
+    }
+
+    /// Every stone becomes two copies of itself.
+    struct Duplicate;
+
+    impl BlinkRule for Duplicate {
+        fn apply(&self, stone: u64) -> Result<(u64, Option<u64>), StoneOverflowError> {
+            Ok((stone, Some(stone)))
+        }
+    }
+
+    /// Every stone is multiplied by 3.
+    struct Triple;
+
+    impl BlinkRule for Triple {
+        fn apply(&self, stone: u64) -> Result<(u64, Option<u64>), StoneOverflowError> {
+            Ok((
+                stone.checked_mul(3).ok_or(StoneOverflowError { stone })?,
+                None,
+            ))
+        }
     }
 
     #[test]
-    fn test_part_2_full() {
+    fn test_step_with() {
+        let mut stones = parse_input(INPUT);
+        for n in 1..=40 {
+            stones.step_with(&Duplicate);
+            assert_eq!(stones.count(), 2 << n);
+        }
         assert_eq!(
-            part_2(&mut parse_input(&read_file_to_string("data/day11.txt"))),
-            229682160383225
-        )
+            stones,
+            Stones(HashMap::from([(125, 1 << 40), (17, 1 << 40)]))
+        );
+
+        let mut stones = Stones::new(&[1]);
+        stones.step_with(&Triple);
+        stones.step_with(&Triple);
+        assert_eq!(stones, Stones::new(&[9]));
+        let mut stones = Stones::new(&[u64::MAX]);
+        assert_eq!(
+            stones.try_step_with(&Triple),
+            Err(StoneOverflowError { stone: u64::MAX })
+        );
+    }
+
+    #[test]
+    fn test_get_insert_n() {
+        let mut stones = parse_input(INPUT);
+        assert_eq!(stones.get(&125), 1);
+        assert_eq!(stones.get(&0), 0);
+        stones.insert_n(125, 3);
+        stones.insert_n(0, 2);
+        assert_eq!(stones.get(&125), 4);
+        assert_eq!(stones.get(&0), 2);
+        assert_eq!(stones.count(), 7);
+        assert_eq!(stones.distinct(), 3);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut stones = parse_input(INPUT);
+        stones.blink(25);
+        let mut left = Stones::new(&[125]);
+        left.blink(25);
+        let mut right = Stones::new(&[17]);
+        right.blink(25);
+        left.merge(right);
+        assert_eq!(left, stones);
+    }
+
+    #[test]
+    fn test_distribution() {
+        let mut stones = parse_input(INPUT);
+        stones.blink(25);
+        assert_eq!(stones.distinct(), 54);
+        let most_common = stones.most_common(3);
+        assert_eq!(most_common, vec![(4, 3204), (8, 3146), (0, 2952)]);
+        assert!(most_common
+            .iter()
+            .all(|(stone, count)| stones.get(stone) == *count));
+        assert_eq!(stones.most_common(usize::MAX).len(), stones.distinct());
+    }
+
+    #[test]
+    fn test_blink_config_default() {
+        let mut stones = parse_input(INPUT);
+        let config = BlinkConfig::default();
+        for _ in 0..25 {
+            stones.take_step_with(&config);
+        }
+        assert_eq!(stones.count(), 55312);
+        for stone in [0, 1, 9, 10, 99, 1000, 123456, 10u64.pow(15) - 1] {
+            assert_eq!(config.apply(stone), DefaultRules.apply(stone));
+        }
+    }
+
+    #[test]
+    fn test_blink_config_base_2() {
+        let config = BlinkConfig {
+            multiplier: 3,
+            base: 2,
+        };
+        // 0b101 has 3 digits and is multiplied.
+        let mut stones = Stones::new(&[0b101]);
+        stones.take_step_with(&config);
+        assert_eq!(stones, Stones::new(&[0b1111]));
+        // 0b1111 has 4 digits and is split in 0b11 and 0b11.
+        stones.take_step_with(&config);
+        assert_eq!(stones, Stones::new(&[0b11, 0b11]));
+        stones.take_step_with(&config);
+        assert_eq!(stones, Stones::new(&[0b1; 4]));
+        stones.take_step_with(&config);
+        assert_eq!(stones, Stones::new(&[0b11; 4]));
+        // 0b1000 has 4 digits and is split in 0b10 and 0b00.
+        let mut stones = Stones::new(&[0b1000]);
+        stones.take_step_with(&config);
+        assert_eq!(stones, Stones::new(&[0b10, 0]));
+    }
+
+    golden_tests! {
+        crate::day11,
+        part_1: |input| part_1(&mut parse_input(input)),
+        part_2: |input| part_2(&mut parse_input(input)),
+        examples: [(INPUT, 55312, 65601038650482)],
+        full: ("data/day11.txt", 193899, 229682160383225),
     }
 }
@@ -1,6 +1,6 @@
-use std::{fmt::Debug, vec};
+use std::{collections::HashSet, fmt::Debug, vec};
 
-use crate::util::{Coordinate, Matrix};
+use crate::util::{connected_components, Connectivity, Coordinate, Matrix, Rect};
 
 type Coord = [usize; 2];
 
@@ -37,12 +37,23 @@ fn get_n_equal_neighbors<T: PartialEq>(coord: Coord, matrix: &Matrix<T>) -> Opti
     })
 }
 
-/// Segment an image into regions of identical value,
-/// connected in the 4 cardinal directions.
+/// The segmentation of an image into regions of identical value.
+#[derive(Debug, PartialEq)]
+pub struct Watershed<T> {
+    /// The label of the region of every cell. Labels are assigned in reading
+    /// order of the first cell of each region, starting from 0.
+    pub labels: Matrix<usize>,
+    pub n_regions: usize,
+    /// The value of the cells of every region, indexed by label.
+    pub values: Vec<T>,
+}
+
+/// Segment an image into regions of identical value, connected in the 4
+/// cardinal directions or also diagonally.
 /// # Example usage
 /// ```rust
 /// use advent_of_code_2024::day12::watershed;
-/// use advent_of_code_2024::util::Matrix;
+/// use advent_of_code_2024::util::{Connectivity, Matrix};
 /// let matrix = Matrix::new(vec![
 ///     vec!['A', 'A', 'A', 'A'],
 ///     vec!['B', 'B', 'C', 'D'],
@@ -55,257 +66,256 @@ fn get_n_equal_neighbors<T: PartialEq>(coord: Coord, matrix: &Matrix<T>) -> Opti
 ///     vec![1, 1, 2, 2],
 ///     vec![4, 4, 4, 2],
 /// ]);
-/// assert_eq!(watershed(&matrix), expected)
+/// let watershed = watershed(&matrix, Connectivity::Four);
+/// assert_eq!(watershed.labels, expected);
+/// assert_eq!(watershed.n_regions, 5);
+/// assert_eq!(watershed.values, vec!['A', 'B', 'C', 'D', 'E']);
 /// ```
-pub fn watershed<T: PartialEq>(matrix: &Matrix<T>) -> Matrix<usize> {
-    let mut output = Matrix::new_like(matrix, 0usize);
-    let mut counter = 0usize;
-    let mut visited = Matrix::new(vec![vec![false; matrix.shape()[1]]; matrix.shape()[0]]);
+pub fn watershed<T: PartialEq + Clone>(
+    matrix: &Matrix<T>,
+    connectivity: Connectivity,
+) -> Watershed<T> {
+    let labels = connected_components(matrix, connectivity);
+    let mut values = Vec::new();
     for row in matrix.row_range() {
         for col in matrix.col_range() {
-            if visited[row][col] {
-                continue;
-            }
-            let mut queue = vec![Coordinate::new(row as isize, col as isize)];
-            while let Some(coord) = queue.pop() {
-                let [row, col] = [coord.r as usize, coord.c as usize];
-                if visited[row][col] {
-                    continue;
-                }
-                let neighbors = get_cardinal_neighbors(coord, matrix);
-                if !neighbors.is_empty() {
-                    visited[row][col] = true;
-                    queue.extend(neighbors);
-                }
-                output[row][col] = counter;
-            }
-            counter += 1;
-        }
-    }
-    output
-}
-
-fn get_cardinal_neighbors<T: PartialEq>(coord: Coordinate, matrix: &Matrix<T>) -> Vec<Coordinate> {
-    let [row, col] = [coord.r as usize, coord.c as usize];
-    let mut vector = vec![];
-    for neighbor in coord.cardinals() {
-        if !neighbor.r.is_negative() && !neighbor.c.is_negative() {
-            let [neighbor_row, neighbor_col] = [neighbor.r as usize, neighbor.c as usize];
-            if let Some(n) = matrix.get_element([neighbor_row, neighbor_col]) {
-                if n == &(matrix[row][col]) {
-                    vector.push(neighbor);
-                }
+            // Labels are assigned in reading order, so an unseen label is
+            // always the next one.
+            if labels[row][col] == values.len() {
+                values.push(matrix[row][col].clone());
             }
         }
     }
-    vector
-}
-fn get_diagonal_neighbors<T: PartialEq>(coord: Coordinate, matrix: &Matrix<T>) -> Vec<Coordinate> {
-    let [row, col] = [coord.r as usize, coord.c as usize];
-    let mut vector = vec![];
-    for neighbor in coord.diagonals() {
-        if !neighbor.r.is_negative() && !neighbor.c.is_negative() {
-            let [neighbor_row, neighbor_col] = [neighbor.r as usize, neighbor.c as usize];
-            if let Some(n) = matrix.get_element([neighbor_row, neighbor_col]) {
-                if n == &(matrix[row][col]) {
-                    vector.push(neighbor);
-                }
-            }
-        }
+    Watershed {
+        labels,
+        n_regions: values.len(),
+        values,
     }
-    vector
 }
 
-#[derive(Debug)]
-struct RegionCircumference {
+/// A connected region of identical values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region<T> {
+    /// The label of the region in the watershed.
+    pub id: usize,
+    pub value: T,
+    /// The cells of the region, in reading order.
+    pub cells: Vec<Coordinate>,
     pub area: usize,
-    pub circumference: usize,
+    pub perimeter: usize,
+    /// The number of straight fence sections, which equals the number of
+    /// corners.
+    pub sides: usize,
+    pub bounding_box: Rect,
 }
 
-/// Track the area and circumference of each connected region of space.
-/// Calculate the sum of all products area x circumference.
-pub fn part_1(matrix: &Matrix<char>) -> usize {
-    let mut regions = <Vec<RegionCircumference>>::new();
-    let watershed = watershed(matrix);
-    for row in matrix.row_range() {
-        for col in 0..matrix.shape()[1] {
-            let circumference = 4 - get_n_equal_neighbors([row, col], &watershed).unwrap();
-            let idx = watershed[row][col];
-            if idx == regions.len() {
-                regions.push(RegionCircumference {
-                    area: 1,
-                    circumference,
-                });
+/// Find all connected regions of identical values, ordered by their label in
+/// the watershed.
+pub fn regions<T: PartialEq + Clone>(matrix: &Matrix<T>) -> Vec<Region<T>> {
+    regions_from_watershed(&watershed(matrix, Connectivity::Four))
+}
+
+/// Find all connected regions of identical values, given a 4-connected
+/// watershed.
+pub fn regions_from_watershed<T: Clone>(watershed: &Watershed<T>) -> Vec<Region<T>> {
+    let labels = &watershed.labels;
+    let corners = count_corners_windowed(labels);
+    let mut regions: Vec<Region<T>> = watershed
+        .values
+        .iter()
+        .enumerate()
+        .map(|(id, value)| Region {
+            id,
+            value: value.clone(),
+            cells: Vec::new(),
+            area: 0,
+            perimeter: 0,
+            sides: corners[id],
+            bounding_box: Rect::default(),
+        })
+        .collect();
+    for row in labels.row_range() {
+        for col in labels.col_range() {
+            let coord = Coordinate::new(row as isize, col as isize);
+            let region = &mut regions[labels[row][col]];
+            if region.cells.is_empty() {
+                region.bounding_box = Rect::from(coord);
             } else {
-                regions[idx].area += 1;
-                regions[idx].circumference += circumference;
+                region.bounding_box.extend(coord);
             }
+            region.cells.push(coord);
+            region.area += 1;
+            region.perimeter += 4 - get_n_equal_neighbors([row, col], labels).unwrap();
         }
     }
-    regions.iter().fold(0, |coord, region| {
-        coord + region.area * region.circumference
-    })
+    regions
 }
 
-#[derive(Debug)]
-pub struct RegionCorners {
-    area: usize,
-    n_corners: usize,
+/// Whether a coordinate is inside the grid and has a label.
+fn has_label(labels: &Matrix<usize>, coord: Coordinate, id: usize) -> bool {
+    !coord.r.is_negative()
+        && !coord.c.is_negative()
+        && labels.get_element([coord.r as usize, coord.c as usize]) == Some(&id)
 }
 
-fn added_corners<T: PartialEq>(coord: Coordinate, matrix: &Matrix<T>) -> usize {
-    let cardinals = get_cardinal_neighbors(coord, matrix);
-    let diagonals = get_diagonal_neighbors(coord, matrix);
-    match cardinals.len() {
-        0 => 4,
-        1 => 2,
-        2 => {
-            // Neighbors are returned in cardinal order NESW.
-            if cardinals == vec![coord.north(), coord.south()]
-                || cardinals == vec![coord.east(), coord.west()]
+/// Find the cells of a region that have at least one cardinal neighbor outside
+/// of the region or on the edge of the grid. This includes the cells bordering
+/// holes in the region.
+pub fn region_boundary(labels: &Matrix<usize>, id: usize) -> HashSet<Coordinate> {
+    let mut boundary = HashSet::new();
+    for row in labels.row_range() {
+        for col in labels.col_range() {
+            let coord = Coordinate::new(row as isize, col as isize);
+            if labels[row][col] == id
+                && coord
+                    .cardinals()
+                    .iter()
+                    .any(|neighbor| !has_label(labels, *neighbor, id))
             {
-                0
-            } else {
-                check_corners_for_2_cardinals(
-                    [coord.north(), coord.east()],
-                    &coord.north_east(),
-                    &cardinals,
-                    &diagonals,
-                )
-                .or_else(|| {
-                    check_corners_for_2_cardinals(
-                        [coord.east(), coord.south()],
-                        &coord.south_east(),
-                        &cardinals,
-                        &diagonals,
-                    )
-                })
-                .or_else(|| {
-                    check_corners_for_2_cardinals(
-                        [coord.south(), coord.west()],
-                        &coord.south_west(),
-                        &cardinals,
-                        &diagonals,
-                    )
-                })
-                .or_else(|| {
-                    check_corners_for_2_cardinals(
-                        [coord.north(), coord.west()],
-                        &coord.north_west(),
-                        &cardinals,
-                        &diagonals,
-                    )
-                })
-                .unwrap()
+                boundary.insert(coord);
             }
         }
-        3 => check_corners_for_3_cardinals(
-            [coord.north(), coord.east(), coord.south()],
-            [coord.north_east(), coord.south_east()],
-            &cardinals,
-            &diagonals,
-        )
-        .or_else(|| {
-            check_corners_for_3_cardinals(
-                [coord.east(), coord.south(), coord.west()],
-                [coord.south_east(), coord.south_west()],
-                &cardinals,
-                &diagonals,
-            )
-        })
-        .or_else(|| {
-            check_corners_for_3_cardinals(
-                [coord.north(), coord.south(), coord.west()],
-                [coord.south_west(), coord.north_west()],
-                &cardinals,
-                &diagonals,
-            )
-        })
-        .or_else(|| {
-            check_corners_for_3_cardinals(
-                [coord.north(), coord.east(), coord.west()],
-                [coord.north_west(), coord.north_east()],
-                &cardinals,
-                &diagonals,
-            )
-        })
-        .unwrap(),
-        4 => check_corners_for_4_cardinals(&diagonals),
-        _ => unreachable!(),
     }
+    boundary
 }
 
-fn check_corners_for_2_cardinals(
-    cardinal: [Coordinate; 2],
-    diagonal: &Coordinate,
-    cardinals: &[Coordinate],
-    diagonals: &[Coordinate],
-) -> Option<usize> {
-    if cardinals == cardinal {
-        if diagonals.contains(diagonal) {
-            return Some(1);
+/// Trace the outer contour of a region clockwise, using Moore neighbor tracing
+/// starting from its first cell in reading order. Consecutive cells, including
+/// the last and the first, are neighbors in one of 8 directions, and cells are
+/// repeated where the region is only one cell wide. Holes in the region are
+/// ignored, their borders are only part of the `region_boundary`.
+pub fn region_outline(labels: &Matrix<usize>, id: usize) -> Vec<Coordinate> {
+    let Some(start) = labels
+        .row_range()
+        .flat_map(|row| labels.col_range().map(move |col| [row, col]))
+        .find(|[row, col]| labels[*row][*col] == id)
+        .map(|[row, col]| Coordinate::new(row as isize, col as isize))
+    else {
+        return Vec::new();
+    };
+    // The neighbors of a cell in clockwise order, starting from the north.
+    let offsets = Coordinate::new(0, 0).neighbors();
+    let mut outline = vec![start];
+    // The first cell in reading order can never have a western neighbor in the
+    // region.
+    let (mut current, mut backtrack) = (start, start.west());
+    loop {
+        let i_backtrack = offsets
+            .iter()
+            .position(|offset| current + *offset == backtrack)
+            .expect("backtrack is a neighbor");
+        // Search clockwise from the last cell outside of the region.
+        let Some(i_next) = (1..=8)
+            .map(|k| (i_backtrack + k) % 8)
+            .find(|i| has_label(labels, current + offsets[*i], id))
+        else {
+            // A region of a single cell.
+            break;
+        };
+        let next = current + offsets[i_next];
+        // Stop when leaving the start in the same way as the first time.
+        if current == start && outline.len() > 1 && next == outline[1] {
+            outline.pop();
+            break;
         }
-        return Some(2);
+        backtrack = current + offsets[(i_next + 7) % 8];
+        current = next;
+        outline.push(next);
     }
-    None
+    outline
+}
+
+/// Compute the answers to both parts, segmenting the matrix only once.
+pub fn solve(matrix: &Matrix<char>) -> (usize, usize) {
+    solve_with_watershed(&watershed(matrix, Connectivity::Four))
+}
+
+/// Compute the answers to both parts, given a 4-connected watershed.
+pub fn solve_with_watershed(watershed: &Watershed<char>) -> (usize, usize) {
+    regions_from_watershed(watershed)
+        .iter()
+        .fold((0, 0), |(price_1, price_2), region| {
+            (
+                price_1 + region.area * region.perimeter,
+                price_2 + region.area * region.sides,
+            )
+        })
 }
 
-fn check_corners_for_3_cardinals(
-    cardinal: [Coordinate; 3],
-    diagonal: [Coordinate; 2],
-    cardinals: &[Coordinate],
-    diagonals: &[Coordinate],
-) -> Option<usize> {
-    if cardinal != cardinals {
-        return None;
+/// Count the corners of every region by sliding a 2x2 window over the labels,
+/// including the edges of the grid. Within a window, a region has a corner if
+/// it has exactly 1 or 3 of its cells, or 2 corners if it has exactly 2
+/// diagonally opposite cells. The number of corners equals the number of
+/// sides.
+pub fn count_corners_windowed(labels: &Matrix<usize>) -> Vec<usize> {
+    let n_regions = labels.iter().flatten().max().map_or(0, |max| max + 1);
+    let mut corners = vec![0; n_regions];
+    let [n_rows, n_cols] = labels.shape();
+    let label = |row: usize, col: usize| -> Option<usize> {
+        // The window is offset by 1 to also cover the edges of the grid.
+        let [row, col] = [row.checked_sub(1)?, col.checked_sub(1)?];
+        labels.get_element([row, col]).copied()
+    };
+    for row in 0..=n_rows {
+        for col in 0..=n_cols {
+            // In reading order, so the diagonals are 0-3 and 1-2.
+            let window = [
+                label(row, col),
+                label(row, col + 1),
+                label(row + 1, col),
+                label(row + 1, col + 1),
+            ];
+            for (i, id) in window.iter().enumerate() {
+                let Some(id) = id else {
+                    continue;
+                };
+                // Only count every region once per window.
+                if window[..i].contains(&Some(*id)) {
+                    continue;
+                }
+                let inside = window.map(|other| other == Some(*id));
+                corners[*id] += match inside.iter().filter(|inside| **inside).count() {
+                    1 | 3 => 1,
+                    2 if inside[0] == inside[3] => 2,
+                    _ => 0,
+                };
+            }
+        }
     }
-    Some(
-        2usize.saturating_sub(
-            diagonal
-                .iter()
-                .map(|diag| diagonals.contains(diag) as usize)
-                .sum(),
-        ),
-    )
+    corners
+}
+
+/// Calculate the sum of the prices of all regions, for any pricing function.
+pub fn total_price<T: PartialEq + Clone>(
+    matrix: &Matrix<T>,
+    pricer: impl Fn(&Region<T>) -> usize,
+) -> usize {
+    regions(matrix).iter().map(pricer).sum()
 }
 
-fn check_corners_for_4_cardinals(diagonals: &[Coordinate]) -> usize {
-    4usize.saturating_sub(diagonals.len())
+/// Track the area and circumference of each connected region of space.
+/// Calculate the sum of all products area x circumference.
+pub fn part_1(matrix: &Matrix<char>) -> usize {
+    total_price(matrix, |region| region.area * region.perimeter)
 }
 
 /// Track the area and number of sides of each connected region of space.
 /// Calculate the sum of all products area x n_sides.
 pub fn part_2(matrix: &Matrix<char>) -> usize {
-    let mut regions = <Vec<RegionCorners>>::new();
-    let watershed = watershed(matrix);
-    for row in matrix.row_range() {
-        for col in 0..matrix.shape()[1] {
-            let n_corners = added_corners(
-                Coordinate {
-                    r: row as isize,
-                    c: col as isize,
-                },
-                matrix,
-            );
-            let idx = watershed[row][col];
-            if idx == regions.len() {
-                regions.push(RegionCorners { area: 1, n_corners });
-            } else {
-                regions[idx].area += 1;
-                regions[idx].n_corners += n_corners;
-            }
-        }
-    }
-    regions
-        .iter()
-        .fold(0, |coord, region| coord + region.area * region.n_corners)
+    total_price(matrix, |region| region.area * region.sides)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use crate::{
-        day12::{get_n_equal_neighbors, parse_input, part_1, part_2, watershed},
-        util::{read_file_to_string, Matrix},
+        day12::{
+            count_corners_windowed, get_n_equal_neighbors, parse_input, part_1, part_2,
+            region_boundary, region_outline, regions, solve, solve_with_watershed, total_price,
+            watershed, Region,
+        },
+        util::{read_file_to_string, Connectivity, Coordinate, Matrix, Rect},
     };
 
     const INPUT: &str = "AAAA\nBBCD\nBBCC\nEEEC";
@@ -363,7 +373,401 @@ mod tests {
             vec![1, 1, 2, 2],
             vec![4, 4, 4, 2],
         ]);
-        assert_eq!(watershed(&matrix), expected)
+        assert_eq!(watershed(&matrix, Connectivity::Four).labels, expected)
+    }
+
+    #[test]
+    fn test_regions() {
+        let regions = regions(&parse_input(INPUT));
+        assert_eq!(regions.len(), 5);
+        assert_eq!(
+            regions[2],
+            Region {
+                id: 2,
+                value: 'C',
+                cells: vec![
+                    Coordinate::new(1, 2),
+                    Coordinate::new(2, 2),
+                    Coordinate::new(2, 3),
+                    Coordinate::new(3, 3)
+                ],
+                area: 4,
+                perimeter: 10,
+                sides: 8,
+                bounding_box: Rect::new(Coordinate::new(1, 2), Coordinate::new(3, 3)),
+            }
+        );
+        let summary: Vec<_> = regions
+            .iter()
+            .map(|region| (region.value, region.area, region.perimeter, region.sides))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ('A', 4, 10, 4),
+                ('B', 4, 8, 4),
+                ('C', 4, 10, 8),
+                ('D', 1, 4, 4),
+                ('E', 3, 8, 4)
+            ]
+        );
+        assert_eq!(regions[4].bounding_box.area(), 3);
+    }
+
+    #[test]
+    fn test_watershed_regions() {
+        let watershed = watershed(&parse_input(INPUT), Connectivity::Four);
+        assert_eq!(watershed.n_regions, 5);
+        assert_eq!(watershed.values, vec!['A', 'B', 'C', 'D', 'E']);
+        // The same value can belong to multiple regions.
+        let watershed = super::watershed(&parse_input("AAB\nAAB\nBBA"), Connectivity::Four);
+        assert_eq!(watershed.n_regions, 4);
+        assert_eq!(watershed.values, vec!['A', 'B', 'B', 'A']);
+        // Labels are in reading order of the first cell of each region.
+        let mut seen = 0;
+        for label in watershed.labels.iter().flatten() {
+            assert!(*label <= seen);
+            if *label == seen {
+                seen += 1;
+            }
+        }
+        assert_eq!(seen, watershed.n_regions);
+    }
+
+    #[test]
+    fn test_watershed_connectivity() {
+        // The X cells are 2 cells apart, so they are separate regions under
+        // both connectivities, while the O cells form a single ring.
+        let matrix = parse_input("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO");
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            let labels = watershed(&matrix, connectivity).labels;
+            assert_eq!(labels[0][0], 0);
+            assert_eq!(
+                [labels[1][1], labels[1][3], labels[3][1], labels[3][3]],
+                [1, 2, 3, 4]
+            );
+        }
+        // Moving the X cells to touch diagonally merges them under
+        // 8-connectivity only.
+        let matrix = parse_input("OOOOO\nOXOOO\nOOXOO\nOXOXO\nOOOOO");
+        let labels = watershed(&matrix, Connectivity::Four).labels;
+        assert_eq!(
+            [labels[1][1], labels[2][2], labels[3][1], labels[3][3]],
+            [1, 2, 3, 4]
+        );
+        let labels = watershed(&matrix, Connectivity::Eight).labels;
+        assert_eq!(
+            [labels[1][1], labels[2][2], labels[3][1], labels[3][3]],
+            [1, 1, 1, 1]
+        );
+        assert!(labels.iter().flatten().all(|&label| label <= 1));
+    }
+
+    /// Check that consecutive cells of an outline, including the last and the
+    /// first, are neighbors.
+    fn assert_closed(outline: &[Coordinate]) {
+        for (i, coord) in outline.iter().enumerate() {
+            let next = outline[(i + 1) % outline.len()];
+            assert!(coord.neighbors().contains(&next), "{coord:?} {next:?}");
+        }
+    }
+
+    #[test]
+    fn test_region_boundary_outline() {
+        let labels = watershed(&parse_input(INPUT), Connectivity::Four).labels;
+        // The C region bends, so all of its cells are on the boundary.
+        let boundary = region_boundary(&labels, 2);
+        assert_eq!(boundary.len(), 4);
+        let outline = region_outline(&labels, 2);
+        assert_eq!(
+            outline,
+            vec![
+                Coordinate::new(1, 2),
+                Coordinate::new(2, 3),
+                Coordinate::new(3, 3),
+                Coordinate::new(2, 2)
+            ]
+        );
+        assert_closed(&outline);
+        assert_eq!(outline.iter().copied().collect::<HashSet<_>>(), boundary);
+        // The A region is a single row, which is traced there and back.
+        let outline = region_outline(&labels, 0);
+        assert_eq!(outline.len(), 6);
+        assert_closed(&outline);
+        // The D region is a single cell.
+        assert_eq!(region_outline(&labels, 3), vec![Coordinate::new(1, 3)]);
+        // An unknown region.
+        assert!(region_outline(&labels, 5).is_empty());
+        assert!(region_boundary(&labels, 5).is_empty());
+    }
+
+    #[test]
+    fn test_region_outline_holes() {
+        let labels = watershed(
+            &parse_input("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO"),
+            Connectivity::Four,
+        )
+        .labels;
+        // The edge of the grid and the cells around the 4 holes.
+        assert_eq!(region_boundary(&labels, 0).len(), 16 + 4);
+        // Only the outer edge of the grid, clockwise.
+        let outline = region_outline(&labels, 0);
+        assert_eq!(outline.len(), 16);
+        assert_eq!(outline[1], Coordinate::new(0, 1));
+        assert_eq!(outline[15], Coordinate::new(1, 0));
+        assert_closed(&outline);
+        assert!(outline
+            .iter()
+            .all(|coord| coord.r == 0 || coord.r == 4 || coord.c == 0 || coord.c == 4));
+    }
+
+    #[test]
+    fn test_solve() {
+        for (input, expected) in [
+            (INPUT, (140, 80)),
+            (INPUT_LARGE, (1930, 1206)),
+            (&read_file_to_string("data/day12.txt"), (1434856, 891106)),
+        ] {
+            let matrix = parse_input(input);
+            assert_eq!(solve(&matrix), expected);
+            // The watershed is an explicit input, so it is only computed once.
+            let watershed = watershed(&matrix, Connectivity::Four);
+            assert_eq!(solve_with_watershed(&watershed), expected);
+        }
+    }
+
+    #[test]
+    fn test_diagonal_contact() {
+        // The two A regions and the two B regions touch diagonally.
+        let matrix = parse_input("AAB\nAAB\nBBA");
+        let summary: Vec<_> = regions(&matrix)
+            .iter()
+            .map(|region| (region.value, region.area, region.perimeter, region.sides))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ('A', 4, 8, 4),
+                ('B', 2, 6, 4),
+                ('B', 2, 6, 4),
+                ('A', 1, 4, 4)
+            ]
+        );
+        assert_eq!(part_1(&matrix), 4 * 8 + 2 * 6 + 2 * 6 + 4);
+        assert_eq!(part_2(&matrix), 4 * 4 + 2 * 4 + 2 * 4 + 4);
+    }
+
+    // The original per-cell corner counting, kept to validate
+    // `count_corners_windowed`.
+
+    fn get_cardinal_neighbors<T: PartialEq>(
+        coord: Coordinate,
+        matrix: &Matrix<T>,
+    ) -> Vec<Coordinate> {
+        let [row, col] = [coord.r as usize, coord.c as usize];
+        let mut vector = vec![];
+        for neighbor in coord.cardinals() {
+            if !neighbor.r.is_negative() && !neighbor.c.is_negative() {
+                let [neighbor_row, neighbor_col] = [neighbor.r as usize, neighbor.c as usize];
+                if let Some(n) = matrix.get_element([neighbor_row, neighbor_col]) {
+                    if n == &(matrix[row][col]) {
+                        vector.push(neighbor);
+                    }
+                }
+            }
+        }
+        vector
+    }
+    fn get_diagonal_neighbors<T: PartialEq>(
+        coord: Coordinate,
+        matrix: &Matrix<T>,
+    ) -> Vec<Coordinate> {
+        let [row, col] = [coord.r as usize, coord.c as usize];
+        let mut vector = vec![];
+        for neighbor in coord.diagonals() {
+            if !neighbor.r.is_negative() && !neighbor.c.is_negative() {
+                let [neighbor_row, neighbor_col] = [neighbor.r as usize, neighbor.c as usize];
+                if let Some(n) = matrix.get_element([neighbor_row, neighbor_col]) {
+                    if n == &(matrix[row][col]) {
+                        vector.push(neighbor);
+                    }
+                }
+            }
+        }
+        vector
+    }
+
+    /// Count the corners of the region of a cell that are located at that cell.
+    /// The cells are compared by their watershed label rather than their value, so
+    /// that regions with the same value never affect each other.
+    fn added_corners(coord: Coordinate, labels: &Matrix<usize>) -> usize {
+        let cardinals = get_cardinal_neighbors(coord, labels);
+        let diagonals = get_diagonal_neighbors(coord, labels);
+        match cardinals.len() {
+            0 => 4,
+            1 => 2,
+            2 => {
+                // Neighbors are returned in cardinal order NESW.
+                if cardinals == vec![coord.north(), coord.south()]
+                    || cardinals == vec![coord.east(), coord.west()]
+                {
+                    0
+                } else {
+                    check_corners_for_2_cardinals(
+                        [coord.north(), coord.east()],
+                        &coord.north_east(),
+                        &cardinals,
+                        &diagonals,
+                    )
+                    .or_else(|| {
+                        check_corners_for_2_cardinals(
+                            [coord.east(), coord.south()],
+                            &coord.south_east(),
+                            &cardinals,
+                            &diagonals,
+                        )
+                    })
+                    .or_else(|| {
+                        check_corners_for_2_cardinals(
+                            [coord.south(), coord.west()],
+                            &coord.south_west(),
+                            &cardinals,
+                            &diagonals,
+                        )
+                    })
+                    .or_else(|| {
+                        check_corners_for_2_cardinals(
+                            [coord.north(), coord.west()],
+                            &coord.north_west(),
+                            &cardinals,
+                            &diagonals,
+                        )
+                    })
+                    .unwrap()
+                }
+            }
+            3 => check_corners_for_3_cardinals(
+                [coord.north(), coord.east(), coord.south()],
+                [coord.north_east(), coord.south_east()],
+                &cardinals,
+                &diagonals,
+            )
+            .or_else(|| {
+                check_corners_for_3_cardinals(
+                    [coord.east(), coord.south(), coord.west()],
+                    [coord.south_east(), coord.south_west()],
+                    &cardinals,
+                    &diagonals,
+                )
+            })
+            .or_else(|| {
+                check_corners_for_3_cardinals(
+                    [coord.north(), coord.south(), coord.west()],
+                    [coord.south_west(), coord.north_west()],
+                    &cardinals,
+                    &diagonals,
+                )
+            })
+            .or_else(|| {
+                check_corners_for_3_cardinals(
+                    [coord.north(), coord.east(), coord.west()],
+                    [coord.north_west(), coord.north_east()],
+                    &cardinals,
+                    &diagonals,
+                )
+            })
+            .unwrap(),
+            4 => check_corners_for_4_cardinals(&diagonals),
+            _ => unreachable!(),
+        }
+    }
+
+    fn check_corners_for_2_cardinals(
+        cardinal: [Coordinate; 2],
+        diagonal: &Coordinate,
+        cardinals: &[Coordinate],
+        diagonals: &[Coordinate],
+    ) -> Option<usize> {
+        if cardinals == cardinal {
+            if diagonals.contains(diagonal) {
+                return Some(1);
+            }
+            return Some(2);
+        }
+        None
+    }
+
+    fn check_corners_for_3_cardinals(
+        cardinal: [Coordinate; 3],
+        diagonal: [Coordinate; 2],
+        cardinals: &[Coordinate],
+        diagonals: &[Coordinate],
+    ) -> Option<usize> {
+        if cardinal != cardinals {
+            return None;
+        }
+        Some(
+            2usize.saturating_sub(
+                diagonal
+                    .iter()
+                    .map(|diag| diagonals.contains(diag) as usize)
+                    .sum(),
+            ),
+        )
+    }
+
+    fn check_corners_for_4_cardinals(diagonals: &[Coordinate]) -> usize {
+        4usize.saturating_sub(diagonals.len())
+    }
+
+    #[test]
+    fn test_count_corners_windowed() {
+        for input in [
+            INPUT,
+            INPUT_LARGE,
+            "OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO",
+            "EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE",
+            "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA",
+            "AAB\nAAB\nBBA",
+            &read_file_to_string("data/day12.txt"),
+        ] {
+            let labels = watershed(&parse_input(input), Connectivity::Four).labels;
+            let mut expected = Vec::new();
+            for row in labels.row_range() {
+                for col in labels.col_range() {
+                    let id = labels[row][col];
+                    if id == expected.len() {
+                        expected.push(0);
+                    }
+                    expected[id] +=
+                        added_corners(Coordinate::new(row as isize, col as isize), &labels);
+                }
+            }
+            assert_eq!(count_corners_windowed(&labels), expected);
+        }
+    }
+
+    #[test]
+    fn test_total_price() {
+        let matrix = parse_input(INPUT);
+        // A: 4 + 10, B: 4 + 8, C: 4 + 10, D: 1 + 4, E: 3 + 8.
+        assert_eq!(
+            total_price(&matrix, |region| region.area + region.perimeter),
+            56
+        );
+        // Only the C region has a bounding box that is not filled.
+        assert_eq!(
+            total_price(&matrix, |region| region.bounding_box.area()),
+            4 + 4 + 6 + 1 + 3
+        );
+        // Regions with an area above 3 are free.
+        assert_eq!(
+            total_price(&matrix, |region| match region.area {
+                0..=3 => region.area * region.perimeter,
+                _ => 0,
+            }),
+            4 + 3 * 8
+        );
     }
 
     #[test]
@@ -140,54 +140,207 @@
 //!
 //! From here, we can calculate the inverted matrix A⁻¹, solve the system and
 //! reject any non-integer solutions.
+//!
+//! Written out, this is Cramer's rule:
+//!
+//! ```text
+//! x = (px . y_b - py . x_b) / det(A)
+//! y = (x_a . py - y_a . px) / det(A)
+//! ```
+//!
+//! Evaluating this in integers, and only accepting exact divisions, avoids the
+//! rounding errors of floating point numbers at the large prize coordinates of
+//! part 2.
 use nom::{
+    branch::alt,
     bytes::complete::tag,
-    character::complete::{line_ending, u32},
+    character::complete::{i64, line_ending, multispace0, one_of},
+    combinator::{all_consuming, eof, peek},
     error::Error,
     multi::separated_list1,
-    sequence::{delimited, preceded, separated_pair, tuple},
+    sequence::{delimited, preceded, separated_pair, terminated, tuple},
     Finish, IResult,
 };
+use std::fmt::Display;
 
-const COST_BUTTON_A: u32 = 3;
-const COST_BUTTON_B: u32 = 1;
-const FLOAT_PRECISION: f64 = 1e-4;
-const PART_1_MAX_PRESSES: u32 = 100;
-const PART_2_PRIZE_OFFSET: f64 = 10_000_000_000_000f64;
-
+/// The costs of the buttons, the maximum number of presses of each button and
+/// the offset added to both prize coordinates.
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Button {
-    x: f64,
-    y: f64,
-    cost: u32,
+pub struct Rules {
+    pub cost_a: u64,
+    pub cost_b: u64,
+    pub max_presses: Option<u64>,
+    pub prize_offset: i64,
 }
 
-impl Button {
-    pub fn new(x: f64, y: f64, cost: u32) -> Self {
-        Button { x, y, cost }
+impl Default for Rules {
+    /// The costs of the puzzle, without a maximum number of presses or an
+    /// offset.
+    fn default() -> Self {
+        Rules {
+            cost_a: 3,
+            cost_b: 1,
+            max_presses: None,
+            prize_offset: 0,
+        }
+    }
+}
+
+impl Rules {
+    pub fn part_1() -> Self {
+        Rules {
+            max_presses: Some(100),
+            ..Rules::default()
+        }
     }
 
-    pub fn new_button_a(x: f64, y: f64) -> Self {
-        Button::new(x, y, COST_BUTTON_A)
+    pub fn part_2() -> Self {
+        Rules {
+            prize_offset: 10_000_000_000_000,
+            ..Rules::default()
+        }
     }
+}
 
-    pub fn new_button_b(x: f64, y: f64) -> Self {
-        Button::new(x, y, COST_BUTTON_B)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Button {
+    x: i64,
+    y: i64,
+}
+
+impl Button {
+    pub fn new(x: i64, y: i64) -> Self {
+        Button { x, y }
     }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Prize {
-    x: f64,
-    y: f64,
+    x: i64,
+    y: i64,
 }
 
 impl Prize {
-    pub fn new(x: f64, y: f64) -> Self {
+    pub fn new(x: i64, y: i64) -> Self {
         Prize { x, y }
     }
 }
 
+/// The outcome of solving a claw machine.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Solve {
+    /// The only number of presses of buttons A and B that wins the prize.
+    Unique([u128; 2]),
+    /// The prize can not be won.
+    None,
+    /// The buttons move the claw in the same direction, so there can be many
+    /// ways to win the prize. This is the cheapest one.
+    Degenerate([u128; 2]),
+}
+
+impl Solve {
+    /// The number of presses of buttons A and B that wins the prize, if any.
+    pub fn presses(&self) -> Option<[u128; 2]> {
+        match self {
+            Solve::Unique(presses) | Solve::Degenerate(presses) => Some(*presses),
+            Solve::None => None,
+        }
+    }
+}
+
+/// An intermediate value of solving a claw machine, or of summing the presses
+/// and costs of many machines, that does not fit in its integer type.
+#[derive(Debug, PartialEq)]
+pub struct OverflowError;
+
+impl Display for OverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "claw machine arithmetic overflows")
+    }
+}
+
+impl std::error::Error for OverflowError {}
+
+fn add(a: i128, b: i128) -> Result<i128, OverflowError> {
+    a.checked_add(b).ok_or(OverflowError)
+}
+
+fn sub(a: i128, b: i128) -> Result<i128, OverflowError> {
+    a.checked_sub(b).ok_or(OverflowError)
+}
+
+fn mul(a: i128, b: i128) -> Result<i128, OverflowError> {
+    a.checked_mul(b).ok_or(OverflowError)
+}
+
+fn neg(a: i128) -> Result<i128, OverflowError> {
+    a.checked_neg().ok_or(OverflowError)
+}
+
+/// Find `g`, `x` and `y` such that `a x + b y = g`, with `g` the greatest
+/// common divisor of `a` and `b`.
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        return if a < 0 { (-a, -1, 0) } else { (a, 1, 0) };
+    }
+    let (g, x, y) = extended_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+/// Find the non-negative integers `a` and `b`, optionally at most `max`, with
+/// `a u + b v = t` that minimize `cost_a a + cost_b b`, if any.
+fn cheapest_on_line(
+    [u, v, t]: [i128; 3],
+    [cost_a, cost_b]: [i128; 2],
+    max: Option<i128>,
+) -> Result<Option<[i128; 2]>, OverflowError> {
+    if u == 0 && v == 0 {
+        return Ok((t == 0).then_some([0, 0]));
+    }
+    let (g, x, y) = extended_gcd(u, v);
+    if t % g != 0 {
+        return Ok(None);
+    }
+    // All solutions are `a = a0 + k da` and `b = b0 + k db` for any integer k.
+    let [a0, b0] = [mul(x, t / g)?, mul(y, t / g)?];
+    let [da, db] = [v / g, -u / g];
+    // The range of k for which both presses are non-negative and at most the
+    // maximum.
+    let [mut lo, mut hi] = [None::<i128>, None::<i128>];
+    let mut raise_lo = |bound: i128| lo = Some(lo.map_or(bound, |lo: i128| lo.max(bound)));
+    let mut lower_hi = |bound: i128| hi = Some(hi.map_or(bound, |hi: i128| hi.min(bound)));
+    for (c0, d) in [(a0, da), (b0, db)] {
+        match d.signum() {
+            1 => {
+                raise_lo(neg(c0.div_euclid(d))?);
+                if let Some(max) = max {
+                    lower_hi(sub(max, c0)?.div_euclid(d));
+                }
+            }
+            -1 => {
+                lower_hi(c0.div_euclid(-d));
+                if let Some(max) = max {
+                    raise_lo(neg(sub(max, c0)?.div_euclid(-d))?);
+                }
+            }
+            _ if c0 < 0 || max.is_some_and(|max| c0 > max) => return Ok(None),
+            _ => (),
+        }
+    }
+    if let (Some(lo), Some(hi)) = (lo, hi) {
+        if lo > hi {
+            return Ok(None);
+        }
+    }
+    // The cost is linear in k. As costs are non-negative, the range is always
+    // bounded in the direction in which the cost decreases.
+    let slope = add(mul(cost_a, da)?, mul(cost_b, db)?)?;
+    let Some(k) = (if slope < 0 { hi.or(lo) } else { lo.or(hi) }) else {
+        return Ok(None);
+    };
+    Ok(Some([add(a0, mul(k, da)?)?, add(b0, mul(k, db)?)?]))
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ClawMachine {
     button_a: Button,
@@ -204,30 +357,89 @@ impl ClawMachine {
         }
     }
 
-    pub fn solve(&self) -> Option<[u128; 2]> {
-        let determinant = (self.button_a.x * self.button_b.y) - (self.button_b.x * self.button_a.y);
-        let inverted = [
-            [
-                self.button_b.y / determinant,
-                -self.button_b.x / determinant,
-            ],
-            [
-                -self.button_a.y / determinant,
-                self.button_a.x / determinant,
-            ],
+    /// Find the number of presses of buttons A and B that move the claw to
+    /// the prize, with the default rules.
+    pub fn solve(&self) -> Solve {
+        self.solve_with(&Rules::default())
+    }
+
+    /// Find the number of presses of buttons A and B that move the claw to
+    /// the prize, using Cramer's rule in exact integer arithmetic.
+    ///
+    /// # Panics
+    /// If an intermediate value overflows, see `try_solve_with`.
+    pub fn solve_with(&self, rules: &Rules) -> Solve {
+        self.try_solve_with(rules)
+            .expect("claw machine should not overflow")
+    }
+
+    /// Find the number of presses of buttons A and B that move the claw to
+    /// the prize, using Cramer's rule in exact integer arithmetic. The
+    /// products of the coordinates can exceed an i128 for extreme inputs.
+    pub fn try_solve_with(&self, rules: &Rules) -> Result<Solve, OverflowError> {
+        let [x_a, y_a] = [self.button_a.x as i128, self.button_a.y as i128];
+        let [x_b, y_b] = [self.button_b.x as i128, self.button_b.y as i128];
+        let [p_x, p_y] = [
+            self.prize.x as i128 + rules.prize_offset as i128,
+            self.prize.y as i128 + rules.prize_offset as i128,
         ];
-        let solved = [
-            inverted[0][0] * self.prize.x + inverted[0][1] * self.prize.y,
-            inverted[1][0] * self.prize.x + inverted[1][1] * self.prize.y,
+        let max = rules.max_presses.map(|max| max as i128);
+        let determinant = sub(mul(x_a, y_b)?, mul(x_b, y_a)?)?;
+        if determinant == 0 {
+            return Self::solve_degenerate([x_a, y_a], [x_b, y_b], [p_x, p_y], rules);
+        }
+        let numerators = [
+            sub(mul(p_x, y_b)?, mul(p_y, x_b)?)?,
+            sub(mul(x_a, p_y)?, mul(y_a, p_x)?)?,
         ];
-        if solved
+        // Only exact, non-negative divisions are a valid number of presses.
+        if numerators
             .iter()
-            .all(|el| el.fract() <= FLOAT_PRECISION || el.fract() >= (1f64 - FLOAT_PRECISION))
+            .any(|numerator| numerator % determinant != 0)
         {
-            Some([solved[0].round() as u128, solved[1].round() as u128])
+            return Ok(Solve::None);
+        }
+        let presses = numerators.map(|numerator| numerator / determinant);
+        if presses
+            .iter()
+            .any(|press| *press < 0 || max.is_some_and(|max| *press > max))
+        {
+            return Ok(Solve::None);
+        }
+        Ok(Solve::Unique(presses.map(|press| press as u128)))
+    }
+
+    /// Find the cheapest number of presses when both buttons move the claw in
+    /// the same direction. The prize must then lie along that direction, which
+    /// reduces the system to a single equation.
+    fn solve_degenerate(
+        [x_a, y_a]: [i128; 2],
+        [x_b, y_b]: [i128; 2],
+        [p_x, p_y]: [i128; 2],
+        rules: &Rules,
+    ) -> Result<Solve, OverflowError> {
+        if sub(mul(x_a, p_y)?, mul(y_a, p_x)?)? != 0 || sub(mul(x_b, p_y)?, mul(y_b, p_x)?)? != 0 {
+            return Ok(Solve::None);
+        }
+        // Use an axis along which at least one button moves the claw.
+        let line = if x_a != 0 || x_b != 0 {
+            [x_a, x_b, p_x]
         } else {
-            None
+            [y_a, y_b, p_y]
+        };
+        let costs = [rules.cost_a as i128, rules.cost_b as i128];
+        let max = rules.max_presses.map(|max| max as i128);
+        let Some([press_a, press_b]) = cheapest_on_line(line, costs, max)? else {
+            return Ok(Solve::None);
+        };
+        // Buttons that do not move the claw can only reach a prize at the
+        // origin, which the single equation does not check along both axes.
+        if add(mul(press_a, x_a)?, mul(press_b, x_b)?)? != p_x
+            || add(mul(press_a, y_a)?, mul(press_b, y_b)?)? != p_y
+        {
+            return Ok(Solve::None);
         }
+        Ok(Solve::Degenerate([press_a as u128, press_b as u128]))
     }
 }
 
@@ -236,26 +448,38 @@ fn parse<'a>(
     name: &str,
     preceded_1: &str,
     preceded_2: &str,
-) -> IResult<&'a str, (u32, u32)> {
+    number: fn(&str) -> IResult<&str, i64>,
+) -> IResult<&'a str, (i64, i64)> {
     delimited(
         tag(name),
         separated_pair(
-            preceded(tag(preceded_1), u32),
+            preceded(tag(preceded_1), number),
             tag(", "),
-            preceded(tag(preceded_2), u32),
+            preceded(tag(preceded_2), number),
         ),
-        line_ending,
+        // The last line of the input may lack a line ending.
+        alt((line_ending, eof)),
     )(input)
 }
 
-fn parse_button_a(input: &str) -> IResult<&str, (u32, u32)> {
-    parse(input, "Button A: ", "X+", "Y+")
+/// Parse a number with an optional sign, e.g. `94` or `-3`.
+fn integer(input: &str) -> IResult<&str, i64> {
+    i64(input)
+}
+
+/// Parse a number with an explicit sign, e.g. `+94` or `-3`.
+fn signed(input: &str) -> IResult<&str, i64> {
+    preceded(peek(one_of("+-")), i64)(input)
+}
+
+fn parse_button_a(input: &str) -> IResult<&str, (i64, i64)> {
+    parse(input, "Button A: ", "X", "Y", signed)
 }
-fn parse_button_b(input: &str) -> IResult<&str, (u32, u32)> {
-    parse(input, "Button B: ", "X+", "Y+")
+fn parse_button_b(input: &str) -> IResult<&str, (i64, i64)> {
+    parse(input, "Button B: ", "X", "Y", signed)
 }
-fn parse_prize(input: &str) -> IResult<&str, (u32, u32)> {
-    parse(input, "Prize: ", "X=", "Y=")
+fn parse_prize(input: &str) -> IResult<&str, (i64, i64)> {
+    parse(input, "Prize: ", "X=", "Y=", integer)
 }
 
 fn parse_machine(input: &str) -> IResult<&str, ClawMachine> {
@@ -267,60 +491,154 @@ fn parse_machine(input: &str) -> IResult<&str, ClawMachine> {
     Ok((
         input,
         ClawMachine {
-            button_a: Button::new_button_a(button_a.0 as f64, button_a.1 as f64),
-            button_b: Button::new_button_b(button_b.0 as f64, button_b.1 as f64),
-            prize: Prize {
-                x: prize.0 as f64,
-                y: prize.1 as f64,
-            },
+            button_a: Button::new(button_a.0, button_a.1),
+            button_b: Button::new(button_b.0, button_b.1),
+            prize: Prize::new(prize.0, prize.1),
         },
     ))
 }
 
+/// Parse all claw machines, separated by blank lines. Anything but trailing
+/// whitespace after the last machine is an error.
 pub fn parse_input(input: &str) -> Result<Vec<ClawMachine>, Error<&str>> {
-    let (_, machines) = separated_list1(line_ending, parse_machine)(input).finish()?;
+    let (_, machines) = all_consuming(terminated(
+        separated_list1(line_ending, parse_machine),
+        multispace0,
+    ))(input)
+    .finish()?;
     Ok(machines)
 }
 
-/// Calculate the cost of the required button presses for winning machines,
-/// capped at 100 presses for each button.
-pub fn part_1(machines: &[ClawMachine]) -> u128 {
+/// The total number of presses of buttons A and B, and their total cost, over
+/// all winning machines.
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub struct Totals {
+    pub presses: [u128; 2],
+    pub cost: u128,
+}
+
+/// Statistics over a set of machines: how many can be won, how many can not,
+/// and how many could only be won without the maximum number of presses,
+/// along with the total presses and cost of the winning machines.
+#[derive(Debug, PartialEq, Default, Clone, Copy)]
+pub struct Summary {
+    pub solvable: usize,
+    pub unsolvable: usize,
+    pub capped: usize,
+    pub total_cost: u128,
+    pub total_presses: [u128; 2],
+}
+
+impl Summary {
+    /// The summary of a single machine.
+    fn of(machine: &ClawMachine, rules: &Rules) -> Result<Self, OverflowError> {
+        let Some(presses) = machine.try_solve_with(rules)?.presses() else {
+            let uncapped = Rules {
+                max_presses: None,
+                ..*rules
+            };
+            let capped = rules.max_presses.is_some()
+                && machine.try_solve_with(&uncapped)?.presses().is_some();
+            return Ok(Summary {
+                unsolvable: usize::from(!capped),
+                capped: usize::from(capped),
+                ..Summary::default()
+            });
+        };
+        let costs = [rules.cost_a as u128, rules.cost_b as u128];
+        let mut total_cost = 0u128;
+        for (press, cost) in presses.iter().zip(costs) {
+            let price = press.checked_mul(cost).ok_or(OverflowError)?;
+            total_cost = total_cost.checked_add(price).ok_or(OverflowError)?;
+        }
+        Ok(Summary {
+            solvable: 1,
+            total_cost,
+            total_presses: presses,
+            ..Summary::default()
+        })
+    }
+
+    /// Combine the summaries of two sets of machines.
+    fn merge(self, other: Self) -> Result<Self, OverflowError> {
+        let add = |a: u128, b: u128| a.checked_add(b).ok_or(OverflowError);
+        Ok(Summary {
+            solvable: self.solvable + other.solvable,
+            unsolvable: self.unsolvable + other.unsolvable,
+            capped: self.capped + other.capped,
+            total_cost: add(self.total_cost, other.total_cost)?,
+            total_presses: [
+                add(self.total_presses[0], other.total_presses[0])?,
+                add(self.total_presses[1], other.total_presses[1])?,
+            ],
+        })
+    }
+}
+
+#[cfg(any(test, not(feature = "rayon")))]
+fn solve_summary_serial(machines: &[ClawMachine], rules: &Rules) -> Result<Summary, OverflowError> {
     machines
         .iter()
-        .filter_map(|machine| machine.solve())
-        .filter(|presses| {
-            presses
-                .iter()
-                .all(|press| press <= &(PART_1_MAX_PRESSES as u128))
+        .try_fold(Summary::default(), |summary, machine| {
+            summary.merge(Summary::of(machine, rules)?)
         })
-        .map(|[press_a, press_b]| press_a * COST_BUTTON_A as u128 + press_b * COST_BUTTON_B as u128)
-        .sum()
+}
+
+#[cfg(feature = "rayon")]
+fn solve_summary_parallel(
+    machines: &[ClawMachine],
+    rules: &Rules,
+) -> Result<Summary, OverflowError> {
+    use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+    machines
+        .par_iter()
+        .map(|machine| Summary::of(machine, rules))
+        .try_reduce(Summary::default, Summary::merge)
+}
+
+/// Summarize all machines in a single pass. Presses are bounded by the i128
+/// solver, but multiplying them by u64 costs and summing over many machines
+/// can overflow a u128. With the `rayon` feature, the machines are solved in
+/// parallel.
+pub fn solve_summary(machines: &[ClawMachine], rules: &Rules) -> Result<Summary, OverflowError> {
+    #[cfg(feature = "rayon")]
+    return solve_summary_parallel(machines, rules);
+    #[cfg(not(feature = "rayon"))]
+    solve_summary_serial(machines, rules)
+}
+
+/// Calculate the presses and cost of the required button presses for winning
+/// machines.
+pub fn solve_all(machines: &[ClawMachine], rules: &Rules) -> Result<Totals, OverflowError> {
+    solve_summary(machines, rules).map(|summary| Totals {
+        presses: summary.total_presses,
+        cost: summary.total_cost,
+    })
+}
+
+/// Calculate the cost of the required button presses for winning machines,
+/// capped at 100 presses for each button.
+pub fn part_1(machines: &[ClawMachine]) -> u128 {
+    solve_summary(machines, &Rules::part_1())
+        .expect("costs should not overflow")
+        .total_cost
 }
 
 /// Calculate the cost of the required button presses for winning machines,
 /// updating each machine to have a large offset in the prize coordinates.
 pub fn part_2(machines: &[ClawMachine]) -> u128 {
-    machines
-        .iter()
-        .filter_map(|machine| {
-            let updated_machine = ClawMachine::new(
-                machine.button_a,
-                machine.button_b,
-                Prize::new(
-                    machine.prize.x + PART_2_PRIZE_OFFSET,
-                    machine.prize.y + PART_2_PRIZE_OFFSET,
-                ),
-            );
-            updated_machine.solve()
-        })
-        .map(|[press_a, press_b]| press_a * COST_BUTTON_A as u128 + press_b * COST_BUTTON_B as u128)
-        .sum()
+    solve_summary(machines, &Rules::part_2())
+        .expect("costs should not overflow")
+        .total_cost
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        day13::{parse_input, part_1, part_2, Button, ClawMachine, Prize},
+        day13::{
+            parse_input, part_1, part_2, solve_all, solve_summary, Button, ClawMachine,
+            OverflowError, Prize, Rules, Solve, Summary, Totals,
+        },
         util::read_file_to_string,
     };
 
@@ -348,29 +666,367 @@ Prize: X=18641, Y=10279
             machines,
             vec![
                 ClawMachine::new(
-                    Button::new_button_a(94.0, 34.0),
-                    Button::new_button_b(22.0, 67.0),
-                    Prize::new(8400.0, 5400.0)
+                    Button::new(94, 34),
+                    Button::new(22, 67),
+                    Prize::new(8400, 5400)
                 ),
                 ClawMachine::new(
-                    Button::new_button_a(26.0, 66.0),
-                    Button::new_button_b(67.0, 21.0),
-                    Prize::new(12748.0, 12176.0)
+                    Button::new(26, 66),
+                    Button::new(67, 21),
+                    Prize::new(12748, 12176)
                 ),
                 ClawMachine::new(
-                    Button::new_button_a(17.0, 86.0),
-                    Button::new_button_b(84.0, 37.0),
-                    Prize::new(7870.0, 6450.0)
+                    Button::new(17, 86),
+                    Button::new(84, 37),
+                    Prize::new(7870, 6450)
                 ),
                 ClawMachine::new(
-                    Button::new_button_a(69.0, 23.0),
-                    Button::new_button_b(27.0, 71.0),
-                    Prize::new(18641.0, 10279.0)
+                    Button::new(69, 23),
+                    Button::new(27, 71),
+                    Prize::new(18641, 10279)
                 ),
             ]
         )
     }
 
+    /// The original floating point solver, which accepts solutions within a
+    /// tolerance of an integer.
+    fn solve_f64(machine: &ClawMachine) -> Option<[u128; 2]> {
+        const FLOAT_PRECISION: f64 = 1e-4;
+        let [x_a, y_a] = [machine.button_a.x as f64, machine.button_a.y as f64];
+        let [x_b, y_b] = [machine.button_b.x as f64, machine.button_b.y as f64];
+        let [p_x, p_y] = [machine.prize.x as f64, machine.prize.y as f64];
+        let determinant = (x_a * y_b) - (x_b * y_a);
+        let inverted = [
+            [y_b / determinant, -x_b / determinant],
+            [-y_a / determinant, x_a / determinant],
+        ];
+        let solved = [
+            inverted[0][0] * p_x + inverted[0][1] * p_y,
+            inverted[1][0] * p_x + inverted[1][1] * p_y,
+        ];
+        if solved
+            .iter()
+            .all(|el| el.fract() <= FLOAT_PRECISION || el.fract() >= (1f64 - FLOAT_PRECISION))
+        {
+            Some([solved[0].round() as u128, solved[1].round() as u128])
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_solve() {
+        let machines = parse_input(INPUT).expect("cannot parse");
+        let solutions: Vec<_> = machines.iter().map(|machine| machine.solve()).collect();
+        assert_eq!(
+            solutions,
+            vec![
+                Solve::Unique([80, 40]),
+                Solve::None,
+                Solve::Unique([38, 86]),
+                Solve::None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_solve_f64_agrees() {
+        let input = read_file_to_string("data/day13.txt");
+        for machine in parse_input(&input).expect("cannot parse") {
+            assert_eq!(machine.solve().presses(), solve_f64(&machine));
+            let offset = Rules::part_2().prize_offset;
+            let offset = ClawMachine::new(
+                machine.button_a,
+                machine.button_b,
+                Prize::new(machine.prize.x + offset, machine.prize.y + offset),
+            );
+            assert_eq!(offset.solve().presses(), solve_f64(&offset));
+        }
+    }
+
+    #[test]
+    fn test_solve_f64_disagrees() {
+        // Button A needs 10^5 + 1/10^5 presses, which is within the tolerance
+        // of the floating point solver.
+        let machine = ClawMachine::new(
+            Button::new(100_000, 0),
+            Button::new(0, 1),
+            Prize::new(100_000 * 100_000 + 1, 7),
+        );
+        assert_eq!(solve_f64(&machine), Some([100_000, 7]));
+        assert_eq!(machine.solve().presses(), None);
+        // An exact solution is found by both.
+        let machine = ClawMachine::new(
+            Button::new(100_000, 0),
+            Button::new(0, 1),
+            Prize::new(100_000 * 100_000, 7),
+        );
+        assert_eq!(solve_f64(&machine), Some([100_000, 7]));
+        assert_eq!(machine.solve().presses(), Some([100_000, 7]));
+    }
+
+    #[test]
+    fn test_solve_negative() {
+        // Reaching the prize requires pressing button B -1 times.
+        let machine = ClawMachine::new(Button::new(1, 1), Button::new(1, 0), Prize::new(4, 5));
+        assert_eq!(machine.solve().presses(), None);
+    }
+
+    #[test]
+    fn test_solve_degenerate() {
+        let machine = |a: [i64; 2], b: [i64; 2], prize: [i64; 2]| {
+            ClawMachine::new(
+                Button::new(a[0], a[1]),
+                Button::new(b[0], b[1]),
+                Prize::new(prize[0], prize[1]),
+            )
+        };
+        // Button B is cheaper and moves the claw further.
+        assert_eq!(
+            machine([1, 1], [2, 2], [10, 10]).solve(),
+            Solve::Degenerate([0, 5])
+        );
+        // 3a + 2b = 7 has a single non-negative solution.
+        assert_eq!(
+            machine([3, 3], [2, 2], [7, 7]).solve(),
+            Solve::Degenerate([1, 2])
+        );
+        // Button A is cheaper per unit of distance: 2 x 3 + 1 < 9.
+        assert_eq!(
+            machine([4, 8], [1, 2], [9, 18]).solve(),
+            Solve::Degenerate([2, 1])
+        );
+        // Only one button moves the claw.
+        assert_eq!(
+            machine([0, 0], [3, 1], [9, 3]).solve(),
+            Solve::Degenerate([0, 3])
+        );
+        assert_eq!(
+            machine([0, 2], [0, 0], [0, 6]).solve(),
+            Solve::Degenerate([3, 0])
+        );
+        // Neither button moves the claw.
+        assert_eq!(
+            machine([0, 0], [0, 0], [0, 0]).solve(),
+            Solve::Degenerate([0, 0])
+        );
+        assert_eq!(machine([0, 0], [0, 0], [0, 1]).solve(), Solve::None);
+    }
+
+    #[test]
+    fn test_solve_degenerate_unwinnable() {
+        let machine = |a: [i64; 2], b: [i64; 2], prize: [i64; 2]| {
+            ClawMachine::new(
+                Button::new(a[0], a[1]),
+                Button::new(b[0], b[1]),
+                Prize::new(prize[0], prize[1]),
+            )
+        };
+        // The prize is not in the direction of the buttons.
+        assert_eq!(machine([1, 1], [2, 2], [3, 4]).solve(), Solve::None);
+        // The prize is in the right direction, but only at even distances.
+        assert_eq!(machine([2, 2], [4, 4], [5, 5]).solve(), Solve::None);
+        // The prize is in the opposite direction.
+        assert_eq!(machine([1, 1], [2, 2], [-3, -3]).solve(), Solve::None);
+        // 5a + 3b = 7 has no non-negative solutions.
+        assert_eq!(machine([5, 5], [3, 3], [7, 7]).solve(), Solve::None);
+    }
+
+    #[test]
+    fn test_solve_all() {
+        let machines = parse_input(INPUT).expect("cannot parse");
+        assert_eq!(
+            solve_all(&machines, &Rules::part_1()),
+            Ok(Totals {
+                presses: [80 + 38, 40 + 86],
+                cost: 480
+            })
+        );
+        assert_eq!(
+            solve_all(&machines, &Rules::part_2()).map(|totals| totals.cost),
+            Ok(875318608908)
+        );
+        // 80 + 3 x 40 and 38 + 3 x 86.
+        let rules = Rules {
+            cost_a: 1,
+            cost_b: 3,
+            ..Rules::part_1()
+        };
+        assert_eq!(
+            solve_all(&machines, &rules).map(|totals| totals.cost),
+            Ok(200 + 296)
+        );
+        // Only the first machine needs fewer than 85 presses.
+        let rules = Rules {
+            max_presses: Some(85),
+            ..Rules::part_1()
+        };
+        assert_eq!(
+            solve_all(&machines, &rules).map(|totals| totals.cost),
+            Ok(280)
+        );
+    }
+
+    #[test]
+    fn test_solve_summary() {
+        let machines = parse_input(INPUT).expect("cannot parse");
+        // The first and third machine can be won in part 1, the others in
+        // part 2.
+        assert_eq!(
+            solve_summary(&machines, &Rules::part_1()),
+            Ok(Summary {
+                solvable: 2,
+                unsolvable: 2,
+                capped: 0,
+                total_cost: 480,
+                total_presses: [80 + 38, 40 + 86],
+            })
+        );
+        let summary = solve_summary(&machines, &Rules::part_2()).expect("no overflow");
+        assert_eq!([summary.solvable, summary.unsolvable], [2, 2]);
+        assert_eq!(summary.total_cost, 875318608908);
+        // Only the first machine needs fewer than 85 presses.
+        let rules = Rules {
+            max_presses: Some(85),
+            ..Rules::part_1()
+        };
+        let summary = solve_summary(&machines, &rules).expect("no overflow");
+        assert_eq!(
+            [summary.solvable, summary.unsolvable, summary.capped],
+            [1, 2, 1]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_solve_summary_parallel() {
+        use crate::day13::{solve_summary_parallel, solve_summary_serial};
+
+        let machines = parse_input(&read_file_to_string("data/day13.txt")).expect("cannot parse");
+        for rules in [Rules::part_1(), Rules::part_2()] {
+            assert_eq!(
+                solve_summary_parallel(&machines, &rules),
+                solve_summary_serial(&machines, &rules)
+            );
+        }
+    }
+
+    #[test]
+    fn test_solve_all_overflow() {
+        // Reaching the prize takes 2 x i64::MAX = u64::MAX - 1 presses of
+        // each button.
+        let machine = || {
+            ClawMachine::new(
+                Button::new(1, 0),
+                Button::new(0, 1),
+                Prize::new(i64::MAX, i64::MAX),
+            )
+        };
+        let rules = Rules {
+            prize_offset: i64::MAX,
+            ..Rules::default()
+        };
+        let presses = u64::MAX as u128 - 1;
+        assert_eq!(
+            solve_all(&[machine(), machine()], &rules),
+            Ok(Totals {
+                presses: [2 * presses, 2 * presses],
+                cost: 2 * (3 * presses + presses)
+            })
+        );
+        // A single button press costs almost a u128, both do not fit.
+        let rules = Rules {
+            cost_a: u64::MAX,
+            cost_b: u64::MAX,
+            ..rules
+        };
+        assert_eq!(solve_all(&[machine()], &rules), Err(OverflowError));
+    }
+
+    #[test]
+    fn test_solve_overflow() {
+        // The numerators of Cramer's rule exceed an i128.
+        let machine = ClawMachine::new(
+            Button::new(1, 1),
+            Button::new(i64::MAX, i64::MIN),
+            Prize::new(i64::MAX, i64::MAX),
+        );
+        let rules = Rules {
+            prize_offset: i64::MAX,
+            ..Rules::default()
+        };
+        assert_eq!(machine.try_solve_with(&rules), Err(OverflowError));
+        assert!(machine.try_solve_with(&Rules::default()).is_ok());
+    }
+
+    #[test]
+    fn test_solve_degenerate_capped() {
+        let machine = ClawMachine::new(Button::new(1, 1), Button::new(2, 2), Prize::new(10, 10));
+        let rules = |max_presses| Rules {
+            max_presses,
+            ..Rules::default()
+        };
+        assert_eq!(machine.solve_with(&rules(None)), Solve::Degenerate([0, 5]));
+        // Button B can not be pressed 5 times, so button A has to make up.
+        assert_eq!(
+            machine.solve_with(&rules(Some(4))),
+            Solve::Degenerate([2, 4])
+        );
+        // Both buttons would need more than 3 presses.
+        assert_eq!(machine.solve_with(&rules(Some(3))), Solve::None);
+    }
+
+    #[test]
+    fn test_parse_negative() {
+        let input = "Button A: X-3, Y+4\nButton B: X+2, Y+1\nPrize: X=-2, Y=10\n";
+        let machines = parse_input(input).expect("cannot parse");
+        assert_eq!(
+            machines,
+            vec![ClawMachine::new(
+                Button::new(-3, 4),
+                Button::new(2, 1),
+                Prize::new(-2, 10)
+            )]
+        );
+        // -3a + 2b = -2 and 4a + b = 10.
+        assert_eq!(machines[0].solve(), Solve::Unique([2, 2]));
+        // The same buttons need negative presses to reach this prize:
+        // a = -2 and b = -2.
+        let machine = ClawMachine::new(Button::new(-3, 4), Button::new(2, 1), Prize::new(2, -10));
+        assert_eq!(machine.solve(), Solve::None);
+    }
+
+    #[test]
+    fn test_parse_missing_sign() {
+        assert!(parse_input("Button A: X3, Y+4\nButton B: X+2, Y+1\nPrize: X=2, Y=10\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_trailing_newline() {
+        let with = parse_input(INPUT).expect("cannot parse");
+        let without = parse_input(INPUT.trim_end()).expect("cannot parse");
+        let extra = parse_input(&format!("{INPUT}\n\n")).expect("cannot parse");
+        assert_eq!(with.len(), 4);
+        assert_eq!(with, without);
+        assert_eq!(with, extra);
+        assert_eq!(part_1(&without), 480);
+        assert_eq!(part_2(&without), 875318608908);
+        let input = read_file_to_string("data/day13.txt");
+        assert_eq!(
+            parse_input(&input).expect("cannot parse"),
+            parse_input(input.trim_end()).expect("cannot parse")
+        );
+    }
+
+    #[test]
+    fn test_parse_trailing_garbage() {
+        // A truncated machine is not silently dropped.
+        let input = format!("{INPUT}\nButton A: X+1, Y+1\n");
+        assert!(parse_input(&input).is_err());
+        assert!(parse_input(&format!("{INPUT}foo")).is_err());
+    }
+
     #[test]
     fn test_part_1_small() {
         assert_eq!(480, part_1(&parse_input(INPUT).unwrap()))
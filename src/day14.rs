@@ -1,22 +1,31 @@
-use std::ops::Range;
+use std::{cmp::Ordering, ops::Range};
+#[cfg(any(test, feature = "fs"))]
+use std::{fs, io, path::Path};
 
 use nom::{
     bytes::complete::tag,
-    character::complete::line_ending,
-    error::Error,
-    multi::many1,
-    sequence::{preceded, separated_pair, terminated},
-    Finish, IResult,
+    combinator::all_consuming,
+    sequence::{preceded, separated_pair},
+    IResult,
 };
 
 use nom::character::complete::i32;
 
-use crate::util::Coordinate;
+use crate::{
+    day12::watershed,
+    util::{lcm, AocError, Connectivity, Coordinate, Matrix},
+};
 
-const DIMENSIONS: Coordinate = Coordinate { r: 101, c: 103 };
-const N_STEPS_PART_1: usize = 100;
-const N_STEPS_PART_2: usize = 10_000;
+/// The shape of the puzzle grid as `[rows, cols]`, i.e. `[height, width]`,
+/// following `Matrix::shape`.
+pub const DIMENSIONS: [usize; 2] = [103, 101];
+pub const N_STEPS_PART_1: usize = 100;
+/// The largest starting row or column of a robot. Grids are kept in memory, so
+/// positions beyond this are most likely a corrupted input.
+const MAX_POSITION: isize = u16::MAX as isize;
 
+/// A robot with a position and a velocity per step. Both are stored as a row
+/// and a column, i.e. `y` and `x` in the puzzle input.
 #[derive(Debug, PartialEq)]
 pub struct Robot {
     coordinate: Coordinate,
@@ -24,17 +33,19 @@ pub struct Robot {
 }
 
 impl Robot {
-    pub fn new(coordinate: [isize; 2], velocity: [isize; 2]) -> Self {
+    /// Create a robot from a position and a velocity, both given as `[x, y]`
+    /// like in the puzzle input.
+    pub fn new([x, y]: [isize; 2], [dx, dy]: [isize; 2]) -> Self {
         Self {
-            coordinate: coordinate.into(),
-            velocity: velocity.into(),
+            coordinate: Coordinate::new(y, x),
+            velocity: Coordinate::new(dy, dx),
         }
     }
 }
 
 fn parse<'a>(input: &'a str, preceded_str: &str) -> IResult<&'a str, Coordinate> {
     let (input, (x, y)) = preceded(tag(preceded_str), separated_pair(i32, tag(","), i32))(input)?;
-    Ok((input, Coordinate::new(x as isize, y as isize)))
+    Ok((input, Coordinate::new(y as isize, x as isize)))
 }
 fn parse_coordinate(input: &str) -> IResult<&str, Coordinate> {
     parse(input, "p=")
@@ -44,10 +55,8 @@ fn parse_velocity(input: &str) -> IResult<&str, Coordinate> {
 }
 
 fn parse_robot(input: &str) -> IResult<&str, Robot> {
-    let (input, (coordinate, velocity)) = terminated(
-        separated_pair(parse_coordinate, tag(" "), parse_velocity),
-        line_ending,
-    )(input)?;
+    let (input, (coordinate, velocity)) =
+        separated_pair(parse_coordinate, tag(" "), parse_velocity)(input)?;
     Ok((
         input,
         Robot {
@@ -57,54 +66,68 @@ fn parse_robot(input: &str) -> IResult<&str, Robot> {
     ))
 }
 
-pub fn parse_input(input: &str) -> Result<Vec<Robot>, Error<&str>> {
-    many1(parse_robot)(input).finish().map(|(input, robots)| {
-        assert!(input.is_empty());
-        robots
-    })
+/// Parse one robot per line, formatted as `p=<x>,<y> v=<dx>,<dy>`. Velocities
+/// can be any number, positions must be non-negative and at most `u16::MAX`.
+pub fn parse_input(input: &str) -> Result<Vec<Robot>, AocError> {
+    let robots = input
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            all_consuming(parse_robot)(line)
+                .ok()
+                .map(|(_, robot)| robot)
+                .filter(|robot| {
+                    (0..=MAX_POSITION).contains(&robot.coordinate.r)
+                        && (0..=MAX_POSITION).contains(&robot.coordinate.c)
+                })
+                .ok_or(AocError::MalformedLine { line: i + 1 })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if robots.is_empty() {
+        return Err(AocError::Missing("robot"));
+    }
+    Ok(robots)
 }
 
 struct Quadrant<T> {
-    x: Range<T>,
-    y: Range<T>,
+    rows: Range<T>,
+    cols: Range<T>,
     pub count: usize,
 }
 
 impl Quadrant<isize> {
     pub fn contains(&self, coordinate: &Coordinate) -> bool {
-        self.x.contains(&coordinate.r) && self.y.contains(&coordinate.c)
+        self.rows.contains(&coordinate.r) && self.cols.contains(&coordinate.c)
     }
 
-    pub fn top_left(dimensions: &Coordinate) -> Self {
-        Quadrant {
-            x: 0..(dimensions.r / 2),
-            y: 0..(dimensions.c / 2),
-            count: 0,
-        }
+    /// The rows or columns before and after the middle one.
+    fn halves(size: usize) -> [Range<isize>; 2] {
+        let size = size as isize;
+        [0..(size / 2), (size - size / 2)..size]
     }
 
-    pub fn bottom_left(dimensions: &Coordinate) -> Self {
+    fn new(dimensions: &[usize; 2], bottom: bool, right: bool) -> Self {
         Quadrant {
-            x: 0..(dimensions.r / 2),
-            y: (dimensions.c - dimensions.c / 2)..dimensions.c,
+            rows: Self::halves(dimensions[0])[bottom as usize].clone(),
+            cols: Self::halves(dimensions[1])[right as usize].clone(),
             count: 0,
         }
     }
 
-    pub fn top_right(dimensions: &Coordinate) -> Self {
-        Quadrant {
-            x: (dimensions.r - dimensions.r / 2)..dimensions.r,
-            y: 0..(dimensions.c / 2),
-            count: 0,
-        }
+    pub fn top_left(dimensions: &[usize; 2]) -> Self {
+        Self::new(dimensions, false, false)
     }
 
-    pub fn bottom_right(dimensions: &Coordinate) -> Self {
-        Quadrant {
-            x: (dimensions.r - dimensions.r / 2)..dimensions.r,
-            y: (dimensions.c - dimensions.c / 2)..dimensions.c,
-            count: 0,
-        }
+    pub fn bottom_left(dimensions: &[usize; 2]) -> Self {
+        Self::new(dimensions, true, false)
+    }
+
+    pub fn top_right(dimensions: &[usize; 2]) -> Self {
+        Self::new(dimensions, false, true)
+    }
+
+    pub fn bottom_right(dimensions: &[usize; 2]) -> Self {
+        Self::new(dimensions, true, true)
     }
 }
 
@@ -115,23 +138,25 @@ pub fn get_total_step(robot: &Robot, steps: usize) -> Coordinate {
     ])
 }
 
-pub fn get_destination(robot: &Robot, steps: usize, dimensions: &Coordinate) -> Coordinate {
+pub fn get_destination(robot: &Robot, steps: usize, dimensions: &[usize; 2]) -> Coordinate {
     let destination = robot.coordinate + get_total_step(robot, steps);
     Coordinate::new(
-        destination.r.rem_euclid(dimensions.r),
-        destination.c.rem_euclid(dimensions.c),
+        destination.r.rem_euclid(dimensions[0] as isize),
+        destination.c.rem_euclid(dimensions[1] as isize),
     )
 }
 
-pub fn solve(robots: &[Robot], dimensions: Coordinate, steps: usize) -> usize {
+/// The product of the number of robots in each quadrant after some steps.
+/// Robots exactly on the middle row or column are not in any quadrant.
+pub fn safety_factor(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> usize {
     let mut quadrants = [
-        Quadrant::top_left(&dimensions),
-        Quadrant::top_right(&dimensions),
-        Quadrant::bottom_left(&dimensions),
-        Quadrant::bottom_right(&dimensions),
+        Quadrant::top_left(dimensions),
+        Quadrant::top_right(dimensions),
+        Quadrant::bottom_left(dimensions),
+        Quadrant::bottom_right(dimensions),
     ];
     for robot in robots {
-        let destination = get_destination(robot, steps, &dimensions);
+        let destination = get_destination(robot, steps, dimensions);
         for quadrant in quadrants.iter_mut() {
             if quadrant.contains(&destination) {
                 quadrant.count += 1;
@@ -142,21 +167,388 @@ pub fn solve(robots: &[Robot], dimensions: Coordinate, steps: usize) -> usize {
     quadrants.iter().map(|quadrant| quadrant.count).product()
 }
 
+/// The number of robots in every cell after some steps.
+pub fn occupancy(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> Matrix<u16> {
+    let mut grid = Matrix::new(vec![vec![0; dimensions[1]]; dimensions[0]]);
+    for robot in robots {
+        let destination = get_destination(robot, steps, dimensions);
+        grid[destination.r as usize][destination.c as usize] += 1;
+    }
+    grid
+}
+
+/// Draw the robots after some steps like in the puzzle statement: the number
+/// of robots in a cell, `#` for more than 9 and `.` for an empty cell.
+pub fn render(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> String {
+    let grid = occupancy(robots, dimensions, steps);
+    Matrix::new(
+        grid.iter()
+            .map(|row| {
+                row.iter()
+                    .map(|count| match count {
+                        0 => '.',
+                        1..=9 => char::from_digit(*count as u32, 10).unwrap(),
+                        _ => '#',
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+    .to_string()
+}
+
+/// Draw the robots after some steps as a plain PBM (`P1`) bitmap, in which an
+/// occupied cell is 1. The image is as wide as the room's `x` axis, i.e. its
+/// columns, and as high as its `y` axis, i.e. its rows.
+pub fn to_pbm(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> String {
+    let [height, width] = *dimensions;
+    let mut pbm = format!("P1\n{width} {height}\n");
+    for row in occupied(robots, dimensions, steps).iter() {
+        let bits: Vec<&str> = row
+            .iter()
+            .map(|occupied| if *occupied { "1" } else { "0" })
+            .collect();
+        pbm.push_str(&bits.join(" "));
+        pbm.push('\n');
+    }
+    pbm
+}
+
+/// Write the PBM bitmap of the robots after some steps to a file.
+#[cfg(any(test, feature = "fs"))]
+pub fn write_pbm<P: AsRef<Path>>(
+    path: P,
+    robots: &[Robot],
+    dimensions: &[usize; 2],
+    steps: usize,
+) -> io::Result<()> {
+    fs::write(path, to_pbm(robots, dimensions, steps))
+}
+
+/// The grid of cells that hold at least one robot after some steps.
+fn occupied(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> Matrix<bool> {
+    let grid = occupancy(robots, dimensions, steps);
+    Matrix::new(
+        grid.iter()
+            .map(|row| row.iter().map(|count| *count > 0).collect())
+            .collect(),
+    )
+}
+
+/// The number of cells in the largest 4-connected group of occupied cells
+/// after some steps.
+pub fn largest_component(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> usize {
+    let watershed = watershed(&occupied(robots, dimensions, steps), Connectivity::Four);
+    let mut sizes = vec![0usize; watershed.n_regions];
+    for label in watershed.labels.iter().flatten() {
+        sizes[*label] += 1;
+    }
+    sizes
+        .iter()
+        .zip(watershed.values.iter())
+        .filter(|(_, occupied)| **occupied)
+        .map(|(size, _)| *size)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The positions of all robots, moved one step at a time. This avoids
+/// recomputing every position from the start for each step of a search.
+pub struct Simulation<'a> {
+    robots: &'a [Robot],
+    dimensions: [usize; 2],
+    positions: Vec<Coordinate>,
+    /// A reusable grid of occupied cells, which is empty between metrics.
+    occupied: Matrix<bool>,
+    /// A reusable stack for the flood fill.
+    stack: Vec<Coordinate>,
+}
+
+impl<'a> Simulation<'a> {
+    pub fn new(robots: &'a [Robot], dimensions: &[usize; 2]) -> Self {
+        Simulation {
+            robots,
+            dimensions: *dimensions,
+            // Parsed positions may lie beyond the grid, wrap them like `step`.
+            positions: robots
+                .iter()
+                .map(|robot| {
+                    Coordinate::new(
+                        robot.coordinate.r.rem_euclid(dimensions[0] as isize),
+                        robot.coordinate.c.rem_euclid(dimensions[1] as isize),
+                    )
+                })
+                .collect(),
+            occupied: Matrix::new(vec![vec![false; dimensions[1]]; dimensions[0]]),
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn positions(&self) -> &[Coordinate] {
+        &self.positions
+    }
+
+    /// Move every robot by its velocity, wrapping around the edges.
+    pub fn step(&mut self) {
+        for (position, robot) in self.positions.iter_mut().zip(self.robots) {
+            *position = Coordinate::new(
+                (position.r + robot.velocity.r).rem_euclid(self.dimensions[0] as isize),
+                (position.c + robot.velocity.c).rem_euclid(self.dimensions[1] as isize),
+            );
+        }
+    }
+
+    /// Find the step in a range with the lowest value of a metric over the
+    /// positions, like `find_min_metric`, but moving every robot one step at a
+    /// time. Steps count from the current positions, and the simulation is left
+    /// at the end of the range. On equal values, the earliest step wins.
+    pub fn find_min_metric<T: PartialOrd>(
+        &mut self,
+        range: Range<usize>,
+        metric: impl Fn(&[Coordinate], &[usize; 2]) -> T,
+    ) -> Option<usize> {
+        for _ in 0..range.start {
+            self.step();
+        }
+        let mut min: Option<(usize, T)> = None;
+        for steps in range {
+            let value = metric(&self.positions, &self.dimensions);
+            if min.as_ref().is_none_or(|(_, min)| value < *min) {
+                min = Some((steps, value));
+            }
+            self.step();
+        }
+        min.map(|(steps, _)| steps)
+    }
+
+    /// The number of cells in the largest 4-connected group of occupied
+    /// cells, see `largest_component`. The flood fill clears every cell it
+    /// visits, so the grid is empty again afterwards.
+    pub fn largest_component(&mut self) -> usize {
+        for position in self.positions.iter() {
+            self.occupied[position.r as usize][position.c as usize] = true;
+        }
+        let mut largest = 0;
+        for seed in self.positions.iter() {
+            if !self.occupied[seed.r as usize][seed.c as usize] {
+                continue;
+            }
+            self.occupied[seed.r as usize][seed.c as usize] = false;
+            self.stack.push(*seed);
+            let mut size = 0;
+            while let Some(coord) = self.stack.pop() {
+                size += 1;
+                for neighbor in coord.cardinals() {
+                    let [r, c] = [neighbor.r as usize, neighbor.c as usize];
+                    if r < self.dimensions[0] && c < self.dimensions[1] && self.occupied[r][c] {
+                        self.occupied[r][c] = false;
+                        self.stack.push(neighbor);
+                    }
+                }
+            }
+            largest = largest.max(size);
+        }
+        largest
+    }
+}
+
+/// Find the first step at which a single group of robots holds more than 30%
+/// of all robots, if any. A picture is drawn by many robots standing next to
+/// each other, while random positions only form small groups.
+pub fn find_tree_step(
+    robots: &[Robot],
+    dimensions: &[usize; 2],
+    max_steps: usize,
+) -> Option<usize> {
+    let threshold = robots.len() * 3 / 10;
+    let mut simulation = Simulation::new(robots, dimensions);
+    for steps in 0..max_steps {
+        if simulation.largest_component() > threshold {
+            return Some(steps);
+        }
+        simulation.step();
+    }
+    None
+}
+
+/// Calculate the safety factor after 100 steps on the puzzle grid.
 pub fn part_1(robots: &[Robot]) -> usize {
-    solve(robots, DIMENSIONS, N_STEPS_PART_1)
+    part_1_with(robots, &DIMENSIONS, N_STEPS_PART_1)
 }
 
-/// The safety factor is a metric for image entropy as it encodes how clustered
-/// the robots (high pixels) are together. Since an image with clear structure
-/// i.e., a christmas tree, will have lower entropy than a random image, the
-/// minimum of the safety factor is where the tree will be.
-pub fn part_2(robots: &mut [Robot]) -> usize {
-    (0..N_STEPS_PART_2)
-        .map(|steps| solve(robots, DIMENSIONS, steps))
-        .enumerate()
-        .min_by(|(_, a), (_, b)| a.cmp(b))
-        .map(|(index, _)| index)
-        .unwrap()
+/// Calculate the safety factor after some steps on a grid of any shape.
+pub fn part_1_with(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> usize {
+    safety_factor(robots, dimensions, steps)
+}
+
+/// Find the first step at which no two robots share a cell, if any. The
+/// picture is drawn with every robot in a cell of its own, while random
+/// positions almost always overlap somewhere.
+pub fn first_all_distinct_step(
+    robots: &[Robot],
+    dimensions: &[usize; 2],
+    max_steps: usize,
+) -> Option<usize> {
+    let mut simulation = Simulation::new(robots, dimensions);
+    // One bit per cell, cleared before every step.
+    let mut seen = vec![0u64; (dimensions[0] * dimensions[1]).div_ceil(64)];
+    for steps in 0..max_steps {
+        seen.fill(0);
+        let distinct = simulation.positions().iter().all(|position| {
+            let index = position.r as usize * dimensions[1] + position.c as usize;
+            let (word, bit) = (index / 64, 1u64 << (index % 64));
+            let unseen = seen[word] & bit == 0;
+            seen[word] |= bit;
+            unseen
+        });
+        if distinct {
+            return Some(steps);
+        }
+        simulation.step();
+    }
+    None
+}
+
+/// The number of steps after which all robots are back at their starting
+/// position. Every robot returns to its starting row after `height` steps and
+/// to its starting column after `width` steps, whatever its velocity.
+pub fn cycle_length(dimensions: &[usize; 2]) -> usize {
+    lcm(dimensions[0], dimensions[1])
+}
+
+/// Find the step at which the robots form a christmas tree on the puzzle grid.
+pub fn part_2(robots: &[Robot]) -> usize {
+    part_2_with(robots, &DIMENSIONS).expect("the robots should form a picture")
+}
+
+/// The sum of the variances of the rows and columns of all robots after some
+/// steps. Robots drawing a picture are packed together, so the variance drops
+/// compared to random positions.
+pub fn variance_metric(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> f64 {
+    variance(
+        robots
+            .iter()
+            .map(|robot| get_destination(robot, steps, dimensions)),
+    )
+}
+
+/// The sum of the variances of the rows and columns of some positions, see
+/// `variance_metric`.
+pub fn variance(positions: impl ExactSizeIterator<Item = Coordinate>) -> f64 {
+    let n = positions.len() as f64;
+    let [mut sum, mut sum_squares] = [[0.0; 2]; 2];
+    for destination in positions {
+        for (axis, value) in [destination.r, destination.c].into_iter().enumerate() {
+            sum[axis] += value as f64;
+            sum_squares[axis] += (value * value) as f64;
+        }
+    }
+    (0..2)
+        .map(|axis| sum_squares[axis] / n - (sum[axis] / n).powi(2))
+        .sum()
+}
+
+/// The largest number of pairs of robots on the same row that mirror each other
+/// across a single column after some steps. Most pictures, like the tree, are
+/// symmetric, while the mirror lines of random pairs are spread out. Use it
+/// with `find_min_metric` through `std::cmp::Reverse`.
+pub fn symmetry_metric(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> usize {
+    let mut rows = vec![vec![]; dimensions[0]];
+    for robot in robots {
+        let destination = get_destination(robot, steps, dimensions);
+        rows[destination.r as usize].push(destination.c);
+    }
+    // Pairs are counted per mirror line, see `Coordinate::reflect_c`.
+    let mut pairs = vec![0; 2 * dimensions[1]];
+    for row in rows.iter_mut() {
+        row.sort_unstable();
+        row.dedup();
+        for (i, c1) in row.iter().enumerate() {
+            for c2 in row[i + 1..].iter() {
+                pairs[(c1 + c2) as usize] += 1;
+            }
+        }
+    }
+    pairs.into_iter().max().unwrap_or_default()
+}
+
+/// The largest number of robots in a single column after some steps. The
+/// picture has a frame, whose sides are long vertical lines of robots, while
+/// random positions spread evenly over the columns. As every robot returns to
+/// its column after `width` steps, searching the first `width` steps only finds
+/// the picture's step modulo the width. Use it with `find_min_metric` through
+/// `std::cmp::Reverse`.
+pub fn column_spike_metric(robots: &[Robot], dimensions: &[usize; 2], steps: usize) -> u16 {
+    occupancy(robots, dimensions, steps)
+        .col_sums()
+        .into_iter()
+        .max()
+        .unwrap_or_default()
+}
+
+/// Order steps by their metric, with the earliest step first on equal values.
+fn by_metric_then_step<T: PartialOrd>(a: &(usize, T), b: &(usize, T)) -> Ordering {
+    a.1.partial_cmp(&b.1)
+        .expect("metrics should be comparable")
+        .then(a.0.cmp(&b.0))
+}
+
+#[cfg(any(test, not(feature = "rayon")))]
+fn find_min_metric_serial<T: PartialOrd>(
+    robots: &[Robot],
+    dimensions: &[usize; 2],
+    range: Range<usize>,
+    metric: impl Fn(&[Robot], &[usize; 2], usize) -> T,
+) -> Option<usize> {
+    range
+        .map(|steps| (steps, metric(robots, dimensions, steps)))
+        .min_by(by_metric_then_step)
+        .map(|(steps, _)| steps)
+}
+
+#[cfg(feature = "rayon")]
+fn find_min_metric_parallel<T: PartialOrd + Send>(
+    robots: &[Robot],
+    dimensions: &[usize; 2],
+    range: Range<usize>,
+    metric: impl Fn(&[Robot], &[usize; 2], usize) -> T + Sync,
+) -> Option<usize> {
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    // A parallel reduction does not keep the first of equal minima, so the
+    // step is part of the comparison.
+    range
+        .into_par_iter()
+        .map(|steps| (steps, metric(robots, dimensions, steps)))
+        .min_by(by_metric_then_step)
+        .map(|(steps, _)| steps)
+}
+
+/// Find the step in a range with the lowest value of a metric. On equal values,
+/// the earliest step wins. Returns `None` for an empty range. With the `rayon`
+/// feature, the steps are evaluated in parallel.
+pub fn find_min_metric<T: PartialOrd + Send>(
+    robots: &[Robot],
+    dimensions: &[usize; 2],
+    range: Range<usize>,
+    metric: impl Fn(&[Robot], &[usize; 2], usize) -> T + Sync,
+) -> Option<usize> {
+    #[cfg(feature = "rayon")]
+    return find_min_metric_parallel(robots, dimensions, range, metric);
+    #[cfg(not(feature = "rayon"))]
+    find_min_metric_serial(robots, dimensions, range, metric)
+}
+
+/// Find the step at which the robots form a picture on a grid of any shape.
+/// The candidate is the step with the lowest variance over a full cycle, after
+/// which all positions repeat, found by stepping a `Simulation`. It is only a
+/// picture if a single group holds more than 30% of all robots, see
+/// `find_tree_step`.
+pub fn part_2_with(robots: &[Robot], dimensions: &[usize; 2]) -> Option<usize> {
+    let steps = Simulation::new(robots, dimensions)
+        .find_min_metric(0..cycle_length(dimensions), |positions, _| {
+            variance(positions.iter().copied())
+        })?;
+    (largest_component(robots, dimensions, steps) > robots.len() * 3 / 10).then_some(steps)
 }
 
 #[cfg(test)]
@@ -165,14 +557,19 @@ mod test {
 
     use crate::{
         day14::{
-            get_destination, part_1, part_2, solve, Quadrant, Robot, DIMENSIONS, N_STEPS_PART_1,
+            column_spike_metric, cycle_length, find_min_metric, find_min_metric_serial,
+            find_tree_step, first_all_distinct_step, get_destination, largest_component, occupancy,
+            part_1, part_1_with, part_2, part_2_with, render, safety_factor, symmetry_metric,
+            to_pbm, variance, variance_metric, write_pbm, Quadrant, Robot, Simulation, DIMENSIONS,
+            N_STEPS_PART_1,
         },
-        util::{read_file_to_string, Coordinate},
+        util::{read_file_to_string, AocError, Coordinate, Matrix},
     };
+    use std::collections::HashSet;
 
     use super::parse_input;
 
-    const DIMENSIONS_SMALL: Coordinate = Coordinate { r: 11, c: 7 };
+    const DIMENSIONS_SMALL: [usize; 2] = [7, 11];
     const INPUT: &str = "p=0,4 v=3,-3
 p=6,3 v=-1,-3
 p=10,3 v=-1,2
@@ -208,33 +605,54 @@ p=9,5 v=-3,-3
         )
     }
 
+    #[test]
+    fn test_parse_input_invalid() {
+        // The last line may lack a line ending.
+        let robots = parse_input(INPUT.trim_end()).expect("cannot parse");
+        assert_eq!(robots.len(), 12);
+        assert_eq!(robots[11], Robot::new([9, 5], [-3, -3]));
+        assert_eq!(
+            parse_input("p=0,4 v=3,-3\np=6,3\n"),
+            Err(AocError::MalformedLine { line: 2 })
+        );
+        assert_eq!(
+            parse_input("p=0,4 v=3,-3\np=-1,3 v=1,1\n"),
+            Err(AocError::MalformedLine { line: 2 })
+        );
+        assert_eq!(
+            parse_input("p=0,4 v=-2147483648,2147483647\n").map(|robots| robots.len()),
+            Ok(1)
+        );
+        assert_eq!(parse_input(""), Err(AocError::Missing("robot")));
+    }
+
     #[test]
     fn test_quadrants() {
         let top_left = Quadrant::top_left(&DIMENSIONS_SMALL);
-        assert_eq!(top_left.x, 0..5);
-        assert_eq!(top_left.y, 0..3);
+        assert_eq!(top_left.rows, 0..3);
+        assert_eq!(top_left.cols, 0..5);
         let top_right = Quadrant::top_right(&DIMENSIONS_SMALL);
-        assert_eq!(top_right.x, 6..11);
-        assert_eq!(top_right.y, 0..3);
+        assert_eq!(top_right.rows, 0..3);
+        assert_eq!(top_right.cols, 6..11);
         let bottom_left = Quadrant::bottom_left(&DIMENSIONS_SMALL);
-        assert_eq!(bottom_left.x, 0..5);
-        assert_eq!(bottom_left.y, 4..7);
+        assert_eq!(bottom_left.rows, 4..7);
+        assert_eq!(bottom_left.cols, 0..5);
         let bottom_right = Quadrant::bottom_right(&DIMENSIONS_SMALL);
-        assert_eq!(bottom_right.x, 6..11);
-        assert_eq!(bottom_right.y, 4..7);
+        assert_eq!(bottom_right.rows, 4..7);
+        assert_eq!(bottom_right.cols, 6..11);
 
         let top_left = Quadrant::top_left(&DIMENSIONS);
-        assert_eq!(top_left.x, 0..50);
-        assert_eq!(top_left.y, 0..51);
+        assert_eq!(top_left.rows, 0..51);
+        assert_eq!(top_left.cols, 0..50);
         let top_right = Quadrant::top_right(&DIMENSIONS);
-        assert_eq!(top_right.x, 51..101);
-        assert_eq!(top_right.y, 0..51);
+        assert_eq!(top_right.rows, 0..51);
+        assert_eq!(top_right.cols, 51..101);
         let bottom_left = Quadrant::bottom_left(&DIMENSIONS);
-        assert_eq!(bottom_left.x, 0..50);
-        assert_eq!(bottom_left.y, 52..103);
+        assert_eq!(bottom_left.rows, 52..103);
+        assert_eq!(bottom_left.cols, 0..50);
         let bottom_right = Quadrant::bottom_right(&DIMENSIONS);
-        assert_eq!(bottom_right.x, 51..101);
-        assert_eq!(bottom_right.y, 52..103);
+        assert_eq!(bottom_right.rows, 52..103);
+        assert_eq!(bottom_right.cols, 51..101);
     }
 
     #[test]
@@ -246,17 +664,17 @@ p=9,5 v=-3,-3
             .sorted()
             .collect();
         let expected: Vec<Coordinate> = vec![
-            Coordinate::new(6, 0),
-            Coordinate::new(6, 0),
-            Coordinate::new(9, 0),
-            Coordinate::new(0, 2),
-            Coordinate::new(1, 3),
-            Coordinate::new(2, 3),
-            Coordinate::new(5, 4),
-            Coordinate::new(4, 5),
+            Coordinate::new(0, 6),
+            Coordinate::new(0, 6),
+            Coordinate::new(0, 9),
+            Coordinate::new(2, 0),
+            Coordinate::new(3, 1),
+            Coordinate::new(3, 2),
             Coordinate::new(4, 5),
-            Coordinate::new(3, 5),
-            Coordinate::new(1, 6),
+            Coordinate::new(5, 4),
+            Coordinate::new(5, 4),
+            Coordinate::new(5, 3),
+            Coordinate::new(6, 1),
             Coordinate::new(6, 6),
         ]
         .into_iter()
@@ -269,9 +687,9 @@ p=9,5 v=-3,-3
     fn test_part_1_small() {
         assert_eq!(
             12,
-            solve(
+            part_1_with(
                 &parse_input(INPUT).unwrap(),
-                DIMENSIONS_SMALL,
+                &DIMENSIONS_SMALL,
                 N_STEPS_PART_1
             )
         )
@@ -279,17 +697,333 @@ p=9,5 v=-3,-3
 
     #[test]
     fn test_part_1() {
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        assert_eq!(230436441, part_1(&robots));
+        assert_eq!(230436441, part_1_with(&robots, &DIMENSIONS, N_STEPS_PART_1));
+    }
+
+    #[test]
+    fn test_column_spike_metric() {
+        let robots = [
+            Robot::new([1, 0], [0, 0]),
+            Robot::new([1, 1], [0, 0]),
+            Robot::new([1, 2], [0, 0]),
+            Robot::new([4, 2], [0, 0]),
+            Robot::new([4, 2], [0, 0]),
+        ];
+        assert_eq!(column_spike_metric(&robots, &[3, 5], 0), 3);
+        // After one step, the two robots on column 4 move onto column 0.
+        let robots = [Robot::new([4, 0], [1, 0]), Robot::new([4, 1], [1, 0])];
+        assert_eq!(column_spike_metric(&robots, &[3, 5], 1), 2);
+
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
         assert_eq!(
-            230436441,
-            part_1(&parse_input(&read_file_to_string("data/day14.txt")).unwrap())
-        )
+            find_min_metric(&robots, &DIMENSIONS, 0..DIMENSIONS[1], |r, d, s| {
+                std::cmp::Reverse(column_spike_metric(r, d, s))
+            }),
+            Some(8270 % DIMENSIONS[1])
+        );
+    }
+
+    #[test]
+    fn test_symmetry_metric() {
+        // Two pairs mirrored across column 2, and one across column 1.5.
+        let robots = [
+            Robot::new([0, 0], [0, 0]),
+            Robot::new([4, 0], [0, 0]),
+            Robot::new([1, 1], [0, 0]),
+            Robot::new([3, 1], [0, 0]),
+            Robot::new([1, 2], [0, 0]),
+            Robot::new([2, 2], [0, 0]),
+        ];
+        assert_eq!(symmetry_metric(&robots, &[3, 5], 0), 2);
+
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        assert_eq!(
+            find_min_metric(
+                &robots,
+                &DIMENSIONS,
+                0..cycle_length(&DIMENSIONS),
+                |r, d, s| { std::cmp::Reverse(symmetry_metric(r, d, s)) }
+            ),
+            Some(8270)
+        );
     }
 
     #[test]
     fn test_part_2() {
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        assert_eq!(8270, part_2(&robots));
+        assert_eq!(Some(8270), part_2_with(&robots, &DIMENSIONS));
+    }
+
+    #[test]
+    fn test_find_tree_step() {
+        // Robots that gather in a 3x3 block at step 5, starting from spread
+        // out positions.
+        let steps = 5;
+        let robots: Vec<Robot> = (0..9)
+            .map(|i: isize| {
+                let [x, y] = [4 + i % 3, 2 + i / 3];
+                let [dx, dy] = [i - 4, 2 * i - 7];
+                let start = [
+                    (x - dx * steps as isize).rem_euclid(DIMENSIONS_SMALL[1] as isize),
+                    (y - dy * steps as isize).rem_euclid(DIMENSIONS_SMALL[0] as isize),
+                ];
+                Robot::new(start, [dx, dy])
+            })
+            .collect();
+        assert_eq!(largest_component(&robots, &DIMENSIONS_SMALL, steps), 9);
+        assert!(largest_component(&robots, &DIMENSIONS_SMALL, 0) < 9);
+        assert_eq!(find_tree_step(&robots, &DIMENSIONS_SMALL, 10), Some(steps));
+        // The block is only formed after the last step searched.
+        assert_eq!(find_tree_step(&robots, &DIMENSIONS_SMALL, steps), None);
+
+        // Starting positions outside the grid wrap around, like in
+        // `get_destination`.
+        let shifted: Vec<Robot> = robots
+            .iter()
+            .map(|robot| {
+                let [x, y] = [robot.coordinate.c, robot.coordinate.r];
+                let [dx, dy] = [robot.velocity.c, robot.velocity.r];
+                Robot::new(
+                    [
+                        x + DIMENSIONS_SMALL[1] as isize,
+                        y + 2 * DIMENSIONS_SMALL[0] as isize,
+                    ],
+                    [dx, dy],
+                )
+            })
+            .collect();
+        assert_eq!(find_tree_step(&shifted, &DIMENSIONS_SMALL, 10), Some(steps));
+        let robots = parse_input("p=20,0 v=0,0").unwrap();
+        assert_eq!(find_tree_step(&robots, &DIMENSIONS_SMALL, 1), Some(0));
         assert_eq!(
-            8270,
-            part_2(&mut parse_input(&read_file_to_string("data/day14.txt")).unwrap())
-        )
+            Simulation::new(&robots, &DIMENSIONS_SMALL).positions(),
+            [Coordinate::new(0, 9)]
+        );
+    }
+
+    #[test]
+    fn test_find_tree_step_input() {
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        assert_eq!(find_tree_step(&robots, &DIMENSIONS, 10_000), Some(8270));
+    }
+
+    #[test]
+    fn test_render() {
+        let robots = parse_input(INPUT).expect("cannot parse");
+        assert_eq!(
+            render(&robots, &DIMENSIONS_SMALL, N_STEPS_PART_1),
+            "......2..1.
+...........
+1..........
+.11........
+.....1.....
+...12......
+.1....1....
+"
+        );
+        let grid = occupancy(&robots, &DIMENSIONS_SMALL, N_STEPS_PART_1);
+        assert_eq!(grid.shape(), DIMENSIONS_SMALL);
+        assert_eq!(grid[0][6], 2);
+        assert_eq!(grid.iter().flatten().sum::<u16>(), 12);
+        // Overlapping robots beyond 9 are drawn as a single symbol.
+        let robots: Vec<Robot> = (0..10).map(|_| Robot::new([1, 0], [0, 0])).collect();
+        assert_eq!(render(&robots, &[1, 3], 0), ".#.\n");
+    }
+
+    #[test]
+    fn test_to_pbm() {
+        // Three cells wide along x, two high along y.
+        let robots = [Robot::new([2, 0], [0, 0]), Robot::new([0, 1], [1, 0])];
+        assert_eq!(to_pbm(&robots, &[2, 3], 0), "P1\n3 2\n0 0 1\n1 0 0\n");
+        assert_eq!(to_pbm(&robots, &[2, 3], 1), "P1\n3 2\n0 0 1\n0 1 0\n");
+
+        let path = std::env::temp_dir().join("day14_test_to_pbm.pbm");
+        write_pbm(&path, &robots, &[2, 3], 0).expect("cannot write");
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("cannot read"),
+            to_pbm(&robots, &[2, 3], 0)
+        );
+        std::fs::remove_file(path).expect("cannot remove");
+    }
+
+    #[test]
+    fn test_cycle_length() {
+        assert_eq!(cycle_length(&DIMENSIONS), 10403);
+        assert_eq!(cycle_length(&DIMENSIONS_SMALL), 77);
+        let robots = parse_input(INPUT).expect("cannot parse");
+        for robot in robots.iter() {
+            assert_eq!(
+                get_destination(robot, 0, &DIMENSIONS_SMALL),
+                get_destination(robot, 77, &DIMENSIONS_SMALL)
+            );
+        }
+        // With only 12 robots, a group of 4 already counts as a picture, but
+        // the most packed frame is not that one.
+        assert_eq!(find_tree_step(&robots, &DIMENSIONS_SMALL, 77), Some(3));
+        assert_eq!(largest_component(&robots, &DIMENSIONS_SMALL, 3), 4);
+        assert_eq!(part_2_with(&robots, &DIMENSIONS_SMALL), None);
+        // Robots that never move and never touch never form a picture.
+        let robots: Vec<Robot> = (0..6)
+            .map(|i| Robot::new([2 * (i % 3), 2 * (i / 3)], [0, 0]))
+            .collect();
+        assert_eq!(part_2_with(&robots, &DIMENSIONS_SMALL), None);
+    }
+
+    #[test]
+    fn test_simulation() {
+        let robots = parse_input(INPUT).expect("cannot parse");
+        let mut simulation = Simulation::new(&robots, &DIMENSIONS_SMALL);
+        for steps in 0..N_STEPS_PART_1 {
+            assert_eq!(
+                simulation.largest_component(),
+                largest_component(&robots, &DIMENSIONS_SMALL, steps)
+            );
+            simulation.step();
+        }
+        for (position, robot) in simulation.positions().iter().zip(robots.iter()) {
+            assert_eq!(
+                *position,
+                get_destination(robot, N_STEPS_PART_1, &DIMENSIONS_SMALL)
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_min_metric() {
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        assert_eq!(
+            find_min_metric(&robots, &DIMENSIONS, 0..10_000, safety_factor),
+            Some(8270)
+        );
+        assert_eq!(
+            find_min_metric(&robots, &DIMENSIONS, 0..10_000, variance_metric),
+            Some(8270)
+        );
+        assert_eq!(
+            find_min_metric(
+                &robots,
+                &DIMENSIONS,
+                8000..8500,
+                |robots, dimensions, steps| {
+                    std::cmp::Reverse(largest_component(robots, dimensions, steps))
+                }
+            ),
+            Some(8270)
+        );
+        assert_eq!(
+            find_min_metric(&robots, &DIMENSIONS, 0..0, variance_metric),
+            None
+        );
+    }
+
+    #[test]
+    fn test_variance_metric() {
+        // Two robots on opposite corners of a 3x3 grid, moving towards each
+        // other, meet in the middle after one step.
+        let robots = [Robot::new([0, 0], [1, 1]), Robot::new([2, 2], [-1, -1])];
+        assert_eq!(variance_metric(&robots, &[3, 3], 0), 2.0);
+        assert_eq!(variance_metric(&robots, &[3, 3], 1), 0.0);
+        // Equal metrics pick the earliest step.
+        assert_eq!(
+            find_min_metric(&robots, &[3, 3], 0..6, variance_metric),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_simulation_find_min_metric() {
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        let metric = |positions: &[Coordinate], _: &[usize; 2]| variance(positions.iter().copied());
+        for range in [0..cycle_length(&DIMENSIONS), 8000..8500, 8271..9000, 0..0] {
+            assert_eq!(
+                Simulation::new(&robots, &DIMENSIONS).find_min_metric(range.clone(), metric),
+                find_min_metric_serial(&robots, &DIMENSIONS, range, variance_metric)
+            );
+        }
+        // Equal metrics pick the earliest step.
+        let robots = [Robot::new([0, 0], [0, 0])];
+        let mut simulation = Simulation::new(&robots, &[1, 1]);
+        assert_eq!(simulation.find_min_metric(5..10, |_, _| 0), Some(5));
+    }
+
+    #[test]
+    fn test_find_min_metric_ties() {
+        // Two steps share the lowest metric, the earliest one wins.
+        let robots = [Robot::new([0, 0], [0, 0])];
+        let metric = |_: &[Robot], _: &[usize; 2], steps: usize| ![30, 70].contains(&steps);
+        assert_eq!(find_min_metric(&robots, &[1, 1], 0..100, metric), Some(30));
+        assert_eq!(find_min_metric(&robots, &[1, 1], 31..100, metric), Some(70));
+        assert_eq!(
+            find_min_metric_serial(&robots, &[1, 1], 0..100, metric),
+            Some(30)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_find_min_metric_parallel() {
+        use crate::day14::find_min_metric_parallel;
+
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        let range = 0..cycle_length(&DIMENSIONS);
+        for metric in [
+            safety_factor,
+            |robots: &[Robot], dimensions: &[usize; 2], steps| {
+                variance_metric(robots, dimensions, steps) as usize
+            },
+        ] {
+            assert_eq!(
+                find_min_metric_parallel(&robots, &DIMENSIONS, range.clone(), metric),
+                find_min_metric_serial(&robots, &DIMENSIONS, range.clone(), metric)
+            );
+        }
+    }
+
+    #[test]
+    fn test_first_all_distinct_step() {
+        let robots = parse_input(&read_file_to_string("data/day14.txt")).unwrap();
+        assert_eq!(
+            first_all_distinct_step(&robots, &DIMENSIONS, cycle_length(&DIMENSIONS)),
+            Some(8270)
+        );
+        // Few robots on a small grid are often all apart, long before any
+        // picture.
+        let robots = parse_input(INPUT).expect("cannot parse");
+        assert_eq!(
+            first_all_distinct_step(&robots, &DIMENSIONS_SMALL, cycle_length(&DIMENSIONS_SMALL)),
+            Some(1)
+        );
+        // Starting positions beyond the last column wrap onto the first one,
+        // rather than onto the next row.
+        let robots = parse_input("p=3,0 v=0,0\np=0,1 v=0,0").unwrap();
+        assert_eq!(first_all_distinct_step(&robots, &[2, 3], 1), Some(0));
+        let robots = parse_input("p=3,0 v=0,0\np=0,0 v=0,0").unwrap();
+        assert_eq!(first_all_distinct_step(&robots, &[2, 3], 1), None);
+        // Robots that always share a cell are never all apart.
+        let robots = [Robot::new([1, 1], [1, 2]), Robot::new([1, 1], [1, 2])];
+        assert_eq!(
+            first_all_distinct_step(&robots, &DIMENSIONS_SMALL, 77),
+            None
+        );
+    }
+
+    #[test]
+    fn test_repeated_frame() {
+        // The first frame to repeat is the initial one, after a full cycle.
+        let robots = parse_input(INPUT).expect("cannot parse");
+        let mut frames = HashSet::new();
+        let repeat = (0..).find(|steps| {
+            let frame = Matrix::new(
+                occupancy(&robots, &DIMENSIONS_SMALL, *steps)
+                    .iter()
+                    .map(|row| row.iter().map(|count| *count > 0).collect())
+                    .collect(),
+            );
+            !frames.insert(frame)
+        });
+        assert_eq!(repeat, Some(cycle_length(&DIMENSIONS_SMALL)));
     }
 }
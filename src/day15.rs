@@ -1,32 +1,12 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
-use nom::{
-    character::complete::{line_ending, one_of},
-    error::Error,
-    multi::{count, fold_many1, separated_list1},
-    sequence::separated_pair,
-    Finish, IResult, Parser,
-};
+use nom::{combinator::all_consuming, Finish};
 
-use crate::util::{Coordinate, Matrix};
+use crate::util::{parse_cardinals, AocError, Cardinal, Coordinate, Matrix};
 
 #[derive(Debug)]
 pub struct CannotParseFromChar;
 
-impl TryFrom<char> for Cardinal {
-    type Error = CannotParseFromChar;
-
-    fn try_from(value: char) -> Result<Self, Self::Error> {
-        match value {
-            '^' => Ok(Self::North),
-            '>' => Ok(Self::East),
-            'v' => Ok(Self::South),
-            '<' => Ok(Self::West),
-            _ => Err(CannotParseFromChar),
-        }
-    }
-}
-
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Narrow {
     Robot,
@@ -35,6 +15,15 @@ pub enum Narrow {
     Package,
 }
 
+impl Cell for Narrow {
+    const EMPTY: Self = Narrow::Empty;
+    const WALL: Self = Narrow::Wall;
+
+    fn is_box(&self) -> bool {
+        *self == Narrow::Package
+    }
+}
+
 impl TryFrom<char> for Narrow {
     type Error = CannotParseFromChar;
 
@@ -64,63 +53,40 @@ impl Display for Narrow {
     }
 }
 
-fn parse_warehouse(input: &str) -> IResult<&str, Vec<Vec<Narrow>>> {
-    separated_list1(
-        line_ending,
-        fold_many1(one_of("@#.O"), Vec::new, |mut acc, c| {
-            acc.push(Narrow::try_from(c).expect("invalid char"));
-            acc
-        }),
-    )
-    .parse(input)
-}
-
-fn parse_directions(input: &str) -> IResult<&str, Vec<Cardinal>> {
-    fold_many1(
-        separated_list1(line_ending, one_of("^>v<")),
-        Vec::new,
-        |mut acc, line| unsafe {
-            acc.extend(
-                line.iter()
-                    .map(|c| Cardinal::try_from(*c).unwrap_unchecked()),
-            );
-            acc
-        },
-    )
-    .parse(input)
+#[derive(PartialEq, Debug)]
+pub struct Warehouse<W> {
+    robot: Coordinate,
+    matrix: Matrix<W>,
+    directions: Vec<Cardinal>,
+    i: usize,
+    /// The steps taken so far, only recorded after `enable_history`.
+    history: Option<Vec<StepRecord>>,
 }
 
-#[repr(u8)]
-#[derive(PartialEq, Debug, Clone, Copy, Eq)]
-pub enum Cardinal {
-    North = b'^',
-    East = b'>',
-    South = b'v',
-    West = b'<',
+/// Everything needed to undo a step: the robot's position before it and the
+/// packages (or package parts) that moved, as `(from, to)`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct StepRecord {
+    pub direction: Cardinal,
+    pub robot_from: Coordinate,
+    pub moved_boxes: Vec<(Coordinate, Coordinate)>,
 }
 
-const COORDINATE_NORTH: Coordinate = Coordinate { r: -1, c: 0 };
-const COORDINATE_EAST: Coordinate = Coordinate { r: 0, c: 1 };
-const COORDINATE_SOUTH: Coordinate = Coordinate { r: 1, c: 0 };
-const COORDINATE_WEST: Coordinate = Coordinate { r: 0, c: -1 };
+/// A cell of a warehouse map that can be cleared or block the robot.
+pub trait Cell: Copy {
+    const EMPTY: Self;
+    const WALL: Self;
 
-impl From<Cardinal> for Coordinate {
-    fn from(value: Cardinal) -> Self {
-        match value {
-            Cardinal::North => COORDINATE_NORTH,
-            Cardinal::East => COORDINATE_EAST,
-            Cardinal::South => COORDINATE_SOUTH,
-            Cardinal::West => COORDINATE_WEST,
-        }
-    }
+    /// Whether the cell holds a box, or the part of a box its position is
+    /// measured from.
+    fn is_box(&self) -> bool;
 }
 
-#[derive(PartialEq, Debug)]
-pub struct Warehouse<W> {
-    robot: Coordinate,
-    matrix: Matrix<W>,
-    directions: Vec<Cardinal>,
-    i: usize,
+/// A cell of a warehouse map whose boxes may span several cells of a row.
+pub trait Spanning: Cell + PartialEq {
+    /// The index of a box part within its box and the width of the box, or
+    /// `None` if the cell holds no box.
+    fn span(&self) -> Option<(usize, usize)>;
 }
 
 impl<W: Display> Display for Warehouse<W> {
@@ -139,89 +105,290 @@ impl<W: Display> Display for Warehouse<W> {
     }
 }
 
-impl Warehouse<Narrow> {
-    pub fn take_step(&mut self) -> Option<()> {
+/// Move the robot according to the next direction, if any are left.
+pub trait TakeStep {
+    /// Take the next step and summarize it.
+    fn step(&mut self) -> Option<StepSummary>;
+
+    fn take_step(&mut self) -> Option<()> {
+        self.step().map(|_| ())
+    }
+}
+
+/// The direction of a step, the position of the robot after it and the number
+/// of boxes it pushed.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct StepSummary {
+    pub direction: Cardinal,
+    pub robot: Coordinate,
+    pub pushed: usize,
+}
+
+/// An iterator that takes the steps of a warehouse, see `Warehouse::steps`.
+pub struct Steps<'a, W> {
+    warehouse: &'a mut Warehouse<W>,
+}
+
+impl<W> Iterator for Steps<'_, W>
+where
+    Warehouse<W>: TakeStep,
+{
+    type Item = StepSummary;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.warehouse.step()
+    }
+}
+
+impl<W> Warehouse<W> {
+    pub fn steps_remaining(&self) -> usize {
+        self.directions.len() - self.i
+    }
+
+    /// The direction of the next step, if any are left.
+    pub fn current_direction(&self) -> Option<Cardinal> {
+        self.directions.get(self.i).copied()
+    }
+
+    /// Record every following step, so it can be undone.
+    pub fn enable_history(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    /// The recorded steps, from the first to the last.
+    pub fn replay(&self) -> impl Iterator<Item = &StepRecord> {
+        self.history.iter().flatten()
+    }
+
+    /// The sum of the GPS coordinates, `100 * row + col`, of all cells that
+    /// count.
+    pub fn gps_sum(&self, counts: impl Fn(&W) -> bool) -> usize {
+        let mut sum = 0;
+        for row in self.matrix.row_range() {
+            for col in self.matrix.col_range() {
+                if counts(&self.matrix[row][col]) {
+                    sum += 100 * row + col;
+                }
+            }
+        }
+        sum
+    }
+
+    fn record(&mut self, record: impl FnOnce() -> StepRecord) {
+        if let Some(history) = self.history.as_mut() {
+            history.push(record());
+        }
+    }
+}
+
+impl<W: Cell> Warehouse<W> {
+    /// The positions of all boxes, by their left part for wide boxes, in
+    /// reading order.
+    pub fn box_positions(&self) -> Vec<Coordinate> {
+        let mut positions = Vec::new();
+        for row in self.matrix.row_range() {
+            for col in self.matrix.col_range() {
+                if self.matrix[row][col].is_box() {
+                    positions.push(Coordinate::new(row as isize, col as isize));
+                }
+            }
+        }
+        positions
+    }
+
+    /// The cell at a coordinate, where everything outside the map is a wall.
+    fn get(&self, coordinate: Coordinate) -> W {
+        match (usize::try_from(coordinate.r), usize::try_from(coordinate.c)) {
+            (Ok(row), Ok(col)) => self
+                .matrix
+                .get_element([row, col])
+                .copied()
+                .unwrap_or(W::WALL),
+            _ => W::WALL,
+        }
+    }
+
+    /// Undo the last recorded step, moving the packages and the robot back.
+    /// Returns `None` if there is no step to undo.
+    pub fn undo_step(&mut self) -> Option<()> {
+        let record = self.history.as_mut()?.pop()?;
+        let parts: Vec<W> = record
+            .moved_boxes
+            .iter()
+            .map(|(_, to)| self.matrix[to.r as usize][to.c as usize])
+            .collect();
+        for (_, to) in record.moved_boxes.iter() {
+            self.matrix[to.r as usize][to.c as usize] = W::EMPTY;
+        }
+        for ((from, _), part) in record.moved_boxes.iter().zip(parts) {
+            self.matrix[from.r as usize][from.c as usize] = part;
+        }
+        self.robot = record.robot_from;
+        self.i -= 1;
+        Some(())
+    }
+}
+
+impl<W> Warehouse<W>
+where
+    Self: TakeStep,
+{
+    /// Take the remaining steps one at a time, as an iterator.
+    pub fn steps(&mut self) -> Steps<'_, W> {
+        Steps { warehouse: self }
+    }
+
+    /// Take steps until no directions are left.
+    pub fn run(&mut self) {
+        self.run_with(|_, _| {});
+    }
+
+    /// Take steps until no directions are left, passing the index of every
+    /// step and the warehouse after it to an observer.
+    pub fn run_with(&mut self, mut observer: impl FnMut(usize, &Self)) {
+        while self.take_step().is_some() {
+            observer(self.i - 1, self);
+        }
+    }
+}
+
+impl TakeStep for Warehouse<Narrow> {
+    fn step(&mut self) -> Option<StepSummary> {
         if self.i < self.directions.len() {
             let direction = self.directions[self.i];
+            let robot_from = self.robot;
             let destination = self.robot + direction.into();
-            match self.matrix[destination.r as usize][destination.c as usize] {
-                Narrow::Empty => self.robot = destination,
-                Narrow::Wall => (),
+            let moved = match self.get(destination) {
+                Narrow::Empty => {
+                    self.robot = destination;
+                    None
+                }
+                Narrow::Wall => None,
                 Narrow::Robot => unreachable!(),
                 Narrow::Package => self.move_package(&destination, &direction),
-            }
+            };
+            // Only the first box moves, to the spot after the last one.
+            let pushed = moved.map_or(0, |(from, to)| {
+                from.r.abs_diff(to.r) + from.c.abs_diff(to.c)
+            });
+            self.record(|| StepRecord {
+                direction,
+                robot_from,
+                moved_boxes: moved.into_iter().collect(),
+            });
             self.i += 1;
-            Some(())
+            Some(StepSummary {
+                direction,
+                robot: self.robot,
+                pushed,
+            })
         } else {
             None
         }
     }
+}
 
-    /// Create an iter along the given axis and direction.
+impl Warehouse<Narrow> {
+    /// Walk from the given box along the direction, past all adjacent boxes.
     /// If it is unobstructed i.e., does not contain any walls before an empty
     /// spot, move the boxes. This can be done "smartly" by moving the first box
-    /// to the end and the robot the first spot.
-    fn move_package(&mut self, package: &Coordinate, towards: &Cardinal) {
-        let p = [package.r as usize, package.c as usize];
-        let iter: Box<dyn Iterator<Item = &Narrow>> = match towards {
-            Cardinal::North => Box::new(
-                self.matrix
-                    .col(p[1])
-                    .unwrap()
-                    .rev()
-                    .skip(self.matrix.shape()[0] - p[0]),
-            ),
-            Cardinal::South => Box::new(self.matrix.col(p[1]).unwrap().skip(p[0] + 1)),
-            Cardinal::East => Box::new(self.matrix.row(p[0]).unwrap().skip(p[1] + 1)),
-            Cardinal::West => Box::new(
-                self.matrix
-                    .row(p[0])
-                    .unwrap()
-                    .rev()
-                    .skip(self.matrix.shape()[1] - p[1]),
-            ),
-        };
-        let mut can_move_to = None;
-        for (i, spot) in iter.enumerate() {
-            match spot {
-                Narrow::Empty => {
-                    can_move_to = Some(i);
-                    break;
-                }
-                Narrow::Wall => {
-                    break;
-                }
-                Narrow::Package => {
-                    continue;
-                }
+    /// to the end and the robot the first spot. Returns where the first box
+    /// moved from and to, if it moved.
+    fn move_package(
+        &mut self,
+        package: &Coordinate,
+        towards: &Cardinal,
+    ) -> Option<(Coordinate, Coordinate)> {
+        let mut destination = *package + (*towards).into();
+        loop {
+            match self.get(destination) {
+                Narrow::Empty => break,
+                Narrow::Wall => return None,
+                Narrow::Package => destination = destination + (*towards).into(),
                 Narrow::Robot => unreachable!(),
             }
         }
-        if let Some(i) = can_move_to {
-            self.robot = self.robot + (*towards).into();
-            let destination = *package + Coordinate::from(*towards) * (i as isize + 1);
-            self.matrix[p[0]][p[1]] = Narrow::Empty;
-            self.matrix[destination.r as usize][destination.c as usize] = Narrow::Package;
-        }
+        self.robot = self.robot + (*towards).into();
+        self.matrix[package.r as usize][package.c as usize] = Narrow::Empty;
+        self.matrix[destination.r as usize][destination.c as usize] = Narrow::Package;
+        Some((*package, destination))
     }
 }
 
-pub fn parse_input(input: &str) -> Result<Warehouse<Narrow>, Error<&str>> {
-    let (input, (mut objects, directions)) =
-        separated_pair(parse_warehouse, count(line_ending, 2), parse_directions)
-            .parse(input)
-            .finish()?;
-    assert!(input.is_empty());
-
-    let mut robot = Coordinate::default();
-    'outer: for (r, row) in objects.iter_mut().enumerate() {
-        for (c, col) in row.iter_mut().enumerate() {
-            if *col == Narrow::Robot {
-                robot = Coordinate::new(r as isize, c as isize);
-                *col = Narrow::Empty;
-                break 'outer;
+/// Parse a map of the warehouse, enclosed by walls and holding exactly one
+/// robot, and the directions of the robot after a blank line. The directions
+/// may be wrapped over several lines, or be missing entirely.
+fn parse_warehouse<W>(input: &str) -> Result<Warehouse<W>, AocError>
+where
+    W: Cell + PartialEq + TryFrom<char>,
+{
+    let mut lines = input.lines().enumerate();
+    let mut objects: Vec<Vec<W>> = Vec::new();
+    let mut robot = None;
+    for (i, line) in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        let mut row = Vec::with_capacity(line.len());
+        for (j, char) in line.chars().enumerate() {
+            if char == '@' {
+                if robot.is_some() {
+                    return Err(AocError::Duplicate {
+                        what: "robot",
+                        line: i + 1,
+                        col: j + 1,
+                    });
+                }
+                robot = Some(Coordinate::new(i as isize, j as isize));
+                row.push(W::EMPTY);
+                continue;
             }
+            row.push(W::try_from(char).map_err(|_| AocError::InvalidCharacter {
+                line: i + 1,
+                col: j + 1,
+                char,
+            })?);
         }
+        if let Some(first) = objects.first() {
+            if row.len() != first.len() {
+                return Err(AocError::RaggedRow {
+                    line: i + 1,
+                    len: row.len(),
+                    expected: first.len(),
+                });
+            }
+        }
+        objects.push(row);
+    }
+    let robot = robot.ok_or(AocError::Missing("robot"))?;
+    // The puzzle always encloses the map in walls, anything else is likely a
+    // truncated input.
+    let [rows, cols] = [objects.len(), objects[0].len()];
+    for (r, row) in objects.iter().enumerate() {
+        for (c, object) in row.iter().enumerate() {
+            let is_border = r == 0 || r == rows - 1 || c == 0 || c == cols - 1;
+            if is_border && *object != W::WALL {
+                return Err(AocError::OpenBorder {
+                    line: r + 1,
+                    col: c + 1,
+                });
+            }
+        }
+    }
+
+    let mut section: Option<(usize, Vec<&str>)> = None;
+    for (i, line) in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+        section.get_or_insert((i, Vec::new())).1.push(line);
+    }
+    let directions = match section {
+        Some((first, section)) => parse_directions(first, &section.join("\n"))?,
+        None => Vec::new(),
+    };
+    if let Some((i, _)) = lines.find(|(_, line)| !line.trim().is_empty()) {
+        return Err(AocError::TrailingInput { line: i + 1 });
     }
 
     Ok(Warehouse {
@@ -229,20 +396,45 @@ pub fn parse_input(input: &str) -> Result<Warehouse<Narrow>, Error<&str>> {
         matrix: Matrix::new(objects),
         directions,
         i: 0,
+        history: None,
     })
 }
 
-pub fn part_1(warehouse: &mut Warehouse<Narrow>) -> usize {
-    while warehouse.take_step().is_some() {}
-    let mut sum = 0;
-    for row in warehouse.matrix.row_range() {
-        for col in warehouse.matrix.col_range() {
-            if warehouse.matrix[row][col] == Narrow::Package {
-                sum += 100 * row + col;
+/// Parse the lines of directions that start at a zero-based line of the input,
+/// joined by `\n`.
+fn parse_directions(first: usize, section: &str) -> Result<Vec<Cardinal>, AocError> {
+    let (_, directions) = all_consuming(parse_cardinals)(section)
+        .finish()
+        .map_err(|error| {
+            // Parsing stops before the line ending of a line with an invalid
+            // character at its start.
+            let rest = error.input.trim_start_matches('\n');
+            let before = &section[..section.len() - rest.len()];
+            AocError::InvalidCharacter {
+                line: first + before.matches('\n').count() + 1,
+                col: before.rsplit('\n').next().unwrap_or("").chars().count() + 1,
+                char: rest.chars().next().expect("parsing failed before the end"),
             }
-        }
+        })?;
+    Ok(directions)
+}
+
+impl FromStr for Warehouse<Narrow> {
+    type Err = AocError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_warehouse(s)
     }
-    sum
+}
+
+/// Parse a warehouse and the directions of the robot, see `Warehouse::from_str`.
+pub fn parse_input(input: &str) -> Result<Warehouse<Narrow>, AocError> {
+    input.parse()
+}
+
+pub fn part_1(warehouse: &mut Warehouse<Narrow>) -> usize {
+    warehouse.run();
+    warehouse.gps_sum_narrow()
 }
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -253,6 +445,39 @@ pub enum Wide {
     PackageRight,
 }
 
+impl TryFrom<char> for Wide {
+    type Error = CannotParseFromChar;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value {
+            '#' => Ok(Self::Wall),
+            '.' => Ok(Self::Empty),
+            '[' => Ok(Self::PackageLeft),
+            ']' => Ok(Self::PackageRight),
+            _ => Err(CannotParseFromChar),
+        }
+    }
+}
+
+impl Cell for Wide {
+    const EMPTY: Self = Wide::Empty;
+    const WALL: Self = Wide::Wall;
+
+    fn is_box(&self) -> bool {
+        *self == Wide::PackageLeft
+    }
+}
+
+impl Spanning for Wide {
+    fn span(&self) -> Option<(usize, usize)> {
+        match self {
+            Wide::PackageLeft => Some((0, 2)),
+            Wide::PackageRight => Some((1, 2)),
+            Wide::Empty | Wide::Wall => None,
+        }
+    }
+}
+
 impl Display for Wide {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let c = match self {
@@ -265,24 +490,145 @@ impl Display for Wide {
     }
 }
 
-fn matrix_to_wide_matrix(matrix: &Matrix<Narrow>) -> Matrix<Wide> {
-    let mut vec: Vec<Vec<Wide>> = Vec::with_capacity(matrix.shape()[0]);
+/// A cell of a warehouse scaled up `width` times, where every box spans
+/// `width` cells and knows which of its parts it is.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum WideK {
+    Wall,
+    Empty,
+    Package { part: usize, width: usize },
+}
+
+impl Cell for WideK {
+    const EMPTY: Self = WideK::Empty;
+    const WALL: Self = WideK::Wall;
+
+    fn is_box(&self) -> bool {
+        matches!(self, WideK::Package { part: 0, .. })
+    }
+}
+
+impl Spanning for WideK {
+    fn span(&self) -> Option<(usize, usize)> {
+        match self {
+            WideK::Package { part, width } => Some((*part, *width)),
+            WideK::Empty | WideK::Wall => None,
+        }
+    }
+}
+
+impl Display for WideK {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let c = match self {
+            WideK::Empty => '.',
+            WideK::Wall => '#',
+            WideK::Package { width: 1, .. } => 'O',
+            WideK::Package { part: 0, .. } => '[',
+            WideK::Package { part, width } if *part == width - 1 => ']',
+            WideK::Package { .. } => '=',
+        };
+        write!(f, "{c}")
+    }
+}
+
+fn widen_matrix(matrix: &Matrix<Narrow>, k: usize) -> Matrix<WideK> {
+    let mut vec: Vec<Vec<WideK>> = Vec::with_capacity(matrix.shape()[0]);
     for row in matrix.row_iter() {
-        let mut new_row: Vec<Wide> = Vec::with_capacity(matrix.shape()[1]);
+        let mut new_row: Vec<WideK> = Vec::with_capacity(matrix.shape()[1] * k);
         for col in row {
-            let arr = match col {
-                Narrow::Empty => [Wide::Empty; 2],
-                Narrow::Wall => [Wide::Wall; 2],
-                Narrow::Package => [Wide::PackageLeft, Wide::PackageRight],
+            match col {
+                Narrow::Empty => new_row.extend([WideK::Empty].repeat(k)),
+                Narrow::Wall => new_row.extend([WideK::Wall].repeat(k)),
+                Narrow::Package => {
+                    new_row.extend((0..k).map(|part| WideK::Package { part, width: k }))
+                }
                 Narrow::Robot => unreachable!(),
-            };
-            new_row.extend(arr.iter());
+            }
         }
         vec.push(new_row)
     }
     Matrix::new(vec)
 }
 
+fn matrix_to_wide_matrix(matrix: &Matrix<Narrow>) -> Matrix<Wide> {
+    let wide = widen_matrix(matrix, 2);
+    Matrix::new(
+        wide.row_iter()
+            .map(|row| {
+                row.map(|cell| match cell {
+                    WideK::Wall => Wide::Wall,
+                    WideK::Empty => Wide::Empty,
+                    WideK::Package { part: 0, .. } => Wide::PackageLeft,
+                    WideK::Package { .. } => Wide::PackageRight,
+                })
+                .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Scale a warehouse up horizontally `k` times: every cell becomes `k` cells
+/// and every box a box spanning `k` cells. The robot keeps its size and stands
+/// on the first cell of its original one.
+///
+/// # Panics
+/// If `k` is zero.
+pub fn widen(warehouse: Warehouse<Narrow>, k: usize) -> Warehouse<WideK> {
+    assert!(k > 0, "cannot widen a warehouse zero times");
+    Warehouse {
+        robot: Coordinate::new(warehouse.robot.r, warehouse.robot.c * k as isize),
+        matrix: widen_matrix(&warehouse.matrix, k),
+        directions: warehouse.directions,
+        i: 0,
+        history: None,
+    }
+}
+
+impl Warehouse<Narrow> {
+    pub fn gps_sum_narrow(&self) -> usize {
+        self.gps_sum(Narrow::is_box)
+    }
+}
+
+impl Warehouse<Wide> {
+    pub fn gps_sum_wide(&self) -> usize {
+        self.gps_sum(Wide::is_box)
+    }
+}
+
+impl Warehouse<WideK> {
+    pub fn gps_sum_wide_k(&self) -> usize {
+        self.gps_sum(WideK::is_box)
+    }
+}
+
+impl FromStr for Warehouse<Wide> {
+    type Err = AocError;
+
+    /// Parse a warehouse that is already scaled up, with boxes drawn as `[]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let warehouse: Warehouse<Wide> = parse_warehouse(s)?;
+        for (r, row) in warehouse.matrix.iter().enumerate() {
+            for (c, object) in row.iter().enumerate() {
+                let [left, right] = [c.checked_sub(1).map(|c| row[c]), row.get(c + 1).copied()];
+                let unmatched = match object {
+                    Wide::PackageLeft => right != Some(Wide::PackageRight),
+                    Wide::PackageRight => left != Some(Wide::PackageLeft),
+                    Wide::Empty | Wide::Wall => false,
+                };
+                if unmatched {
+                    return Err(AocError::InvalidCharacter {
+                        line: r + 1,
+                        col: c + 1,
+                        char: object.to_string().chars().next().unwrap(),
+                    });
+                }
+            }
+        }
+        Ok(warehouse)
+    }
+}
+
 impl From<Warehouse<Narrow>> for Warehouse<Wide> {
     fn from(value: Warehouse<Narrow>) -> Self {
         Self {
@@ -290,126 +636,125 @@ impl From<Warehouse<Narrow>> for Warehouse<Wide> {
             matrix: matrix_to_wide_matrix(&value.matrix),
             directions: value.directions,
             i: 0,
+            history: None,
         }
     }
 }
 
-impl Warehouse<Wide> {
-    /// Create a graph that connects every box part (left and right) to:
-    /// - it's neighboring part
+impl<W: Spanning> Warehouse<W> {
+    /// Create a graph that connects every box part to:
+    /// - the other parts of its box, found from its span
     /// - the box part directly adjacent along the movement axis and direction.
     ///
     /// Compute a BFS along this tree, tracking which box parts might need to be
-    /// moved. If no walls are encountered, make all moves on a matrix copy (it
-    /// might be possible without allocating as well). Otherwise, abort the
-    /// search, clear the stack and list of moves.
+    /// moved. If a wall is encountered, abort the search, clear the stack and
+    /// list of moves. The moves are made in place by `step`: it reads every
+    /// part first, then clears their cells and finally writes each part one
+    /// spot further, so no part overwrites another that has yet to move.
     fn move_package(&mut self, package: Coordinate, direction: &Cardinal) -> Vec<Coordinate> {
         let mut moves = Vec::<Coordinate>::new();
         let mut stack = Vec::<Coordinate>::new();
         let mut visited = Matrix::new_like(&self.matrix, false);
         stack.push(package);
         while let Some(next_package) = stack.pop() {
-            let [row, col] = [next_package.r as usize, next_package.c as usize];
-            if visited[row][col] {
-                continue;
-            }
-            let package_l_r = match self.matrix[row][col] {
-                Wide::PackageLeft => {
-                    stack.push(next_package.east());
-                    [next_package, next_package.east()]
-                }
-                Wide::PackageRight => {
-                    stack.push(next_package.west());
-                    [next_package.west(), next_package]
-                }
-                Wide::Empty | Wide::Wall => unreachable!(),
-            };
-            for package_part in package_l_r {
+            let (part, width) = self.matrix[next_package.r as usize][next_package.c as usize]
+                .span()
+                .expect("only box parts are pushed");
+            let first = next_package.c - part as isize;
+            for package_part in
+                (first..first + width as isize).map(|c| Coordinate::new(next_package.r, c))
+            {
                 if visited[package_part.r as usize][package_part.c as usize] {
                     continue;
                 } else {
                     visited[package_part.r as usize][package_part.c as usize] = true;
                 }
-                let destination = match direction {
-                    Cardinal::North => package_part.north(),
-                    Cardinal::East => package_part.east(),
-                    Cardinal::South => package_part.south(),
-                    Cardinal::West => package_part.west(),
-                };
-                match self.matrix[destination.r as usize][destination.c as usize] {
-                    Wide::Empty => moves.push(package_part),
-                    Wide::Wall => {
-                        moves.clear();
-                        stack.clear();
-                        break;
-                    }
-                    Wide::PackageLeft | Wide::PackageRight => {
-                        moves.push(package_part);
-                        stack.push(destination);
-                    }
+                let destination = package_part + (*direction).into();
+                let cell = self.get(destination);
+                if cell.span().is_some() {
+                    moves.push(package_part);
+                    stack.push(destination);
+                } else if cell == W::EMPTY {
+                    moves.push(package_part);
+                } else {
+                    moves.clear();
+                    stack.clear();
+                    break;
                 }
             }
         }
         moves
     }
+}
 
-    fn take_step(&mut self) -> Option<()> {
+impl<W: Spanning> TakeStep for Warehouse<W> {
+    fn step(&mut self) -> Option<StepSummary> {
         if self.i >= self.directions.len() {
             return None;
         }
         let direction = self.directions[self.i];
+        let robot_from = self.robot;
         let destination = self.robot + direction.into();
         let mut packages = Vec::new();
-        match self.matrix[destination.r as usize][destination.c as usize] {
-            Wide::Empty => self.robot = destination,
-            Wide::Wall => (),
-            Wide::PackageLeft | Wide::PackageRight => {
-                packages = self.move_package(destination, &direction)
-            }
+        let mut pushed = 0;
+        let cell = self.get(destination);
+        if cell.span().is_some() {
+            packages = self.move_package(destination, &direction);
+        } else if cell == W::EMPTY {
+            self.robot = destination;
         }
         if !packages.is_empty() {
             self.robot = destination;
-            let mut copy = Matrix::new(self.matrix.clone());
+            // Read every package part before clearing any of them, as parts
+            // can move into a spot another part moves out of.
+            let moved: Vec<(Coordinate, W)> = packages
+                .iter()
+                .map(|package| {
+                    (
+                        *package + direction.into(),
+                        self.matrix[package.r as usize][package.c as usize],
+                    )
+                })
+                .collect();
+            // All parts of every box moved, count each box by one part.
+            pushed = moved.iter().filter(|(_, part)| part.is_box()).count();
             for package in packages.iter() {
-                copy[package.r as usize][package.c as usize] = Wide::Empty;
+                self.matrix[package.r as usize][package.c as usize] = W::EMPTY;
             }
-            for package in packages.iter() {
-                let dest = match direction {
-                    Cardinal::North => package.north(),
-                    Cardinal::East => package.east(),
-                    Cardinal::South => package.south(),
-                    Cardinal::West => package.west(),
-                };
-                copy[dest.r as usize][dest.c as usize] =
-                    self.matrix[package.r as usize][package.c as usize];
+            for (dest, part) in moved {
+                self.matrix[dest.r as usize][dest.c as usize] = part;
             }
-            self.matrix = copy;
         }
+        self.record(|| StepRecord {
+            direction,
+            robot_from,
+            moved_boxes: packages
+                .iter()
+                .map(|package| (*package, *package + direction.into()))
+                .collect(),
+        });
         self.i += 1;
-        Some(())
+        Some(StepSummary {
+            direction,
+            robot: self.robot,
+            pushed,
+        })
     }
 }
 
 pub fn part_2(warehouse: &mut Warehouse<Wide>) -> usize {
-    while warehouse.take_step().is_some() {}
-    let mut sum = 0;
-    for row in warehouse.matrix.row_range() {
-        for col in warehouse.matrix.col_range() {
-            if warehouse.matrix[row][col] == Wide::PackageLeft {
-                sum += 100 * row + col;
-            }
-        }
-    }
-    sum
+    warehouse.run();
+    warehouse.gps_sum_wide()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
         day15::{
-            matrix_to_wide_matrix, parse_input, part_1, part_2, Cardinal, Narrow, Warehouse, Wide,
+            matrix_to_wide_matrix, parse_input, part_1, part_2, widen, Narrow, StepRecord,
+            StepSummary, TakeStep, Warehouse, Wide, WideK,
         },
-        util::{read_file_to_string, Coordinate, Matrix},
+        util::{read_file_to_string, AocError, Cardinal, Coordinate, Matrix},
     };
 
     const INPUT: &str = "########
@@ -510,11 +855,86 @@ mod tests {
                     Cardinal::West,
                     Cardinal::West
                 ],
-                i: 0
+                i: 0,
+                history: None,
             }
         )
     }
 
+    #[test]
+    fn test_parse_input_invalid() {
+        assert_eq!(
+            parse_input("####\n#..#\n####\n\n<"),
+            Err(AocError::Missing("robot"))
+        );
+        assert_eq!(
+            parse_input("####\n#@@#\n####\n\n<"),
+            Err(AocError::Duplicate {
+                what: "robot",
+                line: 2,
+                col: 3
+            })
+        );
+        assert_eq!(
+            parse_input("####\n#@.#\n###\n\n<"),
+            Err(AocError::RaggedRow {
+                line: 3,
+                len: 3,
+                expected: 4
+            })
+        );
+        assert_eq!(
+            parse_input("####\n#@.#\n####\n\n<>\n\n^"),
+            Err(AocError::TrailingInput { line: 7 })
+        );
+        assert_eq!(
+            parse_input("####\n#@..\n####\n\n<"),
+            Err(AocError::OpenBorder { line: 2, col: 4 })
+        );
+        assert_eq!(
+            parse_input("####\n#@x#\n####\n\n<"),
+            Err(AocError::InvalidCharacter {
+                line: 2,
+                col: 3,
+                char: 'x'
+            })
+        );
+        assert_eq!(
+            parse_input("####\n#@.#\n####\n\n<x"),
+            Err(AocError::InvalidCharacter {
+                line: 5,
+                col: 2,
+                char: 'x'
+            })
+        );
+        assert_eq!(
+            parse_input("####\n#@.#\n####\n\n<>\n^v\nx<"),
+            Err(AocError::InvalidCharacter {
+                line: 7,
+                col: 1,
+                char: 'x'
+            })
+        );
+        assert_eq!(
+            parse_input("####\n#@.#\n####\n\n<>\r\n^ "),
+            Err(AocError::InvalidCharacter {
+                line: 6,
+                col: 2,
+                char: ' '
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_input_no_directions() {
+        // A warehouse without directions is valid, the robot never moves.
+        for input in ["####\n#@O#\n####", "####\n#@O#\n####\n\n"] {
+            let mut warehouse = parse_input(input).expect("input is valid");
+            assert_eq!(warehouse.steps_remaining(), 0);
+            assert_eq!(part_1(&mut warehouse), 102);
+        }
+    }
+
     #[test]
     fn test_part_1_small() {
         assert_eq!(part_1(&mut parse_input(INPUT).expect("cannot read")), 2028);
@@ -689,4 +1109,347 @@ v^^>>><<^^<>>^v^<v^vv<>v^<<>^<^v^v><^<<<><<^<v><v<>vv>>v><v^<vv<>v^<<^";
             1425169
         )
     }
+
+    #[test]
+    fn test_push_stacked_packages() {
+        let height = 200;
+        let mut matrix = vec![vec![Wide::Wall; 6]];
+        matrix.push(vec![
+            Wide::Wall,
+            Wide::Empty,
+            Wide::Empty,
+            Wide::Empty,
+            Wide::Empty,
+            Wide::Wall,
+        ]);
+        for _ in 0..height {
+            matrix.push(vec![
+                Wide::Wall,
+                Wide::Empty,
+                Wide::PackageLeft,
+                Wide::PackageRight,
+                Wide::Empty,
+                Wide::Wall,
+            ]);
+        }
+        matrix.push(vec![
+            Wide::Wall,
+            Wide::Empty,
+            Wide::Empty,
+            Wide::Empty,
+            Wide::Empty,
+            Wide::Wall,
+        ]);
+        matrix.push(vec![Wide::Wall; 6]);
+        let mut warehouse = Warehouse {
+            robot: Coordinate::new(height as isize + 2, 3),
+            matrix: Matrix::new(matrix),
+            directions: vec![Cardinal::North; 2],
+            i: 0,
+            history: None,
+        };
+        // The first push moves the whole column, the second one hits the wall.
+        assert_eq!(warehouse.take_step(), Some(()));
+        assert_eq!(warehouse.robot, Coordinate::new(height as isize + 1, 3));
+        for row in 1..=height {
+            assert_eq!(
+                warehouse.matrix[row][2..4],
+                [Wide::PackageLeft, Wide::PackageRight]
+            );
+        }
+        assert_eq!(
+            warehouse.matrix[height + 1][2..4],
+            [Wide::Empty, Wide::Empty]
+        );
+        assert_eq!(warehouse.take_step(), Some(()));
+        assert_eq!(warehouse.robot, Coordinate::new(height as isize + 1, 3));
+        assert_eq!(
+            warehouse.matrix[1][2..4],
+            [Wide::PackageLeft, Wide::PackageRight]
+        );
+        assert_eq!(warehouse.take_step(), None);
+    }
+
+    #[test]
+    fn test_run_with() {
+        let mut warehouse = parse_input(INPUT).unwrap();
+        assert_eq!(warehouse.steps_remaining(), 15);
+        assert_eq!(warehouse.current_direction(), Some(Cardinal::West));
+        let mut snapshots = Vec::new();
+        warehouse.run_with(|i, warehouse| {
+            if i < 3 {
+                snapshots.push(warehouse.to_string());
+            }
+        });
+        let blocked = "########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+";
+        let moved = "########
+#.@O.O.#
+##..O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+";
+        assert_eq!(snapshots, vec![blocked, moved, moved]);
+        assert_eq!(warehouse.steps_remaining(), 0);
+        assert_eq!(warehouse.current_direction(), None);
+        assert_eq!(warehouse.take_step(), None);
+    }
+
+    #[test]
+    fn test_undo_step() {
+        let mut fresh = parse_input(INPUT).unwrap();
+        fresh.enable_history();
+        let mut warehouse = parse_input(INPUT).unwrap();
+        assert_eq!(warehouse.undo_step(), None);
+        warehouse.enable_history();
+        warehouse.run();
+        assert_eq!(warehouse.replay().count(), 15);
+        // The first step is blocked by a wall, the second one moves freely.
+        let records: Vec<_> = warehouse.replay().take(2).cloned().collect();
+        assert_eq!(
+            records[0],
+            StepRecord {
+                direction: Cardinal::West,
+                robot_from: Coordinate::new(2, 2),
+                moved_boxes: vec![],
+            }
+        );
+        assert_eq!(records[1].robot_from, Coordinate::new(2, 2));
+        while warehouse.undo_step().is_some() {}
+        assert_eq!(warehouse, fresh);
+
+        let mut fresh: Warehouse<Wide> = parse_input(INPUT_MEDIUM).unwrap().into();
+        fresh.enable_history();
+        let mut warehouse: Warehouse<Wide> = parse_input(INPUT_MEDIUM).unwrap().into();
+        warehouse.enable_history();
+        warehouse.run();
+        assert!(warehouse
+            .replay()
+            .any(|record| record.moved_boxes.len() > 2));
+        while warehouse.undo_step().is_some() {}
+        assert_eq!(warehouse, fresh);
+    }
+
+    /// A warehouse without the walls around it, which `parse_input` rejects.
+    fn borderless(map: &str, directions: Vec<Cardinal>) -> Warehouse<Narrow> {
+        let mut robot = Coordinate::default();
+        let matrix = map
+            .lines()
+            .enumerate()
+            .map(|(r, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(|(c, char)| match Narrow::try_from(char).unwrap() {
+                        Narrow::Robot => {
+                            robot = Coordinate::new(r as isize, c as isize);
+                            Narrow::Empty
+                        }
+                        object => object,
+                    })
+                    .collect()
+            })
+            .collect();
+        Warehouse {
+            robot,
+            matrix: Matrix::new(matrix),
+            directions,
+            i: 0,
+            history: None,
+        }
+    }
+
+    #[test]
+    fn test_borderless() {
+        use Cardinal::*;
+        // Boxes against the edge can not be pushed off the map.
+        let mut warehouse = borderless(".O.\nO@O\n.O.", vec![North, East, South, West]);
+        warehouse.run();
+        assert_eq!(warehouse.to_string(), ".O.\nO@O\n.O.\n");
+        // Boxes are pushed up to the edge, after which the robot is blocked.
+        let mut warehouse = borderless("...\n.@O\n...", vec![West, West, East, East, East]);
+        warehouse.run();
+        assert_eq!(warehouse.to_string(), "...\n.@O\n...\n");
+        let mut warehouse = borderless("...\n@O.\n...", vec![East, East, North, North]);
+        warehouse.run();
+        assert_eq!(warehouse.to_string(), ".@.\n..O\n...\n");
+
+        let mut warehouse: Warehouse<Wide> = borderless(
+            ".O.\nO@O\n.O.",
+            vec![North, East, East, South, West, West, West],
+        )
+        .into();
+        warehouse.run();
+        assert_eq!(warehouse.to_string(), "..[]..\n[]@.[]\n..[]..\n");
+        let mut warehouse: Warehouse<Wide> = borderless("@O.", vec![East; 4]).into();
+        warehouse.run();
+        assert_eq!(warehouse.to_string(), "...@[]\n");
+    }
+
+    #[test]
+    fn test_gps_sum() {
+        let mut warehouse = parse_input(INPUT).unwrap();
+        warehouse.run();
+        assert_eq!(warehouse.gps_sum_narrow(), 2028);
+        let mut warehouse: Warehouse<Wide> = parse_input(INPUT_MEDIUM).unwrap().into();
+        warehouse.run();
+        assert_eq!(warehouse.gps_sum_wide(), 9021);
+
+        // 100 * 1 + 1 and 100 * 2 + 3.
+        let warehouse = parse_input("#####\n#O.@#\n#..O#\n#####").unwrap();
+        assert_eq!(
+            warehouse.box_positions(),
+            vec![Coordinate::new(1, 1), Coordinate::new(2, 3)]
+        );
+        assert_eq!(warehouse.gps_sum_narrow(), 101 + 203);
+        // The robot stands on an empty cell.
+        assert_eq!(
+            warehouse.gps_sum(|cell| *cell == Narrow::Empty),
+            102 + 103 + 201 + 202
+        );
+        // Only the left part of a wide box counts: 100 * 1 + 2 and 100 * 2 + 6.
+        let warehouse: Warehouse<Wide> = warehouse.into();
+        assert_eq!(
+            warehouse.box_positions(),
+            vec![Coordinate::new(1, 2), Coordinate::new(2, 6)]
+        );
+        assert_eq!(warehouse.gps_sum_wide(), 102 + 206);
+    }
+
+    #[test]
+    fn test_from_str_round_trip() {
+        let directions = INPUT.split("\n\n").nth(1).unwrap();
+        let warehouse: Warehouse<Narrow> = INPUT.parse().expect("input is valid");
+        let reparsed: Warehouse<Narrow> = format!("{warehouse}\n{directions}")
+            .parse()
+            .expect("input is valid");
+        assert_eq!(reparsed, warehouse);
+
+        let directions = INPUT_MEDIUM.split("\n\n").nth(1).unwrap();
+        let warehouse: Warehouse<Wide> = parse_input(INPUT_MEDIUM).unwrap().into();
+        let mut reparsed: Warehouse<Wide> = format!("{warehouse}\n{directions}")
+            .parse()
+            .expect("input is valid");
+        assert_eq!(reparsed.matrix, warehouse.matrix);
+        assert_eq!(reparsed.robot, warehouse.robot);
+        assert_eq!(reparsed.directions, warehouse.directions);
+        assert_eq!(part_2(&mut reparsed), 9021);
+    }
+
+    #[test]
+    fn test_widen_matches_wide() {
+        for input in [
+            INPUT_MEDIUM.to_string(),
+            read_file_to_string("data/day15.txt"),
+        ] {
+            let mut wide: Warehouse<Wide> = parse_input(&input).unwrap().into();
+            let mut wide_k = widen(parse_input(&input).unwrap(), 2);
+            assert_eq!(wide_k.to_string(), wide.to_string());
+            while let Some(summary) = wide.step() {
+                assert_eq!(wide_k.step(), Some(summary));
+            }
+            assert_eq!(wide_k.step(), None);
+            assert_eq!(wide_k.to_string(), wide.to_string());
+            assert_eq!(wide_k.gps_sum_wide_k(), wide.gps_sum_wide());
+        }
+        let mut wide_k = widen(parse_input(INPUT_MEDIUM).unwrap(), 2);
+        wide_k.run();
+        assert_eq!(wide_k.gps_sum_wide_k(), 9021);
+        let mut wide_k = widen(
+            parse_input(&read_file_to_string("data/day15.txt")).unwrap(),
+            2,
+        );
+        wide_k.run();
+        assert_eq!(wide_k.gps_sum_wide_k(), 1425169);
+    }
+
+    #[test]
+    fn test_widen_triple() {
+        let input = "######\n#....#\n#.OO.#\n#..@.#\n######\n\n^<<<<^>>>";
+        let mut warehouse = widen(parse_input(input).unwrap(), 3);
+        assert_eq!(
+            warehouse.to_string(),
+            "##################
+###............###
+###...[=][=]...###
+###......@.....###
+##################
+"
+        );
+        assert_eq!(warehouse.matrix[2][7], WideK::Package { part: 1, width: 3 });
+        let pushed: Vec<usize> = warehouse.steps().map(|summary| summary.pushed).collect();
+        // The first step pushes the right box up, the next three push the left
+        // box against the wall and the fourth is blocked by it. The robot then
+        // walks around and pushes the right box to the right.
+        assert_eq!(pushed, [1, 1, 1, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(
+            warehouse.to_string(),
+            "##################
+###......@[=]..###
+###[=].........###
+###............###
+##################
+"
+        );
+        assert_eq!(warehouse.gps_sum_wide_k(), 110 + 203);
+    }
+
+    #[test]
+    fn test_from_str_wide_invalid() {
+        assert_eq!(
+            "######\n#@[.]#\n######".parse::<Warehouse<Wide>>(),
+            Err(AocError::InvalidCharacter {
+                line: 2,
+                col: 3,
+                char: '['
+            })
+        );
+        assert_eq!(
+            "######\n#@O..#\n######".parse::<Warehouse<Wide>>(),
+            Err(AocError::InvalidCharacter {
+                line: 2,
+                col: 3,
+                char: 'O'
+            })
+        );
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut warehouse = parse_input(INPUT).unwrap();
+        let summaries: Vec<StepSummary> = warehouse.steps().collect();
+        assert_eq!(summaries.len(), 15);
+        assert_eq!(
+            summaries[0],
+            StepSummary {
+                direction: Cardinal::West,
+                robot: Coordinate::new(2, 2),
+                pushed: 0
+            }
+        );
+        // The walkthrough pushes 1, 2, 4, 1, 1 and 1 boxes.
+        let pushes: Vec<usize> = summaries
+            .iter()
+            .map(|summary| summary.pushed)
+            .filter(|pushed| *pushed > 0)
+            .collect();
+        assert_eq!(pushes, vec![1, 2, 4, 1, 1, 1]);
+        assert_eq!(warehouse.steps().next(), None);
+        assert_eq!(warehouse.gps_sum_narrow(), 2028);
+
+        let mut warehouse: Warehouse<Wide> = parse_input(INPUT_MEDIUM).unwrap().into();
+        assert_eq!(warehouse.steps().take(5).count(), 5);
+        assert_eq!(warehouse.steps_remaining(), 700 - 5);
+        warehouse.steps().for_each(drop);
+        assert_eq!(warehouse.gps_sum_wide(), 9021);
+    }
 }
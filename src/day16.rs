@@ -1,16 +1,22 @@
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::{
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt::Display,
+};
 
-use crate::util::{Cardinal, Coordinate, Matrix};
+use crate::util::{AocError, Cardinal, Coordinate, Matrix};
 
 #[derive(PartialEq, Debug)]
 pub struct Maze {
     pub matrix: Matrix<bool>,
     start: Coordinate,
-    end: Coordinate,
-    direction: Cardinal,
+    ends: Vec<Coordinate>,
 }
 
+/// The direction the reindeer faces at the start, unless told otherwise.
+pub const START_DIRECTION: Cardinal = Cardinal::East;
+
 #[repr(u8)]
+#[derive(Clone, Copy)]
 enum MazeChar {
     Vacant = b'.',
     Wall = b'#',
@@ -18,62 +24,182 @@ enum MazeChar {
     End = b'E',
 }
 
+impl MazeChar {
+    const ALL: [MazeChar; 4] = [
+        MazeChar::Vacant,
+        MazeChar::Wall,
+        MazeChar::Start,
+        MazeChar::End,
+    ];
+}
+
 impl TryFrom<u8> for MazeChar {
     type Error = ();
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            x if x == MazeChar::Vacant as u8 => Ok(MazeChar::Vacant),
-            x if x == MazeChar::Wall as u8 => Ok(MazeChar::Wall),
-            x if x == MazeChar::Start as u8 => Ok(MazeChar::Start),
-            x if x == MazeChar::End as u8 => Ok(MazeChar::End),
-            _ => Err(()),
+        MazeChar::ALL
+            .into_iter()
+            .find(|maze_char| *maze_char as u8 == value)
+            .ok_or(())
+    }
+}
+
+impl Maze {
+    /// A maze with at least one end. The start and ends should all be vacant
+    /// tiles.
+    pub fn new(
+        matrix: Matrix<bool>,
+        start: Coordinate,
+        ends: Vec<Coordinate>,
+    ) -> Result<Self, AocError> {
+        if ends.is_empty() {
+            return Err(AocError::Missing("end"));
         }
+        let maze = Maze {
+            matrix,
+            start,
+            ends,
+        };
+        let markers =
+            std::iter::once(("start", &maze.start)).chain(maze.ends.iter().map(|end| ("end", end)));
+        for (what, coord) in markers {
+            if !maze.is_vacant(coord) {
+                return Err(AocError::Blocked {
+                    what,
+                    line: coord.r as usize + 1,
+                    col: coord.c as usize + 1,
+                });
+            }
+        }
+        Ok(maze)
     }
 }
 
-pub fn parse_input(input: &str) -> Maze {
+pub fn parse_input(input: &str) -> Result<Maze, AocError> {
     let mut start: Option<Coordinate> = None;
-    let mut end: Option<Coordinate> = None;
+    let mut ends = vec![];
 
-    let mut rows = vec![];
+    let mut rows: Vec<Vec<bool>> = vec![];
     for (r, line) in input.lines().enumerate() {
-        let mut row = vec![false; line.len()];
-        for (c, byte) in line.bytes().enumerate() {
-            if byte != MazeChar::Wall as u8 {
-                row[c] = true;
-            }
-            match byte.try_into() {
-                Ok(MazeChar::Wall) | Ok(MazeChar::Vacant) => {}
-                Ok(MazeChar::Start) => {
-                    start = Some(Coordinate {
-                        r: r as isize,
-                        c: c as isize,
-                    })
-                }
-                Ok(MazeChar::End) => {
-                    end = Some(Coordinate {
-                        r: r as isize,
-                        c: c as isize,
+        let mut row = Vec::with_capacity(line.len());
+        for (c, char) in line.chars().enumerate() {
+            let maze_char = u8::try_from(char)
+                .ok()
+                .and_then(|byte| MazeChar::try_from(byte).ok())
+                .ok_or(AocError::InvalidCharacter {
+                    line: r + 1,
+                    col: c + 1,
+                    char,
+                })?;
+            let coord = Coordinate::new(r as isize, c as isize);
+            match maze_char {
+                MazeChar::Wall | MazeChar::Vacant => {}
+                MazeChar::Start if start.is_some() => {
+                    return Err(AocError::Duplicate {
+                        what: "start",
+                        line: r + 1,
+                        col: c + 1,
                     })
                 }
-                Err(()) => unimplemented!(),
+                MazeChar::Start => start = Some(coord),
+                MazeChar::End => ends.push(coord),
+            }
+            row.push(!matches!(maze_char, MazeChar::Wall));
+        }
+        if let Some(first) = rows.first() {
+            if row.len() != first.len() {
+                return Err(AocError::RaggedRow {
+                    line: r + 1,
+                    len: row.len(),
+                    expected: first.len(),
+                });
             }
         }
         rows.push(row)
     }
-    Maze {
-        matrix: Matrix::new(rows),
-        start: start.unwrap(),
-        end: end.unwrap(),
-        direction: Cardinal::East,
+    let start = start.ok_or(AocError::Missing("start"))?;
+    Maze::new(Matrix::new(rows), start, ends)
+}
+
+impl Maze {
+    /// The character of a tile, or the character of a path tile if it is on
+    /// the path.
+    fn tile(&self, coord: &Coordinate, path: Option<&HashSet<Coordinate>>) -> char {
+        let byte = if path.is_some_and(|path| path.contains(coord)) {
+            PATH
+        } else if *coord == self.start {
+            MazeChar::Start as u8
+        } else if self.ends.contains(coord) {
+            MazeChar::End as u8
+        } else if self.is_vacant(coord) {
+            MazeChar::Vacant as u8
+        } else {
+            MazeChar::Wall as u8
+        };
+        byte as char
+    }
+
+    fn write_tiles(
+        &self,
+        f: &mut impl std::fmt::Write,
+        path: Option<&HashSet<Coordinate>>,
+    ) -> std::fmt::Result {
+        for row in self.matrix.row_range() {
+            for col in self.matrix.col_range() {
+                write!(
+                    f,
+                    "{}",
+                    self.tile(&Coordinate::new(row as isize, col as isize), path)
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+
+    /// Draw the maze with an `O` on every tile of the path.
+    pub fn render_with_path(&self, path: &HashSet<Coordinate>) -> String {
+        let mut rendered = String::new();
+        self.write_tiles(&mut rendered, Some(path))
+            .expect("writing to a string should not fail");
+        rendered
     }
 }
 
-#[repr(usize)]
-enum Score {
-    Straight = 1,
-    Turn = 1000,
+/// The character of a tile on a path, as in the puzzle's pictures.
+const PATH: u8 = b'O';
+
+impl Display for Maze {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_tiles(f, None)
+    }
+}
+
+/// The score of taking a step forward, and of turning 90 degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Costs {
+    pub step: usize,
+    pub turn: usize,
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Costs {
+            step: 1,
+            turn: 1000,
+        }
+    }
+}
+
+impl Costs {
+    /// The score of moving in a direction, when facing another one.
+    fn of(&self, facing: Cardinal, direction: Cardinal) -> usize {
+        if facing == direction {
+            self.step
+        } else {
+            self.step + self.turn
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -81,7 +207,6 @@ pub struct TraversalState {
     pub score: usize,
     pub coord: Coordinate,
     pub direction: Cardinal,
-    pub positions: Vec<Coordinate>,
 }
 
 impl PartialEq for TraversalState {
@@ -108,111 +233,663 @@ impl Ord for TraversalState {
     }
 }
 
-fn solve(maze: Maze) -> Vec<TraversalState> {
-    let mut min_heap: BinaryHeap<TraversalState> = BinaryHeap::from([TraversalState {
-        score: 0,
-        coord: maze.start,
-        direction: maze.direction,
-        positions: vec![maze.start],
-    }]);
-    let mut visited = HashMap::new();
-    let mut states = vec![];
-    let mut best_score = None;
+/// A position in the maze together with the direction the reindeer faces.
+type State = (Coordinate, Cardinal);
+
+/// The outcome of searching the maze from the start.
+pub struct Search {
+    /// The lowest score to reach the end, if it can be reached.
+    pub best: Option<usize>,
+    /// The lowest score to reach every visited state.
+    scores: HashMap<State, usize>,
+    /// The states from which every state is reached with its lowest score.
+    predecessors: HashMap<State, Vec<State>>,
+    /// The largest number of states in the heap at once.
+    pub peak_heap: usize,
+    /// The number of states taken from the heap and expanded.
+    pub explored: usize,
+}
+
+/// The directions the reindeer can move in next: straight ahead, or after
+/// turning left or right. Turning around is never part of a best path, other
+/// than at the start, see [`start_states`].
+fn next_directions(direction: Cardinal) -> [Cardinal; 3] {
+    match direction {
+        Cardinal::North => [Cardinal::West, Cardinal::North, Cardinal::East],
+        Cardinal::East => [Cardinal::North, Cardinal::East, Cardinal::South],
+        Cardinal::South => [Cardinal::East, Cardinal::South, Cardinal::West],
+        Cardinal::West => [Cardinal::South, Cardinal::West, Cardinal::North],
+    }
+}
+
+/// The states at the start, facing every direction, with the score of turning
+/// to it from the initial direction. Turning around takes two turns.
+fn start_states(start: Coordinate, direction: Cardinal, costs: &Costs) -> [(State, usize); 4] {
+    let [left, _, right] = next_directions(direction);
+    [
+        ((start, direction), 0),
+        ((start, left), costs.turn),
+        ((start, right), costs.turn),
+        ((start, direction.opposite()), 2 * costs.turn),
+    ]
+}
+
+impl Maze {
+    fn is_vacant(&self, coord: &Coordinate) -> bool {
+        coord.r >= 0
+            && coord.c >= 0
+            && *self
+                .matrix
+                .get_element([coord.r as usize, coord.c as usize])
+                .unwrap_or(&false)
+    }
+}
+
+/// Run Dijkstra's algorithm over the states of the maze, stopping once every
+/// state with a score up to the best score at the end is settled. Next to the
+/// scores, the predecessors that reach a state with its lowest score are kept,
+/// so all best paths can be traced back without storing them.
+pub fn search(maze: &Maze) -> Search {
+    search_with(maze, &Costs::default(), START_DIRECTION)
+}
+
+/// Search the maze like [`search`], with other costs to step and turn, and
+/// starting in another direction.
+pub fn search_with(maze: &Maze, costs: &Costs, direction: Cardinal) -> Search {
+    dijkstra(maze, costs, direction, true)
+}
+
+/// Run Dijkstra's algorithm from the start. Every state is expanded once, at
+/// its lowest score, and reaching it again with an equal score only records
+/// another predecessor. Unless all best paths are needed, the search stops at
+/// the first time an end is reached.
+fn dijkstra(maze: &Maze, costs: &Costs, direction: Cardinal, all_best: bool) -> Search {
+    let starts = start_states(maze.start, direction, costs);
+    let mut min_heap: BinaryHeap<TraversalState> = starts
+        .iter()
+        .map(|((coord, direction), score)| TraversalState {
+            score: *score,
+            coord: *coord,
+            direction: *direction,
+        })
+        .collect();
+    let mut scores: HashMap<State, usize> = starts.into_iter().collect();
+    let mut predecessors: HashMap<State, Vec<State>> = HashMap::new();
+    let mut best = None;
+    let mut peak_heap = min_heap.len();
+    let mut explored = 0;
 
     while let Some(state) = min_heap.pop() {
-        if best_score.is_some() && state.score > best_score.unwrap() {
+        let current = (state.coord, state.direction);
+        if scores
+            .get(&current)
+            .is_some_and(|score| *score < state.score)
+        {
             continue;
         }
-        if state.coord == maze.end {
-            best_score = Some(state.score);
-            states.push(state.clone());
+        if best.is_some_and(|best| state.score > best) {
+            break;
         }
+        if maze.ends.contains(&state.coord) {
+            best.get_or_insert(state.score);
+            if !all_best {
+                break;
+            }
+            continue;
+        }
+        explored += 1;
 
-        // This can be improved. Deviating paths of equal score that merge back
-        // into the main track are again explored fully.
-        // We could run part 1 to get a path, trackin scores along the way.
-        // This could serve as an input to part 2 where we can reject side paths
-        // that get a worse score upon merging.
-        let mut worse_path = false;
-        visited
-            .entry((state.coord, state.direction))
-            .and_modify(|best_score: &mut usize| {
-                if *best_score < state.score {
-                    worse_path = true;
-                } else {
-                    *best_score = state.score
+        for direction in next_directions(state.direction) {
+            let destination = state.coord.cardinal(direction);
+            if !maze.is_vacant(&destination) {
+                continue;
+            };
+            let score = state.score + costs.of(state.direction, direction);
+            let next = (destination, direction);
+            match scores.get(&next) {
+                Some(known) if *known < score => continue,
+                Some(known) if *known == score => {
+                    predecessors.entry(next).or_default().push(current);
+                    continue;
                 }
-            })
-            .or_insert(state.score);
-        if worse_path {
-            continue;
+                _ => (),
+            }
+            scores.insert(next, score);
+            predecessors.insert(next, vec![current]);
+            min_heap.push(TraversalState {
+                score,
+                coord: destination,
+                direction,
+            });
+            peak_heap = peak_heap.max(min_heap.len());
         }
+    }
+    Search {
+        best,
+        scores,
+        predecessors,
+        peak_heap,
+        explored,
+    }
+}
 
-        let directions = match &state.direction {
-            Cardinal::North => [Cardinal::West, Cardinal::North, Cardinal::East],
-            Cardinal::East => [Cardinal::North, Cardinal::East, Cardinal::South],
-            Cardinal::South => [Cardinal::East, Cardinal::South, Cardinal::West],
-            Cardinal::West => [Cardinal::South, Cardinal::West, Cardinal::North],
-        };
+/// The states at any end, facing any direction.
+fn end_states(maze: &Maze) -> Vec<State> {
+    maze.ends
+        .iter()
+        .flat_map(|end| {
+            [
+                Cardinal::North,
+                Cardinal::East,
+                Cardinal::South,
+                Cardinal::West,
+            ]
+            .map(|direction| (*end, direction))
+        })
+        .collect()
+}
+
+/// The states at the ends that are reached with the best score.
+fn best_ends(maze: &Maze, search: &Search, best: usize) -> Vec<State> {
+    end_states(maze)
+        .into_iter()
+        .filter(|state| search.scores.get(state) == Some(&best))
+        .collect()
+}
 
-        for direction in directions {
+/// The best score and one path with that score, from the start to the
+/// nearest end. Returns `None` if no end can be reached.
+pub fn best_path(maze: &Maze) -> Option<(usize, Vec<Coordinate>)> {
+    best_path_with(maze, &Costs::default(), START_DIRECTION)
+}
+
+/// One best path like [`best_path`], with other costs to step and turn, and
+/// starting in another direction.
+pub fn best_path_with(
+    maze: &Maze,
+    costs: &Costs,
+    direction: Cardinal,
+) -> Option<(usize, Vec<Coordinate>)> {
+    let search = dijkstra(maze, costs, direction, false);
+    let best = search.best?;
+    let mut state = best_ends(maze, &search, best)[0];
+    let mut path = vec![state.0];
+    while let Some(predecessor) = search.predecessors.get(&state).and_then(|p| p.first()) {
+        state = *predecessor;
+        path.push(state.0);
+    }
+    path.reverse();
+    Some((best, path))
+}
+
+/// The tiles on any best path, found by walking back from the end along the
+/// predecessors of every state. Returns `None` if no end can be reached.
+pub fn best_path_tiles(maze: &Maze) -> Option<HashSet<Coordinate>> {
+    best_path_tiles_with(maze, &Costs::default(), START_DIRECTION)
+}
+
+/// The tiles on any best path like [`best_path_tiles`], with other costs to
+/// step and turn, and starting in another direction.
+pub fn best_path_tiles_with(
+    maze: &Maze,
+    costs: &Costs,
+    direction: Cardinal,
+) -> Option<HashSet<Coordinate>> {
+    tiles(maze, &search_with(maze, costs, direction))
+}
+
+/// The tiles on any best path of a search of all best paths, `None` if no end
+/// was reached.
+fn tiles(maze: &Maze, search: &Search) -> Option<HashSet<Coordinate>> {
+    let best = search.best?;
+    let mut stack = best_ends(maze, search, best);
+    let mut visited: HashSet<State> = stack.iter().copied().collect();
+    while let Some(state) = stack.pop() {
+        for predecessor in search.predecessors.get(&state).into_iter().flatten() {
+            if visited.insert(*predecessor) {
+                stack.push(*predecessor);
+            }
+        }
+    }
+    Some(visited.into_iter().map(|(coord, _)| coord).collect())
+}
+
+/// The lowest score to reach an end.
+///
+/// # Panics
+/// If no end can be reached from the start.
+pub fn part_1(maze: &Maze) -> usize {
+    best_path(maze).expect("an end should be reachable").0
+}
+
+/// The number of tiles on any best path.
+///
+/// # Panics
+/// If no end can be reached from the start.
+pub fn part_2(maze: &Maze) -> usize {
+    best_path_tiles(maze)
+        .expect("an end should be reachable")
+        .len()
+}
+
+/// The answers to both parts, from a single search. Returns `None` if no end
+/// can be reached.
+pub fn solve_both(maze: &Maze) -> Option<(usize, usize)> {
+    let search = search(maze);
+    Some((search.best?, tiles(maze, &search)?.len()))
+}
+
+/// A lower bound on the score to reach an end: one point per tile between
+/// both, and a turn for every direction that still has to be faced. Facing
+/// away from the end, or not being in line with it, needs at least two turns.
+fn lower_bound(coord: &Coordinate, direction: Cardinal, end: &Coordinate, costs: &Costs) -> usize {
+    let needed: Vec<Cardinal> = [
+        (end.r < coord.r, Cardinal::North),
+        (end.c > coord.c, Cardinal::East),
+        (end.r > coord.r, Cardinal::South),
+        (end.c < coord.c, Cardinal::West),
+    ]
+    .into_iter()
+    .filter_map(|(needed, cardinal)| needed.then_some(cardinal))
+    .collect();
+    let turns = if needed.is_empty() {
+        0
+    } else if needed.contains(&direction) {
+        needed.len() - 1
+    } else if needed.len() == 1 && needed[0] != direction.opposite() {
+        1
+    } else {
+        2
+    };
+    (coord.r.abs_diff(end.r) + coord.c.abs_diff(end.c)) * costs.step + turns * costs.turn
+}
+
+/// The lowest [`lower_bound`] over all ends of the maze.
+fn nearest_bound(maze: &Maze, coord: &Coordinate, direction: Cardinal, costs: &Costs) -> usize {
+    maze.ends
+        .iter()
+        .map(|end| lower_bound(coord, direction, end, costs))
+        .min()
+        .unwrap_or_default()
+}
+
+/// Run A* over the states of the maze, guided by the [`lower_bound`] on the
+/// score left to reach the end. Returns the best score, if the end can be
+/// reached, and the number of states that were expanded.
+fn astar(maze: &Maze, costs: &Costs, direction: Cardinal) -> (Option<usize>, usize) {
+    let starts = start_states(maze.start, direction, costs);
+    // The heap is ordered on the score so far plus the lower bound.
+    let mut min_heap: BinaryHeap<TraversalState> = starts
+        .iter()
+        .map(|((coord, direction), score)| TraversalState {
+            score: score + nearest_bound(maze, coord, *direction, costs),
+            coord: *coord,
+            direction: *direction,
+        })
+        .collect();
+    let mut scores: HashMap<State, usize> = starts.into_iter().collect();
+    let mut explored = 0;
+    while let Some(state) = min_heap.pop() {
+        let current = (state.coord, state.direction);
+        let score = scores[&current];
+        if score + nearest_bound(maze, &state.coord, state.direction, costs) < state.score {
+            continue;
+        }
+        if maze.ends.contains(&state.coord) {
+            return (Some(score), explored);
+        }
+        explored += 1;
+        for direction in next_directions(state.direction) {
             let destination = state.coord.cardinal(direction);
-            if [destination.r, destination.c]
-                .iter()
-                .any(|val| val.is_negative())
-                || !*maze
-                    .matrix
-                    .get_element([destination.r as usize, destination.c as usize])
-                    .unwrap_or(&false)
-            {
+            if !maze.is_vacant(&destination) {
                 continue;
-            };
+            }
+            let next = (destination, direction);
+            let score = score + costs.of(state.direction, direction);
+            if scores.get(&next).is_some_and(|known| *known <= score) {
+                continue;
+            }
+            scores.insert(next, score);
+            min_heap.push(TraversalState {
+                score: score + nearest_bound(maze, &destination, direction, costs),
+                coord: destination,
+                direction,
+            });
+        }
+    }
+    (None, explored)
+}
+
+/// The best score to reach the end, found with A* instead of Dijkstra's
+/// algorithm. Returns `None` if no end can be reached.
+pub fn solve_astar(maze: &Maze) -> Option<usize> {
+    astar(maze, &Costs::default(), START_DIRECTION).0
+}
 
-            let (coord, score) = if direction == state.direction {
-                (destination, state.score + Score::Straight as usize)
+/// The lowest score of every state reachable from the sources, which start
+/// with their own score. Moving forward
+/// from a state `(coord, facing)` leads to `(coord + direction, direction)`.
+/// In reverse, a state `(coord, direction)` is reached from
+/// `(coord - direction, facing)`, which gives the lowest score to get from a
+/// state to any of the sources.
+fn scores_from(
+    maze: &Maze,
+    sources: &[(State, usize)],
+    reverse: bool,
+    costs: &Costs,
+) -> HashMap<State, usize> {
+    let mut scores: HashMap<State, usize> = sources.iter().copied().collect();
+    let mut min_heap: BinaryHeap<TraversalState> = sources
+        .iter()
+        .map(|((coord, direction), score)| TraversalState {
+            score: *score,
+            coord: *coord,
+            direction: *direction,
+        })
+        .collect();
+    while let Some(state) = min_heap.pop() {
+        if scores[&(state.coord, state.direction)] < state.score {
+            continue;
+        }
+        // Turning left or right is symmetric, so the same directions are
+        // possible in both senses.
+        for direction in next_directions(state.direction) {
+            let (next, score) = if reverse {
+                let previous = state.coord.cardinal(state.direction.opposite());
+                (
+                    (previous, direction),
+                    state.score + costs.of(direction, state.direction),
+                )
             } else {
                 (
-                    destination,
-                    state.score + Score::Straight as usize + Score::Turn as usize,
+                    (state.coord.cardinal(direction), direction),
+                    state.score + costs.of(state.direction, direction),
                 )
             };
-            let mut positions = state.clone().positions;
-            positions.push(destination);
+            if !maze.is_vacant(&next.0) || scores.get(&next).is_some_and(|known| *known <= score) {
+                continue;
+            }
+            scores.insert(next, score);
             min_heap.push(TraversalState {
-                direction,
                 score,
-                coord,
-                positions,
+                coord: next.0,
+                direction: next.1,
             });
         }
     }
-    states
+    scores
+}
+
+/// Count the tiles on any best path, by searching from the start and, in
+/// reverse, from the end facing any direction. A state is on a best path if
+/// the scores to reach it and to get from it to the end add up to the best.
+/// Returns `None` if no end can be reached.
+pub fn part_2_bidirectional(maze: &Maze) -> Option<usize> {
+    let costs = Costs::default();
+    let from_start = scores_from(
+        maze,
+        &start_states(maze.start, START_DIRECTION, &costs),
+        false,
+        &costs,
+    );
+    let ends = end_states(maze);
+    let sources: Vec<(State, usize)> = ends.iter().map(|end| (*end, 0)).collect();
+    let to_end = scores_from(maze, &sources, true, &costs);
+    let best = ends
+        .iter()
+        .filter_map(|end| from_start.get(end))
+        .min()
+        .copied()?;
+    let tiles: HashSet<_> = from_start
+        .iter()
+        .filter(|(state, score)| to_end.get(state).is_some_and(|rest| **score + rest == best))
+        .map(|((coord, _), _)| *coord)
+        .collect();
+    Some(tiles.len())
+}
+
+/// A corridor between two nodes of a [`MazeGraph`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    /// The node at the other end of the corridor.
+    pub to: Coordinate,
+    /// The direction in which the corridor leaves its first node.
+    pub exit: Cardinal,
+    /// The direction in which the corridor enters the other node.
+    pub entry: Cardinal,
+    /// The number of steps along the corridor.
+    pub steps: usize,
+    /// The number of turns along the corridor.
+    pub turns: usize,
+    /// The tiles strictly between both nodes.
+    pub tiles: Vec<Coordinate>,
+}
+
+/// The maze with its corridors collapsed into edges. The nodes are the start,
+/// the ends, and every vacant tile that does not have exactly two vacant
+/// neighbours, i.e. junctions and dead ends.
+#[derive(Debug)]
+pub struct MazeGraph {
+    start: Coordinate,
+    ends: Vec<Coordinate>,
+    /// The corridors leaving every node.
+    pub edges: HashMap<Coordinate, Vec<Edge>>,
 }
 
-pub fn part_1(maze: Maze) -> usize {
-    solve(maze).first().unwrap().score
+impl Maze {
+    /// The tiles that are a node of the [`MazeGraph`].
+    fn is_node(&self, coord: &Coordinate) -> bool {
+        *coord == self.start
+            || self.ends.contains(coord)
+            || coord
+                .cardinals()
+                .iter()
+                .filter(|neighbour| self.is_vacant(neighbour))
+                .count()
+                != 2
+    }
+
+    /// Collapse all corridors into edges between junctions, dead ends, the
+    /// start and the ends.
+    pub fn compress(&self) -> MazeGraph {
+        let nodes = self.matrix.row_range().flat_map(|r| {
+            self.matrix
+                .col_range()
+                .map(move |c| Coordinate::new(r as isize, c as isize))
+        });
+        let edges = nodes
+            .filter(|coord| self.is_vacant(coord) && self.is_node(coord))
+            .map(|node| (node, self.corridors(node)))
+            .collect();
+        MazeGraph {
+            start: self.start,
+            ends: self.ends.clone(),
+            edges,
+        }
+    }
+
+    /// Follow every corridor leaving a node up to the next node.
+    fn corridors(&self, node: Coordinate) -> Vec<Edge> {
+        let mut edges = vec![];
+        for exit in [
+            Cardinal::North,
+            Cardinal::East,
+            Cardinal::South,
+            Cardinal::West,
+        ] {
+            let mut coord = node.cardinal(exit);
+            if !self.is_vacant(&coord) {
+                continue;
+            }
+            let mut edge = Edge {
+                to: coord,
+                exit,
+                entry: exit,
+                steps: 1,
+                turns: 0,
+                tiles: vec![],
+            };
+            // A tile that is not a node has exactly one way forward.
+            while !self.is_node(&coord) {
+                let direction = next_directions(edge.entry)
+                    .into_iter()
+                    .find(|direction| self.is_vacant(&coord.cardinal(*direction)))
+                    .expect("a corridor should continue");
+                if direction != edge.entry {
+                    edge.turns += 1;
+                }
+                edge.tiles.push(coord);
+                coord = coord.cardinal(direction);
+                edge.entry = direction;
+                edge.steps += 1;
+            }
+            edge.to = coord;
+            edges.push(edge);
+        }
+        edges
+    }
 }
 
-pub fn part_2(maze: Maze) -> usize {
-    let mut positions = HashSet::<Coordinate>::new();
-    for solution in solve(maze) {
-        positions.extend(solution.positions);
+impl MazeGraph {
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// Run Dijkstra's algorithm over the nodes and the direction the reindeer
+    /// faces in them, like [`search`] does over all tiles, starting in the given
+    /// direction. The turn to take a
+    /// corridor is paid at the node it leaves. Returns the best score and the
+    /// tiles on any best path, including the tiles along the corridors.
+    pub fn solve(
+        &self,
+        costs: &Costs,
+        direction: Cardinal,
+    ) -> Option<(usize, HashSet<Coordinate>)> {
+        let starts = start_states(self.start, direction, costs);
+        let mut min_heap: BinaryHeap<TraversalState> = starts
+            .iter()
+            .map(|((coord, direction), score)| TraversalState {
+                score: *score,
+                coord: *coord,
+                direction: *direction,
+            })
+            .collect();
+        let mut scores: HashMap<State, usize> = starts.into_iter().collect();
+        // The states and corridors from which every state is reached with its
+        // lowest score.
+        let mut predecessors: HashMap<State, Vec<(State, &Edge)>> = HashMap::new();
+        let mut best = None;
+        while let Some(state) = min_heap.pop() {
+            let current = (state.coord, state.direction);
+            if scores[&current] < state.score {
+                continue;
+            }
+            if best.is_some_and(|best| state.score > best) {
+                break;
+            }
+            if self.ends.contains(&state.coord) {
+                best.get_or_insert(state.score);
+                continue;
+            }
+            for edge in self.edges[&state.coord].iter() {
+                if edge.exit == state.direction.opposite() {
+                    continue;
+                }
+                let turns = edge.turns + usize::from(edge.exit != state.direction);
+                let score = state.score + edge.steps * costs.step + turns * costs.turn;
+                let next = (edge.to, edge.entry);
+                match scores.get(&next) {
+                    Some(known) if *known < score => continue,
+                    Some(known) if *known == score => {
+                        predecessors.entry(next).or_default().push((current, edge));
+                        continue;
+                    }
+                    _ => (),
+                }
+                scores.insert(next, score);
+                predecessors.insert(next, vec![(current, edge)]);
+                min_heap.push(TraversalState {
+                    score,
+                    coord: edge.to,
+                    direction: edge.entry,
+                });
+            }
+        }
+        let best = best?;
+
+        let mut stack: Vec<State> = self
+            .ends
+            .iter()
+            .flat_map(|end| {
+                [
+                    Cardinal::North,
+                    Cardinal::East,
+                    Cardinal::South,
+                    Cardinal::West,
+                ]
+                .map(|direction| (*end, direction))
+            })
+            .filter(|state| scores.get(state) == Some(&best))
+            .collect();
+        let mut visited: HashSet<State> = stack.iter().copied().collect();
+        let mut tiles: HashSet<Coordinate> = stack.iter().map(|(coord, _)| *coord).collect();
+        while let Some(state) = stack.pop() {
+            for (predecessor, edge) in predecessors.get(&state).into_iter().flatten() {
+                tiles.insert(predecessor.0);
+                tiles.extend(edge.tiles.iter().copied());
+                if visited.insert(*predecessor) {
+                    stack.push(*predecessor);
+                }
+            }
+        }
+        Some((best, tiles))
     }
-    positions.len()
+}
+
+/// The answer to part 1, searched over the compressed maze.
+///
+/// # Panics
+/// If no end can be reached from the start.
+pub fn part_1_compressed(maze: &Maze) -> usize {
+    maze.compress()
+        .solve(&Costs::default(), START_DIRECTION)
+        .expect("an end should be reachable")
+        .0
+}
+
+/// The answer to part 2, searched over the compressed maze.
+///
+/// # Panics
+/// If no end can be reached from the start.
+pub fn part_2_compressed(maze: &Maze) -> usize {
+    maze.compress()
+        .solve(&Costs::default(), START_DIRECTION)
+        .expect("an end should be reachable")
+        .1
+        .len()
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BinaryHeap;
+    use std::collections::{BinaryHeap, HashMap};
 
     use itertools::assert_equal;
 
     use crate::{
-        day16::{Maze, TraversalState},
-        util::{read_file_to_string, Cardinal, Coordinate, Matrix},
+        day16::{next_directions, Maze, TraversalState},
+        util::{read_file_to_string, AocError, Cardinal, Coordinate, Matrix},
     };
 
-    use super::{parse_input, part_1, part_2};
+    use super::{
+        astar, best_path, best_path_tiles, best_path_with, dijkstra, parse_input, part_1,
+        part_1_compressed, part_2, part_2_bidirectional, part_2_compressed, search, solve_astar,
+        solve_both, Costs, START_DIRECTION,
+    };
 
     const INPUT_1: &str = "###############
 #.......#....E#
@@ -260,7 +937,7 @@ mod tests {
     #[test]
     fn test_parse_input() {
         assert_eq!(
-            parse_input(INPUT_1),
+            parse_input(INPUT_1).unwrap(),
             Maze {
                 matrix: Matrix::new(vec![
                     vec![
@@ -325,8 +1002,7 @@ mod tests {
                     ],
                 ]),
                 start: Coordinate { r: 13, c: 1 },
-                end: Coordinate { r: 1, c: 13 },
-                direction: Cardinal::East
+                ends: vec![Coordinate { r: 1, c: 13 }],
             }
         )
     }
@@ -337,19 +1013,16 @@ mod tests {
             score: 1,
             coord: Coordinate::default(),
             direction: Cardinal::North,
-            positions: vec![Coordinate::default()],
         };
         let state_2 = TraversalState {
             score: 2,
             coord: Coordinate::default(),
             direction: Cardinal::North,
-            positions: vec![Coordinate::default()],
         };
         let state_3 = TraversalState {
             score: 3,
             coord: Coordinate::default(),
             direction: Cardinal::North,
-            positions: vec![Coordinate::default()],
         };
         let states = [state_3.clone(), state_1.clone(), state_2.clone()];
 
@@ -363,30 +1036,394 @@ mod tests {
 
     #[test]
     fn test_part_1_small() {
-        assert_eq!(part_1(parse_input(INPUT_1)), 7036);
-        assert_eq!(part_1(parse_input(INPUT_2)), 11048);
-        assert_eq!(part_1(parse_input(INPUT_3)), 3022);
+        assert_eq!(part_1(&parse_input(INPUT_1).unwrap()), 7036);
+        assert_eq!(part_1(&parse_input(INPUT_2).unwrap()), 11048);
+        assert_eq!(part_1(&parse_input(INPUT_3).unwrap()), 3022);
     }
 
     #[test]
     fn test_part_1() {
         assert_eq!(
-            part_1(parse_input(&read_file_to_string("data/day16.txt"))),
+            part_1(&parse_input(&read_file_to_string("data/day16.txt")).unwrap()),
             106512
         )
     }
 
     #[test]
     fn test_part_2_small() {
-        assert_eq!(part_2(parse_input(INPUT_1)), 45);
-        assert_eq!(part_2(parse_input(INPUT_2)), 64);
+        assert_eq!(part_2(&parse_input(INPUT_1).unwrap()), 45);
+        assert_eq!(part_2(&parse_input(INPUT_2).unwrap()), 64);
     }
 
     #[test]
     fn test_part_2() {
         assert_eq!(
-            part_2(parse_input(&read_file_to_string("data/day16.txt"))),
+            part_2(&parse_input(&read_file_to_string("data/day16.txt")).unwrap()),
             563
         )
     }
+
+    /// The original search, which carries the path in every state. Returns
+    /// the largest number of coordinates in the heap at once, and the number
+    /// of states that were expanded.
+    fn original_search_stats(maze: &Maze) -> (usize, usize) {
+        #[derive(Clone)]
+        struct PathState {
+            state: TraversalState,
+            positions: Vec<Coordinate>,
+        }
+        impl PartialEq for PathState {
+            fn eq(&self, other: &Self) -> bool {
+                self.state == other.state
+            }
+        }
+        impl Eq for PathState {}
+        impl PartialOrd for PathState {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for PathState {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.state.cmp(&other.state)
+            }
+        }
+
+        let mut min_heap = BinaryHeap::from([PathState {
+            state: TraversalState {
+                score: 0,
+                coord: maze.start,
+                direction: START_DIRECTION,
+            },
+            positions: vec![maze.start],
+        }]);
+        let mut visited = HashMap::new();
+        let mut best_score = None;
+        let mut in_heap = 1;
+        let mut peak = in_heap;
+        let mut popped = 0;
+        while let Some(PathState { state, positions }) = min_heap.pop() {
+            in_heap -= positions.len();
+            if best_score.is_some_and(|best| state.score > best) {
+                continue;
+            }
+            if maze.ends.contains(&state.coord) {
+                best_score = Some(state.score);
+            }
+            let best = visited
+                .entry((state.coord, state.direction))
+                .or_insert(state.score);
+            if *best < state.score {
+                continue;
+            }
+            *best = state.score;
+            popped += 1;
+            for direction in next_directions(state.direction) {
+                let destination = state.coord.cardinal(direction);
+                if !maze.is_vacant(&destination) {
+                    continue;
+                }
+                let turn = if direction == state.direction {
+                    0
+                } else {
+                    1000
+                };
+                let mut positions = positions.clone();
+                positions.push(destination);
+                in_heap += positions.len();
+                min_heap.push(PathState {
+                    state: TraversalState {
+                        score: state.score + 1 + turn,
+                        coord: destination,
+                        direction,
+                    },
+                    positions,
+                });
+                peak = peak.max(in_heap);
+            }
+        }
+        (peak, popped)
+    }
+
+    #[test]
+    fn test_peak_heap() {
+        // Every state in the heap now holds a single coordinate, instead of
+        // the whole path to it.
+        let maze = parse_input(INPUT_2).unwrap();
+        let peak = search(&maze).peak_heap;
+        let (peak_with_paths, _) = original_search_stats(&maze);
+        assert!(
+            10 * peak < peak_with_paths,
+            "{peak} coordinates in the heap, against {peak_with_paths} with paths"
+        );
+    }
+
+    #[test]
+    fn test_explored() {
+        // States reached again with an equal score are no longer expanded
+        // again, and a single best path stops at the end.
+        let maze = parse_input(INPUT_2).unwrap();
+        let (_, original) = original_search_stats(&maze);
+        let all_best = dijkstra(&maze, &Costs::default(), START_DIRECTION, true).explored;
+        let single_best = dijkstra(&maze, &Costs::default(), START_DIRECTION, false).explored;
+        assert!(
+            single_best <= all_best && all_best < original,
+            "{single_best} and {all_best} states expanded, against {original} originally"
+        );
+    }
+
+    #[test]
+    fn test_part_2_bidirectional() {
+        let full = read_file_to_string("data/day16.txt");
+        for input in [INPUT_1, INPUT_2, INPUT_3, &full] {
+            assert_eq!(
+                part_2_bidirectional(&parse_input(input).unwrap()),
+                Some(part_2(&parse_input(input).unwrap()))
+            );
+        }
+        assert_eq!(
+            part_2_bidirectional(&parse_input(INPUT_1).unwrap()),
+            Some(45)
+        );
+        assert_eq!(
+            part_2_bidirectional(&parse_input(INPUT_2).unwrap()),
+            Some(64)
+        );
+    }
+
+    #[test]
+    fn test_solve_astar() {
+        let full = read_file_to_string("data/day16.txt");
+        for input in [INPUT_1, INPUT_2, INPUT_3, &full] {
+            assert_eq!(
+                solve_astar(&parse_input(input).unwrap()),
+                Some(part_1(&parse_input(input).unwrap()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_astar_explored() {
+        let maze = parse_input(INPUT_2).unwrap();
+        let dijkstra = search(&maze).explored;
+        let (_, astar) = astar(&maze, &Costs::default(), START_DIRECTION);
+        assert!(
+            astar < dijkstra,
+            "A* explored {astar} states, against {dijkstra} for Dijkstra"
+        );
+    }
+
+    #[test]
+    fn test_best_path() {
+        let maze = parse_input(INPUT_1).unwrap();
+        let (score, path) = best_path(&maze).unwrap();
+        assert_eq!(score, 7036);
+        assert_eq!(path.first(), Some(&Coordinate { r: 13, c: 1 }));
+        assert_eq!(path.last(), Some(&Coordinate { r: 1, c: 13 }));
+
+        let mut direction = Cardinal::East;
+        let mut recomputed = 0;
+        for (from, to) in path.iter().zip(path.iter().skip(1)) {
+            let step = [
+                Cardinal::North,
+                Cardinal::East,
+                Cardinal::South,
+                Cardinal::West,
+            ]
+            .into_iter()
+            .find(|cardinal| from.cardinal(*cardinal) == *to)
+            .expect("consecutive tiles should be one step apart");
+            recomputed += if step == direction { 1 } else { 1001 };
+            direction = step;
+        }
+        assert_eq!(recomputed, 7036);
+
+        let tiles = best_path_tiles(&maze).unwrap();
+        assert_eq!(tiles.len(), 45);
+        assert!(path.iter().all(|coord| tiles.contains(coord)));
+    }
+
+    #[test]
+    fn test_costs() {
+        let maze = parse_input(INPUT_3).unwrap();
+        assert_eq!(
+            best_path_with(&maze, &Costs::default(), START_DIRECTION)
+                .unwrap()
+                .0,
+            3022
+        );
+        // Without turning costs, the winding path of 12 steps upwards beats
+        // the 22 steps around to the right.
+        let (score, path) =
+            best_path_with(&maze, &Costs { step: 1, turn: 0 }, START_DIRECTION).unwrap();
+        assert_eq!(score, 12);
+        assert_eq!(path.len(), 13);
+        assert_eq!(
+            best_path_with(&maze, &Costs { step: 2, turn: 1 }, START_DIRECTION)
+                .unwrap()
+                .0,
+            2 * 12 + 7
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let maze = parse_input(INPUT_1).unwrap();
+        assert_eq!(maze.to_string(), INPUT_1);
+        let rendered = maze.render_with_path(&best_path_tiles(&maze).unwrap());
+        assert_eq!(rendered.matches('O').count(), 45);
+        assert_eq!(rendered.lines().nth(13), Some("#O..#.....#OOO#"));
+    }
+
+    #[test]
+    fn test_parse_input_invalid() {
+        assert_eq!(
+            parse_input("#####\n#S.E#\n#.x.#\n#####").unwrap_err(),
+            AocError::InvalidCharacter {
+                line: 3,
+                col: 3,
+                char: 'x'
+            }
+        );
+        assert_eq!(
+            parse_input("#####\n#..E#\n#####").unwrap_err(),
+            AocError::Missing("start")
+        );
+        assert_eq!(
+            parse_input("#####\n#S..#\n#####").unwrap_err(),
+            AocError::Missing("end")
+        );
+        assert_eq!(
+            parse_input("#####\n#S.E#\n#.S.#\n#####").unwrap_err(),
+            AocError::Duplicate {
+                what: "start",
+                line: 3,
+                col: 3
+            }
+        );
+        assert_eq!(
+            parse_input("#####\n#S.E#\n####").unwrap_err(),
+            AocError::RaggedRow {
+                line: 3,
+                len: 4,
+                expected: 5
+            }
+        );
+    }
+
+    #[test]
+    fn test_maze_blocked() {
+        let maze = parse_input(INPUT_3).unwrap();
+        assert_eq!(
+            Maze::new(maze.matrix, Coordinate::new(0, 0), maze.ends).unwrap_err(),
+            AocError::Blocked {
+                what: "start",
+                line: 1,
+                col: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_input_crlf() {
+        assert_eq!(
+            parse_input(&INPUT_1.replace('\n', "\r\n")).unwrap(),
+            parse_input(INPUT_1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_multiple_ends() {
+        let maze = parse_input("##########\n#S.E....E#\n##########").unwrap();
+        assert_eq!(maze.ends.len(), 2);
+        assert_eq!(
+            best_path(&maze),
+            Some((2, vec![maze.start, Coordinate::new(1, 2), maze.ends[0]]))
+        );
+        assert_eq!(part_2(&parse_input(&maze.to_string()).unwrap()), 3);
+    }
+
+    #[test]
+    fn test_start_direction() {
+        // Facing north, the path around to the right takes four turns, while
+        // the winding path upwards takes six.
+        let maze = parse_input(INPUT_3).unwrap();
+        assert_eq!(
+            best_path_with(&maze, &Costs::default(), Cardinal::North)
+                .unwrap()
+                .0,
+            22 + 4 * 1000
+        );
+    }
+
+    #[test]
+    fn test_solve_both() {
+        let full = parse_input(&read_file_to_string("data/day16.txt")).unwrap();
+        for (maze, expected) in [
+            (parse_input(INPUT_1).unwrap(), (7036, 45)),
+            (parse_input(INPUT_2).unwrap(), (11048, 64)),
+            (full, (106512, 563)),
+        ] {
+            assert_eq!((part_1(&maze), part_2(&maze)), expected);
+            assert_eq!(solve_both(&maze), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_turn_around_at_start() {
+        // The only way to the end is behind the reindeer: two turns, then two
+        // steps.
+        let maze = parse_input("#####\n#S.E#\n#####").unwrap();
+        assert_eq!(
+            best_path_with(&maze, &Costs::default(), Cardinal::West),
+            Some((2002, vec![maze.start, Coordinate::new(1, 2), maze.ends[0]]))
+        );
+        let maze = parse_input("#####\n#E.S#\n#####").unwrap();
+        assert_eq!(solve_both(&maze), Some((2002, 3)));
+        assert_eq!(solve_astar(&maze), Some(2002));
+        assert_eq!(part_2_bidirectional(&maze), Some(3));
+        assert_eq!(part_1_compressed(&maze), 2002);
+        assert_eq!(part_2_compressed(&maze), 3);
+        assert_eq!(
+            crate::solve_day(16, &maze.to_string()).unwrap(),
+            ("2002".to_string(), "3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unreachable_end() {
+        let maze = parse_input("#######\n#S.#.E#\n#######").unwrap();
+        assert_eq!(best_path(&maze), None);
+        assert_eq!(best_path_tiles(&maze), None);
+        assert_eq!(solve_both(&maze), None);
+        assert_eq!(solve_astar(&maze), None);
+        assert_eq!(part_2_bidirectional(&maze), None);
+        assert_eq!(
+            crate::solve_day(16, &maze.to_string()).unwrap_err(),
+            AocError::Missing("path")
+        );
+    }
+
+    #[test]
+    fn test_compressed() {
+        let full = read_file_to_string("data/day16.txt");
+        for input in [INPUT_1, INPUT_2, INPUT_3, &full] {
+            let maze = parse_input(input).unwrap();
+            assert_eq!(part_1_compressed(&maze), part_1(&maze));
+            assert_eq!(part_2_compressed(&maze), part_2(&maze));
+        }
+
+        let maze = parse_input(&full).unwrap();
+        let tiles = maze
+            .matrix
+            .iter()
+            .flatten()
+            .filter(|vacant| **vacant)
+            .count();
+        let nodes = maze.compress().len();
+        assert!(
+            4 * nodes < tiles,
+            "{nodes} nodes in the graph, against {tiles} vacant tiles"
+        );
+    }
 }
@@ -14,4 +14,139 @@ pub mod day13;
 pub mod day14;
 pub mod day15;
 pub mod day16;
+#[cfg(test)]
+mod testing;
 pub mod util;
+
+use util::AocError;
+
+/// Solve both parts of a day from its puzzle input, without touching the
+/// filesystem.
+///
+/// # Panics
+/// If the input of a day whose parser does not report errors is malformed.
+pub fn solve_day(day: u8, input: &str) -> Result<(String, String), AocError> {
+    let answers = match day {
+        1 => {
+            let mut data = day01::parse_input::<isize>(input);
+            (day01::part_1(&mut data), day01::part_2(&mut data)).to_strings()
+        }
+        2 => {
+            let data = day02::parse_input::<isize>(input);
+            (day02::part_1(&data), day02::part_2(&data)).to_strings()
+        }
+        3 => {
+            let data = day03::parse_input(input);
+            (day03::part_1(&data), day03::part_2(&data)).to_strings()
+        }
+        4 => {
+            let data = day04::parse_input(input);
+            (day04::part_1(&data), day04::part_2(&data)).to_strings()
+        }
+        5 => {
+            let (rules, mut pages) = day05::parse_input(input);
+            let part_1 = day05::part_1(&rules, &pages);
+            (part_1, day05::part_2(&rules, &mut pages)).to_strings()
+        }
+        6 => {
+            let (matrix, guard) = day06::parse_input(input)?;
+            let part_1 = day06::part_1(&matrix, &mut { guard });
+            (part_1, day06::part_2(&matrix, &guard)).to_strings()
+        }
+        7 => {
+            let calcs = day07::parse_input(input)?;
+            (day07::part_1(&calcs), day07::part_2(&calcs)).to_strings()
+        }
+        8 => {
+            let matrix = day08::parse_input(input)?;
+            (day08::part_1(&matrix), day08::part_2(&matrix)).to_strings()
+        }
+        9 => {
+            let part_1 = day09::part_1(&mut day09::parse_input(input)?);
+            (part_1, day09::part_2(&mut day09::parse_input(input)?)).to_strings()
+        }
+        10 => {
+            let matrix = day10::parse_input(input)?;
+            (day10::part_1(&matrix), day10::part_2(&matrix)).to_strings()
+        }
+        11 => (
+            day11::part_1(&mut day11::parse_input(input)),
+            day11::part_2(&mut day11::parse_input(input)),
+        )
+            .to_strings(),
+        12 => {
+            let matrix = day12::parse_input(input);
+            (day12::part_1(&matrix), day12::part_2(&matrix)).to_strings()
+        }
+        13 => {
+            let machines = day13::parse_input(input).map_err(|e| AocError::MalformedLine {
+                line: input[..input.len() - e.input.len()].matches('\n').count() + 1,
+            })?;
+            (day13::part_1(&machines), day13::part_2(&machines)).to_strings()
+        }
+        14 => {
+            let robots = day14::parse_input(input)?;
+            (day14::part_1(&robots), day14::part_2(&robots)).to_strings()
+        }
+        15 => {
+            let part_1 = day15::part_1(&mut day15::parse_input(input)?);
+            (
+                part_1,
+                day15::part_2(&mut day15::parse_input(input)?.into()),
+            )
+                .to_strings()
+        }
+        16 => {
+            let maze = day16::parse_input(input)?;
+            day16::solve_both(&maze)
+                .ok_or(AocError::Missing("path"))?
+                .to_strings()
+        }
+        _ => return Err(AocError::UnknownDay(day)),
+    };
+    Ok(answers)
+}
+
+trait ToStrings {
+    fn to_strings(self) -> (String, String);
+}
+
+impl<A: ToString, B: ToString> ToStrings for (A, B) {
+    fn to_strings(self) -> (String, String) {
+        (self.0.to_string(), self.1.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::solve_day;
+    use crate::util::{read_file_to_string, AocError};
+
+    #[test]
+    fn test_solve_day() {
+        assert_eq!(
+            solve_day(1, &read_file_to_string("data/day01.txt")),
+            Ok(("1320851".to_string(), "26859182".to_string()))
+        );
+        assert_eq!(
+            solve_day(7, "190: 10 19\n3267: 81 40 27\n292: 11 6 16 20"),
+            Ok(("3749".to_string(), "3749".to_string()))
+        );
+        assert_eq!(
+            solve_day(16, &read_file_to_string("data/day16.txt")),
+            Ok(("106512".to_string(), "563".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_solve_day_errors() {
+        assert_eq!(solve_day(26, ""), Err(AocError::UnknownDay(26)));
+        assert_eq!(
+            solve_day(
+                13,
+                "Button A: X+94, Y+34\nButton B: X+22, Y+67\nPrize: X=8400"
+            ),
+            Err(AocError::MalformedLine { line: 3 })
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Helpers shared by the test modules of all days.
+
+use std::fmt::Debug;
+use std::path::Path;
+
+use crate::util::read_file_to_string;
+
+/// Read a puzzle input, or `None` if it is missing. Puzzle inputs are personal
+/// and may not be checked out, so tests on them are skipped rather than failed.
+pub fn read_data<P: AsRef<Path>>(path: P) -> Option<String> {
+    if path.as_ref().exists() {
+        Some(read_file_to_string(path))
+    } else {
+        eprintln!("skipping: {} is missing", path.as_ref().display());
+        None
+    }
+}
+
+/// Assert that solving an input gives the expected answer.
+pub fn check<T: Debug + PartialEq>(solve: impl Fn(&str) -> T, input: &str, expected: T) {
+    assert_eq!(solve(input), expected, "input:\n{input}");
+}
+
+/// A xorshift generator, so randomized tests always check the same inputs
+/// without depending on a random crate.
+pub struct XorShift(u64);
+
+impl XorShift {
+    /// # Panics
+    /// If the seed is zero, which would only ever generate zeros.
+    pub fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "the seed should not be zero");
+        XorShift(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Generate the standard tests of a day: `test_part_1_small`,
+/// `test_part_2_small` on one or more examples and, optionally,
+/// `test_part_1_full` and `test_part_2_full` on a puzzle input, which are
+/// skipped when the file is missing.
+///
+/// Each part is given as a closure from the input to the answer, which adapts
+/// the parser and solver signatures of the day. The closures see all items of
+/// the day module.
+///
+/// ```ignore
+/// golden_tests! {
+///     crate::day01,
+///     part_1: |input| part_1(&mut parse_input::<isize>(input)),
+///     part_2: |input| part_2(&mut parse_input::<isize>(input)),
+///     examples: [(INPUT, 11, 31)],
+///     full: ("data/day01.txt", 1320851, 26859182),
+/// }
+/// ```
+macro_rules! golden_tests {
+    (
+        crate::$day:ident,
+        part_1: $part_1:expr,
+        part_2: $part_2:expr,
+        examples: [$(($example:expr, $small_1:expr, $small_2:expr)),+ $(,)?]
+        $(, full: ($path:expr, $full_1:expr, $full_2:expr))?
+        $(,)?
+    ) => {
+        #[test]
+        fn test_part_1_small() {
+            #[allow(unused_imports)]
+            use $crate::$day::*;
+            $($crate::testing::check($part_1, $example, $small_1);)+
+        }
+
+        #[test]
+        fn test_part_2_small() {
+            #[allow(unused_imports)]
+            use $crate::$day::*;
+            $($crate::testing::check($part_2, $example, $small_2);)+
+        }
+
+        $(
+            #[test]
+            fn test_part_1_full() {
+                #[allow(unused_imports)]
+                use $crate::$day::*;
+                if let Some(input) = $crate::testing::read_data($path) {
+                    $crate::testing::check($part_1, &input, $full_1);
+                }
+            }
+
+            #[test]
+            fn test_part_2_full() {
+                #[allow(unused_imports)]
+                use $crate::$day::*;
+                if let Some(input) = $crate::testing::read_data($path) {
+                    $crate::testing::check($part_2, &input, $full_2);
+                }
+            }
+        )?
+    };
+}
+
+pub(crate) use golden_tests;
@@ -1,13 +1,97 @@
-use nom::{character::complete::one_of, combinator::recognize, multi::many1, IResult, Parser};
-use std::collections::HashMap;
+use nom::{
+    character::complete::{line_ending, one_of},
+    combinator::{map, map_opt, recognize},
+    multi::{many1, separated_list1},
+    IResult, Parser,
+};
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Display;
+#[cfg(any(test, feature = "fs"))]
 use std::fs::{read_to_string, File};
+use std::hash::{Hash, Hasher};
 use std::io;
+#[cfg(any(test, feature = "fs"))]
 use std::io::BufRead;
 use std::ops::Range;
 use std::ops::{Add, Deref, DerefMut, Mul, Sub};
+#[cfg(any(test, feature = "fs"))]
 use std::path::Path;
 
+/// Errors raised while parsing a puzzle input. Lines and columns are counted
+/// from 1, as in a text editor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AocError {
+    /// A character that has no meaning in the puzzle input.
+    InvalidCharacter { line: usize, col: usize, char: char },
+    /// Reading the puzzle input failed.
+    Io(io::ErrorKind),
+    /// A byte that has no meaning in the puzzle input, at a zero-based offset.
+    InvalidByte { offset: usize, byte: u8 },
+    /// A line that does not follow the expected format.
+    MalformedLine { line: usize },
+    /// A row of a grid whose length differs from that of the first row.
+    RaggedRow {
+        line: usize,
+        len: usize,
+        expected: usize,
+    },
+    /// A required element is not present in the input.
+    Missing(&'static str),
+    /// An element that should be unique is present more than once.
+    Duplicate {
+        what: &'static str,
+        line: usize,
+        col: usize,
+    },
+    /// Input left over after everything the puzzle needs.
+    TrailingInput { line: usize },
+    /// A cell on the edge of a map that should be enclosed, e.g. by walls.
+    OpenBorder { line: usize, col: usize },
+    /// An element placed on a cell that cannot hold it, e.g. a wall.
+    Blocked {
+        what: &'static str,
+        line: usize,
+        col: usize,
+    },
+    /// A day without a solver.
+    UnknownDay(u8),
+}
+
+impl Display for AocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AocError::InvalidCharacter { line, col, char } => {
+                write!(f, "invalid character {char:?} at line {line}, column {col}")
+            }
+            AocError::Io(kind) => write!(f, "failed to read input: {kind}"),
+            AocError::InvalidByte { offset, byte } => {
+                write!(f, "invalid byte {byte:#04x} at offset {offset}")
+            }
+            AocError::MalformedLine { line } => write!(f, "malformed line {line}"),
+            AocError::RaggedRow {
+                line,
+                len,
+                expected,
+            } => write!(f, "line {line} has length {len} instead of {expected}"),
+            AocError::Missing(what) => write!(f, "no {what} found"),
+            AocError::Duplicate { what, line, col } => {
+                write!(f, "duplicate {what} at line {line}, column {col}")
+            }
+            AocError::TrailingInput { line } => write!(f, "unexpected input at line {line}"),
+            AocError::OpenBorder { line, col } => {
+                write!(f, "open border at line {line}, column {col}")
+            }
+            AocError::Blocked { what, line, col } => {
+                write!(f, "{what} at line {line}, column {col} is blocked")
+            }
+            AocError::UnknownDay(day) => write!(f, "no solver for day {day}"),
+        }
+    }
+}
+
+impl std::error::Error for AocError {}
+
+#[cfg(any(test, feature = "fs"))]
 pub fn read_file_to_string<P>(filename: P) -> String
 where
     P: AsRef<Path>,
@@ -17,6 +101,7 @@ where
 
 // The output is wrapped in a Result to allow matching on errors.
 // Returns an Iterator to the Reader of the lines of the file.
+#[cfg(any(test, feature = "fs"))]
 pub fn read_file_to_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where
     P: AsRef<Path>,
@@ -30,6 +115,32 @@ pub fn count_digits(int: u64) -> u32 {
     int.checked_ilog10().unwrap_or(0) + 1
 }
 
+/// Count the number of digits in a u64, written in a base.
+///
+/// # Panics
+/// If the base is smaller than 2.
+pub fn count_digits_in_base(int: u64, base: u64) -> u32 {
+    assert!(base >= 2, "base {base} should be at least 2");
+    int.checked_ilog(base).unwrap_or(0) + 1
+}
+
+/// The greatest common divisor of two numbers.
+pub fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// The least common multiple of two numbers.
+pub fn lcm(a: usize, b: usize) -> usize {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    a / gcd(a, b) * b
+}
+
 /// Add a number of counts to a hashmap that tracks the number of occurrences of
 /// `T`s. If the `T` is not yet present, insert the value.
 pub fn hashmap_add_or_default<T>(hashmap: &mut HashMap<T, usize>, key: T, value: usize)
@@ -45,6 +156,70 @@ where
         .or_insert(value);
 }
 
+/// A cache around a recursive function, mapping its arguments to its result.
+///
+/// The function that computes a missing value receives the memo itself, so it
+/// can recurse back into it. The memo is not borrowed while the function runs:
+/// the value is only inserted once the function returns. A function that
+/// recurses back into its own key therefore never finishes, and values it
+/// inserts for other keys are kept.
+#[derive(Debug)]
+pub struct Memo<K, V> {
+    cache: HashMap<K, V>,
+    hits: usize,
+}
+
+impl<K, V> Default for Memo<K, V> {
+    fn default() -> Self {
+        Memo {
+            cache: HashMap::new(),
+            hits: 0,
+        }
+    }
+}
+
+impl<K, V> Memo<K, V>
+where
+    K: std::cmp::Eq + std::hash::Hash,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cached value of a key, or the value computed by `f`, which is then
+    /// cached.
+    pub fn get_or_insert_with(&mut self, key: K, f: impl FnOnce(&mut Self) -> V) -> V {
+        if let Some(value) = self.cache.get(&key) {
+            self.hits += 1;
+            return value.clone();
+        }
+        let value = f(self);
+        self.cache.insert(key, value.clone());
+        value
+    }
+
+    /// The number of cached values.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// The number of times a value was taken from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Forget all cached values and hits.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+        self.hits = 0;
+    }
+}
+
 /// A nom parser to identify decimal numbers.
 pub fn parse_decimal<T>(input: &str) -> IResult<&str, T>
 where
@@ -75,6 +250,22 @@ impl Coordinate {
         self.r >= c1.r && self.c >= c1.c && self.r < c2.r && self.c < c2.c
     }
 
+    /// Add another coordinate, `None` on overflow.
+    pub fn checked_add(&self, rhs: Self) -> Option<Coordinate> {
+        Some(Coordinate::new(
+            self.r.checked_add(rhs.r)?,
+            self.c.checked_add(rhs.c)?,
+        ))
+    }
+
+    /// Multiply both components by a factor, `None` on overflow.
+    pub fn checked_mul(&self, rhs: isize) -> Option<Coordinate> {
+        Some(Coordinate::new(
+            self.r.checked_mul(rhs)?,
+            self.c.checked_mul(rhs)?,
+        ))
+    }
+
     pub fn north(&self) -> Coordinate {
         Coordinate::new(self.r - 1, self.c)
     }
@@ -123,6 +314,30 @@ impl Coordinate {
         ]
     }
 
+    /// Mirror the coordinate across a row. To also allow mirroring between two
+    /// rows, the axis is the sum of a row and its mirror image, i.e. twice the
+    /// row of the mirror line: an axis of 4 mirrors across row 2, one of 5
+    /// between rows 2 and 3.
+    pub fn reflect_r(&self, axis_r: isize) -> Self {
+        Coordinate::new(axis_r - self.r, self.c)
+    }
+
+    /// Mirror the coordinate across a column, with the axis as in `reflect_r`.
+    pub fn reflect_c(&self, axis_c: isize) -> Self {
+        Coordinate::new(self.r, axis_c - self.c)
+    }
+
+    /// Mirror the coordinate through a point, i.e. rotate it by 180 degrees
+    /// around it. Like the axes of `reflect_r`, the center is twice the point.
+    pub fn reflect_point(&self, center: Coordinate) -> Self {
+        self.reflect_r(center.r).reflect_c(center.c)
+    }
+
+    /// Multiply both the row and the column by a factor.
+    pub fn scaled(&self, factor: isize) -> Self {
+        Coordinate::new(self.r * factor, self.c * factor)
+    }
+
     pub fn neighbors(&self) -> [Coordinate; 8] {
         [
             self.north(),
@@ -180,6 +395,43 @@ where
     }
 }
 
+/// An axis-aligned rectangle of coordinates, including both corners.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub min: Coordinate,
+    pub max: Coordinate,
+}
+
+impl Rect {
+    pub fn new(min: Coordinate, max: Coordinate) -> Self {
+        Rect { min, max }
+    }
+
+    /// Grow the rectangle to also contain a coordinate.
+    pub fn extend(&mut self, coord: Coordinate) {
+        self.min = Coordinate::new(self.min.r.min(coord.r), self.min.c.min(coord.c));
+        self.max = Coordinate::new(self.max.r.max(coord.r), self.max.c.max(coord.c));
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.r - self.min.r + 1) as usize
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.c - self.min.c + 1) as usize
+    }
+
+    pub fn area(&self) -> usize {
+        self.height() * self.width()
+    }
+}
+
+impl From<Coordinate> for Rect {
+    fn from(value: Coordinate) -> Self {
+        Rect::new(value, value)
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Copy, Eq, PartialOrd, Ord, Hash)]
 pub enum Cardinal {
     North,
@@ -189,6 +441,15 @@ pub enum Cardinal {
 }
 
 impl Cardinal {
+    pub fn clockwise(self) -> Self {
+        match self {
+            Cardinal::North => Cardinal::East,
+            Cardinal::East => Cardinal::South,
+            Cardinal::South => Cardinal::West,
+            Cardinal::West => Cardinal::North,
+        }
+    }
+
     pub fn opposite(self) -> Self {
         match self {
             Cardinal::North => Cardinal::South,
@@ -197,6 +458,30 @@ impl Cardinal {
             Cardinal::West => Cardinal::East,
         }
     }
+
+    /// The direction of an arrow, i.e. one of `^>v<`.
+    pub fn from_arrow(arrow: char) -> Option<Self> {
+        match arrow {
+            '^' => Some(Cardinal::North),
+            '>' => Some(Cardinal::East),
+            'v' => Some(Cardinal::South),
+            '<' => Some(Cardinal::West),
+            _ => None,
+        }
+    }
+}
+
+/// A nom parser for a sequence of arrows `^>v<`, which may be wrapped over
+/// several lines.
+pub fn parse_cardinals(input: &str) -> IResult<&str, Vec<Cardinal>> {
+    map(
+        separated_list1(
+            line_ending,
+            many1(map_opt(one_of("^>v<"), Cardinal::from_arrow)),
+        ),
+        |lines| lines.into_iter().flatten().collect(),
+    )
+    .parse(input)
 }
 
 const COORDINATE_NORTH: Coordinate = Coordinate { r: -1, c: 0 };
@@ -324,6 +609,39 @@ impl<T> Matrix<T> {
         (0..self.shape()[1]).map(|index| self.col(index).unwrap())
     }
 
+    /// Fold every row separately, starting each from `init`.
+    pub fn fold_rows<B: Clone>(&self, init: B, mut f: impl FnMut(B, &T) -> B) -> Vec<B> {
+        self.row_iter()
+            .map(|row| row.fold(init.clone(), &mut f))
+            .collect()
+    }
+
+    /// Fold every column separately, starting each from `init`.
+    pub fn fold_cols<B: Clone>(&self, init: B, mut f: impl FnMut(B, &T) -> B) -> Vec<B> {
+        self.col_iter()
+            .map(|col| col.fold(init.clone(), &mut f))
+            .collect()
+    }
+
+    /// Transpose a square matrix without allocating, see `transpose`.
+    ///
+    /// # Panics
+    /// If the matrix is not square.
+    pub fn transpose_in_place(&mut self) {
+        let [rows, cols] = self.shape();
+        assert_eq!(
+            rows, cols,
+            "cannot transpose a {rows}x{cols} matrix in place"
+        );
+        for r in 0..rows {
+            // Every row below `r` holds the elements to swap with row `r`.
+            let (top, bottom) = self.split_at_mut(r + 1);
+            for (c, row) in (r + 1..cols).zip(bottom.iter_mut()) {
+                std::mem::swap(&mut top[r][c], &mut row[r]);
+            }
+        }
+    }
+
     /// Get the diagonal (going top-left to bottom-right) at the index.
     /// Indices are counted clockwise along the outside of the matrix from the
     /// bottom-left corner to the top-right corner e.g., diagonal 2 and 3 are
@@ -441,6 +759,86 @@ impl<T> Matrix<T> {
     }
 }
 
+impl<T: PartialEq> Matrix<T> {
+    /// Whether every element equals its mirror image, in the given direction.
+    fn is_symmetric(&self, reflect: impl Fn(&Coordinate) -> Coordinate) -> bool {
+        self.iter().enumerate().all(|(r, row)| {
+            row.iter().enumerate().all(|(c, element)| {
+                let mirror = reflect(&Coordinate::new(r as isize, c as isize));
+                self[mirror.r as usize][mirror.c as usize] == *element
+            })
+        })
+    }
+
+    /// Whether the matrix equals itself flipped horizontally, i.e. every row
+    /// reads the same from right to left.
+    pub fn is_symmetric_horizontal(&self) -> bool {
+        let n_cols = self.first().map_or(0, |row| row.len()) as isize;
+        self.is_symmetric(|coord| coord.reflect_c(n_cols - 1))
+    }
+
+    /// Whether the matrix equals itself flipped vertically, i.e. every column
+    /// reads the same from bottom to top.
+    pub fn is_symmetric_vertical(&self) -> bool {
+        let n_rows = self.len() as isize;
+        self.is_symmetric(|coord| coord.reflect_r(n_rows - 1))
+    }
+}
+
+impl<T: Clone> Matrix<T> {
+    /// A new matrix of shape `[cols, rows]`, where `output[c][r] == self[r][c]`.
+    pub fn transpose(&self) -> Matrix<T> {
+        Matrix::new(self.col_iter().map(|col| col.cloned().collect()).collect())
+    }
+
+    /// A new matrix with the order of the columns reversed, i.e. mirrored
+    /// left to right, see `is_symmetric_horizontal`.
+    pub fn flip_horizontal(&self) -> Matrix<T> {
+        Matrix::new(
+            self.iter()
+                .map(|row| row.iter().rev().cloned().collect())
+                .collect(),
+        )
+    }
+
+    /// A new matrix with the order of the rows reversed, i.e. mirrored top to
+    /// bottom, see `is_symmetric_vertical`.
+    pub fn flip_vertical(&self) -> Matrix<T> {
+        Matrix::new(self.iter().rev().cloned().collect())
+    }
+
+    /// A new matrix of shape `[cols, rows]`, turned a quarter clockwise: the
+    /// first column from the bottom up becomes the first row.
+    pub fn rotate_cw(&self) -> Matrix<T> {
+        Matrix::new(
+            (0..self.shape()[1])
+                .map(|index| self.col(index).unwrap().rev().cloned().collect())
+                .collect(),
+        )
+    }
+
+    /// A new matrix of shape `[cols, rows]`, turned a quarter counterclockwise:
+    /// the last column from the top down becomes the first row.
+    pub fn rotate_ccw(&self) -> Matrix<T> {
+        Matrix::new(
+            (0..self.shape()[1])
+                .rev()
+                .map(|index| self.col(index).unwrap().cloned().collect())
+                .collect(),
+        )
+    }
+
+    /// A new matrix turned half a turn.
+    pub fn rotate_180(&self) -> Matrix<T> {
+        Matrix::new(
+            self.iter()
+                .rev()
+                .map(|row| row.iter().rev().cloned().collect())
+                .collect(),
+        )
+    }
+}
+
 impl<T: Copy> Matrix<T> {
     pub fn slice(&self, row: Range<usize>, col: Range<usize>) -> Matrix<T> {
         let mut row_vec = Vec::with_capacity(row.end - row.start);
@@ -461,6 +859,83 @@ impl<T: Copy> Matrix<T> {
     }
 }
 
+impl<T: Copy + std::iter::Sum> Matrix<T> {
+    /// The sum of every row.
+    pub fn row_sums(&self) -> Vec<T> {
+        self.row_iter().map(|row| row.copied().sum()).collect()
+    }
+
+    /// The sum of every column.
+    pub fn col_sums(&self) -> Vec<T> {
+        self.col_iter().map(|col| col.copied().sum()).collect()
+    }
+}
+
+/// Hash the shape, then the elements in row-major order.
+impl<T: Hash> Hash for Matrix<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let n_cols = self.first().map_or(0, |row| row.len());
+        state.write_usize(self.len());
+        state.write_usize(n_cols);
+        for element in self.iter().flatten() {
+            element.hash(state);
+        }
+    }
+}
+
+impl<T: Hash> Matrix<T> {
+    /// A cheap hash of the matrix that is the same on every run, for when
+    /// comparing the full matrices is not needed.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A fast, non-cryptographic hasher in the style of FxHash, which mixes in a
+/// word at a time.
+#[derive(Default)]
+struct FxHasher {
+    hash: u64,
+}
+
+impl FxHasher {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(Self::SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in chunks.by_ref() {
+            self.add_to_hash(u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+        for byte in chunks.remainder() {
+            self.add_to_hash(*byte as u64);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add_to_hash(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add_to_hash(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
 impl<T: Display + Display> Display for Matrix<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for row in self.row_range() {
@@ -473,12 +948,111 @@ impl<T: Display + Display> Display for Matrix<T> {
     }
 }
 
+/// The neighbors of a cell that it is connected to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only the 4 cardinal directions.
+    #[default]
+    Four,
+    /// The 4 cardinal and the 4 diagonal directions.
+    Eight,
+}
+
+impl Connectivity {
+    pub fn neighbors(&self, coord: &Coordinate) -> Vec<Coordinate> {
+        match self {
+            Connectivity::Four => coord.cardinals().to_vec(),
+            Connectivity::Eight => coord.neighbors().to_vec(),
+        }
+    }
+}
+
+/// Label every connected region of identical values. Labels are assigned in
+/// reading order of the first cell of each region, starting from 0.
+pub fn connected_components<T: PartialEq>(
+    matrix: &Matrix<T>,
+    connectivity: Connectivity,
+) -> Matrix<usize> {
+    let mut output = Matrix::new_like(matrix, 0usize);
+    let mut counter = 0usize;
+    let mut visited = Matrix::new_like(matrix, false);
+    let bounds = Coordinate::new(matrix.shape()[0] as isize, matrix.shape()[1] as isize);
+    for row in matrix.row_range() {
+        for col in matrix.col_range() {
+            if visited[row][col] {
+                continue;
+            }
+            visited[row][col] = true;
+            let mut queue = vec![Coordinate::new(row as isize, col as isize)];
+            while let Some(coord) = queue.pop() {
+                let [r, c] = [coord.r as usize, coord.c as usize];
+                output[r][c] = counter;
+                for neighbor in connectivity.neighbors(&coord) {
+                    if !neighbor.is_in(&Coordinate::new(0, 0), &bounds) {
+                        continue;
+                    }
+                    let [neighbor_r, neighbor_c] = [neighbor.r as usize, neighbor.c as usize];
+                    if !visited[neighbor_r][neighbor_c]
+                        && matrix[neighbor_r][neighbor_c] == matrix[r][c]
+                    {
+                        visited[neighbor_r][neighbor_c] = true;
+                        queue.push(neighbor);
+                    }
+                }
+            }
+            counter += 1;
+        }
+    }
+    output
+}
+
+/// The number of 4-connected steps from every cell of a grid to the nearest
+/// source, or `None` for cells that are impassable or cannot be reached.
+/// Sources that are impassable or outside the grid are skipped.
+pub fn distance_map(
+    shape: [usize; 2],
+    sources: impl IntoIterator<Item = Coordinate>,
+    passable: impl Fn(Coordinate) -> bool,
+) -> Matrix<Option<usize>> {
+    let mut distances = Matrix::new(vec![vec![None; shape[1]]; shape[0]]);
+    let bounds = Coordinate::new(shape[0] as isize, shape[1] as isize);
+    let mut queue = VecDeque::new();
+    for source in sources {
+        if source.is_in(&Coordinate::new(0, 0), &bounds)
+            && passable(source)
+            && distances[source.r as usize][source.c as usize].is_none()
+        {
+            distances[source.r as usize][source.c as usize] = Some(0);
+            queue.push_back((source, 0));
+        }
+    }
+    while let Some((coord, distance)) = queue.pop_front() {
+        for neighbor in coord.cardinals() {
+            if neighbor.is_in(&Coordinate::new(0, 0), &bounds)
+                && distances[neighbor.r as usize][neighbor.c as usize].is_none()
+                && passable(neighbor)
+            {
+                distances[neighbor.r as usize][neighbor.c as usize] = Some(distance + 1);
+                queue.push_back((neighbor, distance + 1));
+            }
+        }
+    }
+    distances
+}
+
 #[cfg(test)]
 mod test {
     use std::vec;
 
-    use super::{parse_decimal, Matrix};
+    use super::{
+        connected_components, distance_map, gcd, lcm, parse_cardinals, parse_decimal, Cardinal,
+        Connectivity, Coordinate, Matrix, Memo,
+    };
     use nom::{bytes::complete::tag, sequence::separated_pair};
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
 
     fn get_matrix() -> Matrix<i32> {
         Matrix::new(vec![
@@ -488,6 +1062,31 @@ mod test {
         ])
     }
 
+    #[test]
+    fn test_gcd_lcm() {
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(7, 0), 7);
+        assert_eq!(lcm(4, 6), 12);
+        assert_eq!(lcm(101, 103), 10403);
+        assert_eq!(lcm(0, 5), 0);
+    }
+
+    #[test]
+    fn test_parse_cardinals() {
+        use Cardinal::*;
+        assert_eq!(
+            parse_cardinals("<^\nv>\n\n#"),
+            Ok(("\n\n#", vec![West, North, South, East]))
+        );
+        assert_eq!(
+            parse_cardinals("<<\r\n>x"),
+            Ok(("x", vec![West, West, East]))
+        );
+        assert_eq!(parse_cardinals("^\nx"), Ok(("\nx", vec![North])));
+        assert!(parse_cardinals("x").is_err());
+        assert!(parse_cardinals("\n<").is_err());
+    }
+
     #[test]
     fn test_parse_decimal() {
         assert_eq!(parse_decimal("123"), Ok(("", 123)));
@@ -550,6 +1149,107 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_fold_rows_cols() {
+        let matrix = get_matrix();
+        assert_eq!(matrix.row_sums(), [6, 22, 38]);
+        assert_eq!(matrix.col_sums(), [12, 15, 18, 21]);
+        assert_eq!(matrix.fold_rows(0, |acc, el| acc.max(*el)), [3, 7, 11]);
+        assert_eq!(
+            matrix.fold_cols(String::new(), |acc, el| acc + &el.to_string()),
+            ["048", "159", "2610", "3711"]
+        );
+        assert_eq!(matrix.fold_rows(0, |acc, el| acc + *el), matrix.row_sums());
+    }
+
+    #[test]
+    fn test_transpose() {
+        let matrix = get_matrix();
+        let transposed = matrix.transpose();
+        assert_eq!(transposed.shape(), [4, 3]);
+        assert_eq!(transposed[3][1], matrix[1][3]);
+        assert_eq!(transposed.transpose(), matrix);
+        for (row, col) in transposed.row_iter().zip(matrix.col_iter()) {
+            assert!(row.eq(col));
+        }
+        let row = Matrix::new(vec![vec![1, 2, 3]]);
+        assert_eq!(
+            row.transpose(),
+            Matrix::new(vec![vec![1], vec![2], vec![3]])
+        );
+        assert_eq!(row.transpose().transpose(), row);
+
+        let mut square = Matrix::new(vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+        let transposed = square.transpose();
+        square.transpose_in_place();
+        assert_eq!(square, transposed);
+        let mut single = Matrix::new(vec![vec![1]]);
+        single.transpose_in_place();
+        assert_eq!(single, Matrix::new(vec![vec![1]]));
+    }
+
+    #[test]
+    fn test_rotate_flip() {
+        let matrix = get_matrix();
+        assert_eq!(
+            matrix.rotate_cw(),
+            Matrix::new(vec![
+                vec![8, 4, 0],
+                vec![9, 5, 1],
+                vec![10, 6, 2],
+                vec![11, 7, 3],
+            ])
+        );
+        assert_eq!(
+            matrix.rotate_ccw(),
+            Matrix::new(vec![
+                vec![3, 7, 11],
+                vec![2, 6, 10],
+                vec![1, 5, 9],
+                vec![0, 4, 8],
+            ])
+        );
+        assert_eq!(
+            matrix.rotate_180(),
+            Matrix::new(vec![vec![11, 10, 9, 8], vec![7, 6, 5, 4], vec![3, 2, 1, 0],])
+        );
+        assert_eq!(
+            matrix.flip_horizontal(),
+            Matrix::new(vec![vec![3, 2, 1, 0], vec![7, 6, 5, 4], vec![11, 10, 9, 8],])
+        );
+        assert_eq!(
+            matrix.flip_vertical(),
+            Matrix::new(vec![vec![8, 9, 10, 11], vec![4, 5, 6, 7], vec![0, 1, 2, 3],])
+        );
+
+        assert_eq!(
+            matrix.rotate_cw().rotate_cw().rotate_cw().rotate_cw(),
+            matrix
+        );
+        assert_eq!(matrix.rotate_cw().rotate_cw(), matrix.rotate_180());
+        assert_eq!(matrix.rotate_cw().rotate_ccw(), matrix);
+        assert_eq!(matrix.rotate_ccw(), matrix.rotate_180().rotate_cw());
+        assert_eq!(
+            matrix.flip_horizontal().flip_vertical(),
+            matrix.rotate_180()
+        );
+        assert_eq!(matrix.rotate_cw().flip_horizontal(), matrix.transpose());
+        assert_eq!(matrix.rotate_ccw().flip_vertical(), matrix.transpose());
+        assert_eq!(matrix.flip_vertical().rotate_cw(), matrix.transpose());
+
+        // Flips agree with the symmetry checks.
+        let palindromes = Matrix::new(vec![vec![1, 2, 1], vec![3, 4, 3]]);
+        assert!(palindromes.is_symmetric_horizontal());
+        assert_eq!(palindromes.flip_horizontal(), palindromes);
+        assert_ne!(palindromes.flip_vertical(), palindromes);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot transpose a 3x4 matrix in place")]
+    fn test_transpose_in_place_not_square() {
+        get_matrix().transpose_in_place();
+    }
+
     #[test]
     fn test_matrix_diagonal() {
         let matrix = get_matrix();
@@ -607,4 +1307,157 @@ mod test {
             ])
         )
     }
+
+    #[test]
+    fn test_connected_components() {
+        let matrix = Matrix::new(vec![
+            vec!['X', 'O', 'X'], //
+            vec!['O', 'X', 'O'], //
+            vec!['X', 'O', 'X'], //
+        ]);
+        assert_eq!(
+            connected_components(&matrix, Connectivity::Four),
+            Matrix::new(vec![
+                vec![0, 1, 2], //
+                vec![3, 4, 5], //
+                vec![6, 7, 8], //
+            ])
+        );
+        assert_eq!(
+            connected_components(&matrix, Connectivity::Eight),
+            Matrix::new(vec![
+                vec![0, 1, 0], //
+                vec![1, 0, 1], //
+                vec![0, 1, 0], //
+            ])
+        );
+    }
+
+    #[test]
+    fn test_distance_map() {
+        // The first example of day 16, with both the start and the end as
+        // sources.
+        let maze = Matrix::new(
+            "###############
+#.......#....E#
+#.#.###.#.###.#
+#.....#.#...#.#
+#.###.#####.#.#
+#.#.#.......#.#
+#.#.#####.###.#
+#...........#.#
+###.#.#####.#.#
+#...#.....#.#.#
+#.#.#.###.#.#.#
+#.....#...#.#.#
+#.###.#.#.#.#.#
+#S..#.....#...#
+###############"
+                .lines()
+                .map(|line| line.chars().collect())
+                .collect(),
+        );
+        let sources = [Coordinate::new(13, 1), Coordinate::new(1, 13)];
+        let distances = distance_map(maze.shape(), sources, |coord| {
+            maze[coord.r as usize][coord.c as usize] != '#'
+        });
+        for ([r, c], distance) in [
+            ([13, 1], Some(0)),
+            ([1, 13], Some(0)),
+            ([13, 3], Some(2)),
+            ([9, 3], Some(6)),
+            ([3, 13], Some(2)),
+            ([13, 13], Some(12)),
+            ([0, 0], None),
+            ([12, 2], None),
+        ] {
+            assert_eq!(distances[r][c], distance, "({r}, {c})");
+        }
+
+        // Impassable and out-of-bounds sources are skipped.
+        let distances = distance_map(
+            [2, 3],
+            [Coordinate::new(0, 0), Coordinate::new(5, 5)],
+            |_| false,
+        );
+        assert_eq!(*distances, vec![vec![None; 3]; 2]);
+    }
+
+    #[test]
+    fn test_reflect() {
+        let coord = Coordinate::new(1, 3);
+        // Across row 2 and column 2.
+        assert_eq!(coord.reflect_r(4), Coordinate::new(3, 3));
+        assert_eq!(coord.reflect_c(4), Coordinate::new(1, 1));
+        // Between rows 2 and 3, and columns 3 and 4.
+        assert_eq!(coord.reflect_r(5), Coordinate::new(4, 3));
+        assert_eq!(coord.reflect_c(7), Coordinate::new(1, 4));
+        // A coordinate on the axis is its own mirror image.
+        assert_eq!(coord.reflect_c(6), coord);
+        assert_eq!(coord.reflect_r(5).reflect_r(5), coord);
+        assert_eq!(
+            coord.reflect_point(Coordinate::new(4, 4)),
+            Coordinate::new(3, 1)
+        );
+        assert_eq!(coord.reflect_point(coord.scaled(2)), coord);
+        assert_eq!(coord.scaled(-2), Coordinate::new(-2, -6));
+    }
+
+    #[test]
+    fn test_is_symmetric() {
+        let matrix = Matrix::new(vec![vec![1, 2, 1], vec![3, 4, 3]]);
+        assert!(matrix.is_symmetric_horizontal());
+        assert!(!matrix.is_symmetric_vertical());
+        let matrix = Matrix::new(vec![vec![1, 2], vec![3, 4], vec![1, 2]]);
+        assert!(!matrix.is_symmetric_horizontal());
+        assert!(matrix.is_symmetric_vertical());
+        let matrix = Matrix::new(vec![vec![1, 1], vec![1, 1]]);
+        assert!(matrix.is_symmetric_horizontal() && matrix.is_symmetric_vertical());
+        assert!(!get_matrix().is_symmetric_horizontal());
+        assert!(!get_matrix().is_symmetric_vertical());
+    }
+
+    fn fibonacci(n: u64, memo: &mut Memo<u64, u64>) -> u64 {
+        if n < 2 {
+            return n;
+        }
+        memo.get_or_insert_with(n, |memo| fibonacci(n - 1, memo) + fibonacci(n - 2, memo))
+    }
+
+    #[test]
+    fn test_memo() {
+        let mut memo = Memo::new();
+        assert!(memo.is_empty());
+        assert_eq!(fibonacci(90, &mut memo), 2880067194370816120);
+        // Every value from 2 up to 90 is computed once, from the value before
+        // it, and taken from the cache once, for the value after it.
+        assert_eq!(memo.len(), 89);
+        assert_eq!(memo.hits(), 87);
+        assert_eq!(fibonacci(90, &mut memo), 2880067194370816120);
+        assert_eq!(memo.hits(), 88);
+        memo.clear();
+        assert_eq!((memo.len(), memo.hits()), (0, 0));
+    }
+
+    fn default_hash<T: Hash>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_matrix_hash() {
+        assert_eq!(default_hash(&get_matrix()), default_hash(&get_matrix()));
+        assert_eq!(get_matrix().fingerprint(), get_matrix().fingerprint());
+
+        // The same elements in another shape.
+        let wide = Matrix::new(vec![vec![1, 2, 3], vec![4, 5, 6]]);
+        let tall = Matrix::new(vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+        assert_ne!(default_hash(&wide), default_hash(&tall));
+        assert_ne!(wide.fingerprint(), tall.fingerprint());
+
+        let mut other = Matrix::new(vec![vec![1, 2, 3], vec![4, 5, 6]]);
+        other[1][2] = 7;
+        assert_ne!(wide.fingerprint(), other.fingerprint());
+    }
 }